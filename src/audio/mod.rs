@@ -0,0 +1,608 @@
+//! Audio, through the native protocol of PulseAudio, which the PulseAudio server of PipeWire
+//! speaks too. Setting `PULSE_SERVER` makes it talk to another server, e.g. one with only a null
+//! sink.
+
+pub mod pulse;
+pub mod subscription;
+pub mod ui;
+
+use std::{ffi::CString, fmt::Display, path::PathBuf, sync::Arc};
+
+use pulseaudio::protocol::{
+    ChannelVolume, Command, Prop, ProtocolError, PulseError, ServerInfo, SetDeviceMuteParams,
+    SetDeviceVolumeParams, SinkInfoList, SourceInfoList, SourceOutputInfoList, Volume,
+};
+use tokio::sync::Mutex;
+
+use pulse::Connection;
+
+#[derive(Debug)]
+pub enum AudioError {
+    /// neither `PULSE_SERVER` nor the runtime directory give the socket of the server
+    ServerNotFound,
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ConnectionError(std::io::Error),
+    ProtocolError(ProtocolError),
+    ServerError {
+        command: String,
+        error: PulseError,
+    },
+    /// the server closed the connection, e.g. because it was restarted
+    Disconnected,
+}
+
+impl From<ProtocolError> for AudioError {
+    fn from(error: ProtocolError) -> Self {
+        Self::ProtocolError(error)
+    }
+}
+
+impl Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ServerNotFound => write!(f, "Unable to find the socket of the audio server"),
+            Self::IoError { path, error } => {
+                write!(f, "Unable to connect to {} : {}", path.display(), error)
+            }
+            Self::ConnectionError(error) => {
+                write!(f, "Error communicating with the audio server : {}", error)
+            }
+            Self::ProtocolError(error) => {
+                write!(f, "Invalid message from the audio server : {}", error)
+            }
+            Self::ServerError { command, error } => {
+                writeln!(f, "The audio server refused a command.")?;
+                writeln!(f, "Sent command : {}", command)?;
+                write!(f, "Got error : {:?}", error)
+            }
+            Self::Disconnected => write!(f, "The audio server closed the connection"),
+        }
+    }
+}
+
+/// Sinks are the outputs (speakers, headphones, ...), and sources the inputs (microphones, ...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Sink,
+    Source,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub description: String,
+    /// the average over the channels
+    pub volume_percent: u32,
+    pub muted: bool,
+    /// the volume is set to the same value on each of them
+    channels: u8,
+}
+
+/// The devices of the server, and which ones are the default ones.
+#[derive(Debug, Clone, Default)]
+pub struct AudioState {
+    pub sinks: Vec<Device>,
+    pub sources: Vec<Device>,
+    pub default_sink: Option<String>,
+    pub default_source: Option<String>,
+    /// the names of the applications recording from a source, e.g. during a video call
+    pub recording: Vec<String>,
+}
+
+impl AudioState {
+    pub fn devices(&self, kind: DeviceKind) -> &[Device] {
+        match kind {
+            DeviceKind::Sink => &self.sinks,
+            DeviceKind::Source => &self.sources,
+        }
+    }
+
+    pub fn default_device(&self, kind: DeviceKind) -> Option<&Device> {
+        let default = match kind {
+            DeviceKind::Sink => self.default_sink.as_ref()?,
+            DeviceKind::Source => self.default_source.as_ref()?,
+        };
+        self.devices(kind)
+            .iter()
+            .find(|device| &device.name == default)
+    }
+
+    pub fn default_device_mut(&mut self, kind: DeviceKind) -> Option<&mut Device> {
+        let (devices, default) = match kind {
+            DeviceKind::Sink => (&mut self.sinks, self.default_sink.as_ref()?),
+            DeviceKind::Source => (&mut self.sources, self.default_source.as_ref()?),
+        };
+        devices.iter_mut().find(|device| &device.name == default)
+    }
+}
+
+/// The state of the server, with the connection on which to change it. The volume and the
+/// microphone displays share a single one, held by the main app.
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub client: Client,
+    pub state: AudioState,
+}
+
+/// Turns a volume of the protocol into a percentage, rounded like `pactl` does.
+fn percent(volume: &ChannelVolume) -> u32 {
+    let channels = volume.channels();
+    let average = channels
+        .iter()
+        .map(|volume| volume.as_u32() as u64)
+        .sum::<u64>()
+        / channels.len().max(1) as u64;
+    let norm = Volume::NORM.as_u32() as u64;
+    ((average * 100 + norm / 2) / norm) as u32
+}
+
+fn text(text: Option<&CString>) -> Option<String> {
+    text.map(|text| text.to_string_lossy().into_owned())
+}
+
+/// Reads the whole state of the server.
+pub async fn get_state(connection: &mut Connection) -> Result<AudioState, AudioError> {
+    let info: ServerInfo = connection.request(Command::GetServerInfo).await?;
+    let sinks: SinkInfoList = connection.request(Command::GetSinkInfoList).await?;
+    let sources: SourceInfoList = connection.request(Command::GetSourceInfoList).await?;
+    let source_outputs: SourceOutputInfoList =
+        connection.request(Command::GetSourceOutputInfoList).await?;
+
+    // recording what a sink plays, like audio visualizers do, isn't using the microphone
+    let monitors = sources
+        .iter()
+        .filter(|source| source.monitor_of_sink_index.is_some())
+        .map(|source| source.index)
+        .collect::<Vec<_>>();
+    let recording = source_outputs
+        .iter()
+        .filter(|output| !monitors.contains(&output.source_index))
+        .map(|output| {
+            output
+                .props
+                .get(Prop::ApplicationName)
+                .map_or("?".into(), |name| {
+                    String::from_utf8_lossy(name)
+                        .trim_end_matches('\0')
+                        .to_string()
+                })
+        })
+        .collect();
+
+    Ok(AudioState {
+        sinks: sinks
+            .iter()
+            .map(|sink| Device {
+                name: sink.name.to_string_lossy().into_owned(),
+                description: text(sink.description.as_ref())
+                    .unwrap_or_else(|| sink.name.to_string_lossy().into_owned()),
+                volume_percent: percent(&sink.cvolume),
+                muted: sink.muted,
+                channels: sink.cvolume.channels().len() as u8,
+            })
+            .collect(),
+        sources: sources
+            .iter()
+            .filter(|source| source.monitor_of_sink_index.is_none())
+            .map(|source| Device {
+                name: source.name.to_string_lossy().into_owned(),
+                description: text(source.description.as_ref())
+                    .unwrap_or_else(|| source.name.to_string_lossy().into_owned()),
+                volume_percent: percent(&source.cvolume),
+                muted: source.muted,
+                channels: source.cvolume.channels().len() as u8,
+            })
+            .collect(),
+        default_sink: text(info.default_sink_name.as_ref()),
+        default_source: text(info.default_source_name.as_ref()),
+        recording,
+    })
+}
+
+/// A connection for the commands of the widgets, shared by all of them.
+#[derive(Clone)]
+pub struct Client(Arc<Mutex<Connection>>);
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Client")
+    }
+}
+
+impl Client {
+    pub async fn connect() -> Result<Self, AudioError> {
+        Ok(Self(Arc::new(Mutex::new(Connection::connect().await?))))
+    }
+
+    async fn execute(&self, command: Command) -> Result<(), AudioError> {
+        self.0.lock().await.execute(command).await
+    }
+
+    pub async fn set_volume(
+        self,
+        kind: DeviceKind,
+        device: Device,
+        percent: u32,
+    ) -> Result<(), AudioError> {
+        let volume =
+            Volume::from_u32_clamped((Volume::NORM.as_u32() as u64 * percent as u64 / 100) as u32);
+        let mut channels = ChannelVolume::empty();
+        for _ in 0..device.channels.max(1) {
+            channels.push(volume);
+        }
+        let params = SetDeviceVolumeParams {
+            device_index: None,
+            device_name: Some(c_name(device.name)?),
+            volume: channels,
+        };
+        self.execute(match kind {
+            DeviceKind::Sink => Command::SetSinkVolume(params),
+            DeviceKind::Source => Command::SetSourceVolume(params),
+        })
+        .await
+    }
+
+    pub async fn set_mute(
+        self,
+        kind: DeviceKind,
+        name: String,
+        mute: bool,
+    ) -> Result<(), AudioError> {
+        let params = SetDeviceMuteParams {
+            device_index: None,
+            device_name: Some(c_name(name)?),
+            mute,
+        };
+        self.execute(match kind {
+            DeviceKind::Sink => Command::SetSinkMute(params),
+            DeviceKind::Source => Command::SetSourceMute(params),
+        })
+        .await
+    }
+
+    pub async fn set_default(self, kind: DeviceKind, name: String) -> Result<(), AudioError> {
+        let name = c_name(name)?;
+        self.execute(match kind {
+            DeviceKind::Sink => Command::SetDefaultSink(name),
+            DeviceKind::Source => Command::SetDefaultSource(name),
+        })
+        .await
+    }
+}
+
+/// The names come from the server, so they never contain a NUL byte.
+fn c_name(name: String) -> Result<CString, AudioError> {
+    CString::new(name).map_err(|e| ProtocolError::Invalid(e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufReader, Write},
+        os::unix::net::UnixListener,
+        path::Path,
+        process::{Child, Stdio},
+        sync::Mutex as StdMutex,
+        time::Duration,
+    };
+
+    use pulseaudio::protocol::{
+        self, AuthReply, Props, SetClientNameReply, SinkInfo, SourceInfo, SourceOutputInfo,
+        SubscriptionEvent, SubscriptionEventFacility, SubscriptionEventType,
+    };
+
+    use super::*;
+    use crate::testing::{block_on, FakeTree};
+
+    fn volume(percents: &[u32]) -> ChannelVolume {
+        let mut volume = ChannelVolume::empty();
+        for percent in percents {
+            volume.push(Volume::from_u32_clamped(
+                Volume::NORM.as_u32() * percent / 100,
+            ));
+        }
+        volume
+    }
+
+    fn sink(index: u32, name: &str, percents: &[u32]) -> SinkInfo {
+        SinkInfo {
+            name: CString::new(name).unwrap(),
+            description: Some(CString::new(format!("{} speakers", name)).unwrap()),
+            cvolume: volume(percents),
+            ..SinkInfo::new_dummy(index)
+        }
+    }
+
+    fn source(index: u32, name: &str, monitor_of_sink_index: Option<u32>) -> SourceInfo {
+        SourceInfo {
+            index,
+            name: CString::new(name).unwrap(),
+            cvolume: volume(&[100]),
+            monitor_of_sink_index,
+            ..Default::default()
+        }
+    }
+
+    fn source_output(source_index: u32, application: &str) -> SourceOutputInfo {
+        let mut props = Props::new();
+        props.set(Prop::ApplicationName, CString::new(application).unwrap());
+        SourceOutputInfo {
+            source_index,
+            props,
+            ..Default::default()
+        }
+    }
+
+    /// A server answering a single client, which records the commands changing the devices.
+    struct MockServer {
+        path: PathBuf,
+        commands: Arc<StdMutex<Vec<Command>>>,
+        _tree: FakeTree,
+    }
+
+    impl MockServer {
+        fn start() -> Self {
+            let tree = FakeTree::new();
+            let path = tree.path().join("native");
+            let listener = UnixListener::bind(&path).unwrap();
+            let commands = Arc::<StdMutex<Vec<Command>>>::default();
+            let recorded = commands.clone();
+            std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                let version = protocol::MAX_VERSION;
+                while let Ok((seq, command)) = protocol::read_command_message(&mut reader, version)
+                {
+                    let w = &mut writer;
+                    match command {
+                        Command::Auth(_) => {
+                            let reply = AuthReply {
+                                version,
+                                ..Default::default()
+                            };
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::SetClientName(_) => {
+                            let reply = SetClientNameReply { client_id: 1 };
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::GetServerInfo => {
+                            // an event sent before the reply, which the client keeps for later
+                            let event = Command::SubscribeEvent(SubscriptionEvent {
+                                event_facility: SubscriptionEventFacility::Sink,
+                                event_type: SubscriptionEventType::Changed,
+                                index: Some(0),
+                            });
+                            protocol::write_command_message(w, u32::MAX, &event, version).unwrap();
+                            let reply = ServerInfo {
+                                default_sink_name: Some(CString::new("speakers").unwrap()),
+                                default_source_name: Some(CString::new("mic").unwrap()),
+                                ..Default::default()
+                            };
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::GetSinkInfoList => {
+                            let reply: SinkInfoList =
+                                vec![sink(0, "speakers", &[40, 60]), sink(1, "hdmi", &[100])];
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::GetSourceInfoList => {
+                            let reply: SourceInfoList = vec![
+                                source(0, "speakers.monitor", Some(0)),
+                                source(1, "mic", None),
+                            ];
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::GetSourceOutputInfoList => {
+                            let reply: SourceOutputInfoList = vec![
+                                source_output(0, "visualizer"),
+                                source_output(1, "video call"),
+                            ];
+                            protocol::write_reply_message(w, seq, &reply, version)
+                        }
+                        Command::SetDefaultSink(ref name) if name.as_bytes() != b"speakers" => {
+                            protocol::write_error(w, seq, &PulseError::NoEntity)
+                        }
+                        command => {
+                            recorded.lock().unwrap().push(command);
+                            protocol::write_ack_message(w, seq)
+                        }
+                    }
+                    .unwrap();
+                    writer.flush().unwrap();
+                }
+            });
+            Self {
+                path,
+                commands,
+                _tree: tree,
+            }
+        }
+
+        async fn connect(&self) -> Connection {
+            Connection::connect_to(self.path.clone(), Vec::new())
+                .await
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn reads_the_state_of_the_server() {
+        let server = MockServer::start();
+        block_on(async {
+            let mut connection = server.connect().await;
+            let state = get_state(&mut connection).await.unwrap();
+
+            let sink = state.default_device(DeviceKind::Sink).unwrap();
+            assert_eq!(sink.description, "speakers speakers");
+            assert_eq!(sink.volume_percent, 50);
+            assert_eq!(sink.channels, 2);
+            assert_eq!(state.sinks.len(), 2);
+            // the monitor of the speakers is no microphone, and recording it is no call
+            let sources = state.sources.iter().map(|source| source.name.as_str());
+            assert_eq!(sources.collect::<Vec<_>>(), ["mic"]);
+            assert_eq!(state.recording, ["video call"]);
+
+            // the event which arrived before the reply of the server info
+            let event = connection.next_event().await.unwrap();
+            assert_eq!(event.event_facility, SubscriptionEventFacility::Sink);
+        });
+    }
+
+    #[test]
+    fn sets_the_volume_of_every_channel() {
+        let server = MockServer::start();
+        block_on(async {
+            let mut connection = server.connect().await;
+            let state = get_state(&mut connection).await.unwrap();
+            let sink = state.default_device(DeviceKind::Sink).unwrap().clone();
+            let client = Client(Arc::new(Mutex::new(connection)));
+
+            client
+                .clone()
+                .set_volume(DeviceKind::Sink, sink, 120)
+                .await
+                .unwrap();
+            client
+                .set_mute(DeviceKind::Source, "mic".into(), true)
+                .await
+                .unwrap();
+        });
+
+        let commands = server.commands.lock().unwrap();
+        let Command::SetSinkVolume(params) = &commands[0] else {
+            panic!("unexpected command {:?}", commands[0]);
+        };
+        assert_eq!(params.device_name, CString::new("speakers").ok());
+        let channels = params.volume.channels();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0], channels[1]);
+        assert_eq!(percent(&params.volume), 120);
+        let Command::SetSourceMute(params) = &commands[1] else {
+            panic!("unexpected command {:?}", commands[1]);
+        };
+        assert_eq!(params.device_name, CString::new("mic").ok());
+        assert!(params.mute);
+    }
+
+    #[test]
+    fn reports_the_commands_refused_by_the_server() {
+        let server = MockServer::start();
+        block_on(async {
+            let client = Client(Arc::new(Mutex::new(server.connect().await)));
+            let result = client
+                .set_default(DeviceKind::Sink, "unplugged".into())
+                .await;
+            assert!(matches!(
+                result,
+                Err(AudioError::ServerError {
+                    error: PulseError::NoEntity,
+                    ..
+                })
+            ));
+        });
+    }
+
+    /// A real PulseAudio server with only a null sink, stopped once dropped.
+    struct NullSinkServer {
+        daemon: Child,
+        socket: PathBuf,
+        _tree: FakeTree,
+    }
+
+    impl NullSinkServer {
+        /// Starts the server, or returns `None` when `pulseaudio` isn't installed, in which case
+        /// the test should be skipped.
+        fn start() -> Option<Self> {
+            let tree = FakeTree::new();
+            let socket = tree.path().join("native");
+            let daemon = std::process::Command::new("pulseaudio")
+                .args([
+                    "--daemonize=no",
+                    "--exit-idle-time=-1",
+                    "--disable-shm",
+                    "-n",
+                    "-L",
+                    "module-null-sink",
+                    "-L",
+                ])
+                .arg(format!(
+                    "module-native-protocol-unix auth-anonymous=1 socket={}",
+                    socket.display()
+                ))
+                .env("HOME", tree.path())
+                .env("XDG_RUNTIME_DIR", tree.path())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            let daemon = match daemon {
+                Ok(daemon) => daemon,
+                Err(e) => {
+                    eprintln!("Skipping the test, unable to start pulseaudio : {}", e);
+                    return None;
+                }
+            };
+            let server = Self {
+                daemon,
+                socket,
+                _tree: tree,
+            };
+            for _ in 0..50 {
+                if server.socket.exists() {
+                    return Some(server);
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            panic!("pulseaudio didn't create {}", server.socket.display());
+        }
+
+        fn path(&self) -> &Path {
+            &self.socket
+        }
+    }
+
+    impl Drop for NullSinkServer {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn changes_the_volume_of_a_null_sink() {
+        let Some(server) = NullSinkServer::start() else {
+            return;
+        };
+        block_on(async {
+            let connect = || Connection::connect_to(server.path().into(), Vec::new());
+            let mut events = connect().await.unwrap();
+            events.subscribe(subscription::EVENTS).await.unwrap();
+            let client = Client(Arc::new(Mutex::new(connect().await.unwrap())));
+
+            let state = get_state(&mut events).await.unwrap();
+            let sink = state.default_device(DeviceKind::Sink).unwrap().clone();
+            assert_eq!(sink.name, "null");
+            assert!(state.sources.is_empty());
+
+            client
+                .clone()
+                .set_volume(DeviceKind::Sink, sink, 30)
+                .await
+                .unwrap();
+            client
+                .set_mute(DeviceKind::Sink, "null".into(), true)
+                .await
+                .unwrap();
+            events.next_event().await.unwrap();
+
+            let state = get_state(&mut events).await.unwrap();
+            let sink = state.default_device(DeviceKind::Sink).unwrap();
+            assert_eq!(sink.volume_percent, 30);
+            assert!(sink.muted);
+        });
+    }
+}
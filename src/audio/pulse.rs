@@ -0,0 +1,149 @@
+//! A client of the native protocol of PulseAudio, which the PulseAudio server of PipeWire speaks
+//! too. The messages are encoded by the `pulseaudio` crate, and sent on tokio's sockets so that
+//! waiting for the server never blocks a thread.
+
+use std::{collections::VecDeque, ffi::CString, io::Cursor, path::PathBuf};
+
+use pulseaudio::protocol::{
+    self, AuthParams, AuthReply, Command, CommandReply, Prop, Props, SetClientNameReply,
+    SubscriptionEvent, SubscriptionMask, DESCRIPTOR_SIZE,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use super::AudioError;
+
+/// A connection to the server, on which the commands are sent one at a time.
+pub struct Connection {
+    stream: UnixStream,
+    /// the bytes received but not parsed yet, which keeps reading cancel-safe
+    received: Vec<u8>,
+    protocol_version: u16,
+    next_seq: u32,
+    /// the events received while waiting for a reply
+    events: VecDeque<SubscriptionEvent>,
+}
+
+impl Connection {
+    /// Connects to the server given by `PULSE_SERVER`, or to the one of the session.
+    pub async fn connect() -> Result<Self, AudioError> {
+        let path = pulseaudio::socket_path_from_env().ok_or(AudioError::ServerNotFound)?;
+        let cookie = pulseaudio::cookie_path_from_env()
+            .and_then(|path| std::fs::read(path).ok())
+            .unwrap_or_default();
+        Self::connect_to(path, cookie).await
+    }
+
+    pub async fn connect_to(path: PathBuf, cookie: Vec<u8>) -> Result<Self, AudioError> {
+        let stream = UnixStream::connect(&path)
+            .await
+            .map_err(|error| AudioError::IoError { path, error })?;
+        let mut connection = Self {
+            stream,
+            received: Vec::new(),
+            protocol_version: protocol::MAX_VERSION,
+            next_seq: 0,
+            events: VecDeque::new(),
+        };
+
+        let auth: AuthReply = connection
+            .request(Command::Auth(AuthParams {
+                version: protocol::MAX_VERSION,
+                supports_shm: false,
+                supports_memfd: false,
+                cookie,
+            }))
+            .await?;
+        connection.protocol_version = auth.version.min(protocol::MAX_VERSION);
+
+        let mut props = Props::new();
+        props.set(Prop::ApplicationName, CString::new("widgets").unwrap());
+        let _: SetClientNameReply = connection.request(Command::SetClientName(props)).await?;
+        Ok(connection)
+    }
+
+    /// Sends a command, and returns the reply to it. The events received meanwhile are kept for
+    /// `next_event`.
+    pub async fn request<R: CommandReply>(&mut self, command: Command) -> Result<R, AudioError> {
+        let packet = self.send(command).await?;
+        Ok(protocol::read_reply_message::<R>(&mut Cursor::new(packet), self.protocol_version)?.1)
+    }
+
+    /// Sends a command whose reply is empty.
+    pub async fn execute(&mut self, command: Command) -> Result<(), AudioError> {
+        self.send(command).await.map(|_| ())
+    }
+
+    /// Asks the server to tell about the changes of these objects.
+    pub async fn subscribe(&mut self, mask: SubscriptionMask) -> Result<(), AudioError> {
+        self.execute(Command::Subscribe(mask)).await
+    }
+
+    /// Waits for the next event. This is cancel-safe, so it can be raced against a timeout.
+    pub async fn next_event(&mut self) -> Result<SubscriptionEvent, AudioError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let packet = self.receive().await?;
+            let (_, command) =
+                protocol::read_command_message(&mut Cursor::new(packet), self.protocol_version)?;
+            if let Command::SubscribeEvent(event) = command {
+                self.events.push_back(event);
+            }
+        }
+    }
+
+    /// Sends a command, and returns the whole packet of its reply.
+    async fn send(&mut self, command: Command) -> Result<Vec<u8>, AudioError> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let mut message = Vec::new();
+        protocol::write_command_message(&mut message, seq, &command, self.protocol_version)?;
+        self.stream
+            .write_all(&message)
+            .await
+            .map_err(AudioError::ConnectionError)?;
+
+        loop {
+            let packet = self.receive().await?;
+            let (reply_seq, reply) = protocol::read_command_message(
+                &mut Cursor::new(&packet[..]),
+                self.protocol_version,
+            )?;
+            match reply {
+                Command::Reply if reply_seq == seq => return Ok(packet),
+                Command::Error(error) if reply_seq == seq => {
+                    return Err(AudioError::ServerError {
+                        command: format!("{:?}", command),
+                        error,
+                    })
+                }
+                Command::SubscribeEvent(event) => self.events.push_back(event),
+                _ => (),
+            }
+        }
+    }
+
+    /// Reads the next packet, with its descriptor.
+    async fn receive(&mut self) -> Result<Vec<u8>, AudioError> {
+        loop {
+            if self.received.len() >= DESCRIPTOR_SIZE {
+                let length = u32::from_be_bytes(self.received[..4].try_into().unwrap()) as usize;
+                if self.received.len() >= DESCRIPTOR_SIZE + length {
+                    return Ok(self.received.drain(..DESCRIPTOR_SIZE + length).collect());
+                }
+            }
+            let read = self
+                .stream
+                .read_buf(&mut self.received)
+                .await
+                .map_err(AudioError::ConnectionError)?;
+            if read == 0 {
+                return Err(AudioError::Disconnected);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use iced::{subscription, Subscription};
+use pulseaudio::protocol::SubscriptionMask;
+
+use super::{get_state, pulse::Connection, Audio, AudioError, Client};
+
+/// The server sends several events for a single change, e.g. when a device is plugged in, which
+/// we handle at once.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+/// How long to wait before connecting to the server again when it fails, e.g. while it restarts.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// The applications' playback streams (sink inputs) don't change the state, unlike the recording
+/// ones (source outputs).
+pub(super) const EVENTS: SubscriptionMask = SubscriptionMask::SINK
+    .union(SubscriptionMask::SOURCE)
+    .union(SubscriptionMask::SOURCE_OUTPUT)
+    .union(SubscriptionMask::SERVER)
+    .union(SubscriptionMask::CARD);
+
+#[derive(Debug, Clone)]
+pub enum AudioEvent {
+    State(Audio),
+    // like for Hyprland, the UI only needs to know that there is an error, which is logged here
+    Error,
+}
+
+enum SubscriptionState {
+    Starting,
+    Ongoing {
+        /// the connection receiving the events, on which the state is read too
+        events: Connection,
+        client: Client,
+    },
+    Error,
+}
+
+async fn start() -> Result<(Connection, Client), AudioError> {
+    let mut events = Connection::connect().await?;
+    events.subscribe(EVENTS).await?;
+    Ok((events, Client::connect().await?))
+}
+
+/// Waits for the next change of the devices.
+async fn next_change(events: &mut Connection) -> Result<(), AudioError> {
+    events.next_event().await?;
+    while let Ok(event) = tokio::time::timeout(DEBOUNCE, events.next_event()).await {
+        event?;
+    }
+    Ok(())
+}
+
+/// Reads the state of the audio server, and then again each time it changes.
+pub fn watch() -> Subscription<AudioEvent> {
+    struct PulseSubscription;
+
+    subscription::unfold(
+        std::any::TypeId::of::<PulseSubscription>(),
+        SubscriptionState::Starting,
+        |state| async move {
+            let started = match state {
+                SubscriptionState::Starting => start().await,
+                SubscriptionState::Ongoing { mut events, client } => {
+                    next_change(&mut events).await.map(|()| (events, client))
+                }
+                SubscriptionState::Error => {
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                    start().await
+                }
+            };
+            let result = match started {
+                Ok((mut events, client)) => get_state(&mut events)
+                    .await
+                    .map(|state| (state, events, client)),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok((audio_state, events, client)) => (
+                    AudioEvent::State(Audio {
+                        client: client.clone(),
+                        state: audio_state,
+                    }),
+                    SubscriptionState::Ongoing { events, client },
+                ),
+                Err(e) => {
+                    log::error!("{}", e);
+                    (AudioEvent::Error, SubscriptionState::Error)
+                }
+            }
+        },
+    )
+}
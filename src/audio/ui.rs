@@ -0,0 +1,279 @@
+use iced::{
+    color,
+    widget::{button, column, container, mouse_area, scrollable, text, vertical_space, Column},
+    Command, Element, Length, Padding,
+};
+
+use super::{Audio, DeviceKind};
+use crate::{
+    scroll_area::ScrollArea,
+    style::{self, ContainerStyle},
+};
+
+/// How much one scroll step changes the volume, in percents.
+const VOLUME_STEP: i32 = 5;
+/// Longer lists of recording applications are cut, so that the widget doesn't push the others
+/// away.
+const MAX_LABEL_LENGTH: usize = 40;
+
+#[derive(Debug, Clone)]
+pub enum VolumeMessage {
+    /// change the volume of the default sink by this many steps
+    AdjustVolume(i32),
+    ToggleMute,
+    /// open or close the popup to pick the default sink and source
+    TogglePopup,
+    SetDefault(DeviceKind, String),
+    CommandDone(Result<(), String>),
+}
+
+/// Shows the volume of the default sink. Scroll to change it, click to mute it, and right click
+/// to pick the default sink and source. The state of the server is given by the main app, which
+/// shares it with the microphone display; it is `None` until the server has been reached, or
+/// after an error.
+#[derive(Default)]
+pub struct VolumeDisplay;
+
+impl VolumeDisplay {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn update(
+        &mut self,
+        audio: Option<&mut Audio>,
+        message: VolumeMessage,
+    ) -> Command<VolumeMessage> {
+        match message {
+            VolumeMessage::AdjustVolume(steps) => {
+                let Some(Audio { client, state }) = audio else {
+                    return Command::none();
+                };
+                let Some(sink) = state.default_device_mut(DeviceKind::Sink) else {
+                    return Command::none();
+                };
+                // scrolling up never goes above 100%, but doesn't bring down a volume already
+                // raised above it elsewhere either
+                let max = sink.volume_percent.max(100) as i32;
+                // updated right away, so that scrolling quickly adds up before the server
+                // tells us about the new volume
+                sink.volume_percent =
+                    (sink.volume_percent as i32 + steps * VOLUME_STEP).clamp(0, max) as u32;
+                return run(
+                    client
+                        .clone()
+                        .set_volume(DeviceKind::Sink, sink.clone(), sink.volume_percent),
+                    VolumeMessage::CommandDone,
+                );
+            }
+            VolumeMessage::ToggleMute => {
+                if let Some(Audio { client, state }) = audio {
+                    if let Some(sink) = state.default_device(DeviceKind::Sink) {
+                        return run(
+                            client.clone().set_mute(
+                                DeviceKind::Sink,
+                                sink.name.clone(),
+                                !sink.muted,
+                            ),
+                            VolumeMessage::CommandDone,
+                        );
+                    }
+                }
+            }
+            VolumeMessage::SetDefault(kind, name) => {
+                if let Some(Audio { client, .. }) = audio {
+                    return run(
+                        client.clone().set_default(kind, name),
+                        VolumeMessage::CommandDone,
+                    );
+                }
+            }
+            VolumeMessage::CommandDone(Err(e)) => log::error!("{}", e),
+            // the server tells us about the changes like for any other
+            VolumeMessage::CommandDone(Ok(())) | VolumeMessage::TogglePopup => (),
+        }
+        Command::none()
+    }
+
+    pub fn view(&self, audio: Option<&Audio>) -> Element<'_, VolumeMessage> {
+        let label = match audio.and_then(|audio| audio.state.default_device(DeviceKind::Sink)) {
+            Some(sink) if sink.muted => format!("󰝟 {}%", sink.volume_percent),
+            Some(sink) => {
+                let icon = match sink.volume_percent {
+                    0..=33 => '󰕿',
+                    34..=66 => '󰖀',
+                    _ => '󰕾',
+                };
+                format!("{} {}%", icon, sink.volume_percent)
+            }
+            None => "󰖁 ?".into(),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme());
+
+        let clickable = mouse_area(content)
+            .on_press(VolumeMessage::ToggleMute)
+            .on_right_press(VolumeMessage::TogglePopup);
+
+        ScrollArea::new(clickable)
+            .on_scroll_up(VolumeMessage::AdjustVolume(1))
+            .on_scroll_down(VolumeMessage::AdjustVolume(-1))
+            .into()
+    }
+
+    /// the content of the popup, with the sinks and the sources to pick the default ones from
+    pub fn view_popup<'a>(&self, audio: Option<&'a Audio>) -> Element<'a, VolumeMessage> {
+        let Some(Audio { state, .. }) = audio else {
+            return style::popup(scrollable(column![
+                text("Le serveur audio ne répond pas").style(iced::Color::WHITE)
+            ]));
+        };
+
+        let devices = |kind, title| {
+            let default = state.default_device(kind).map(|device| &device.name);
+            let buttons = state
+                .devices(kind)
+                .iter()
+                .map(|device| {
+                    button(text(&device.description).style(iced::Color::WHITE))
+                        .width(Length::Fill)
+                        .style(iced::theme::Button::custom(DeviceButtonStyle {
+                            selected: Some(&device.name) == default,
+                        }))
+                        .on_press(VolumeMessage::SetDefault(kind, device.name.clone()))
+                        .into()
+                })
+                .collect::<Vec<Element<_>>>();
+            column![
+                text(title).style(iced::Color::WHITE),
+                Column::with_children(buttons).spacing(5)
+            ]
+            .spacing(5)
+        };
+
+        style::popup(scrollable(
+            column![
+                devices(DeviceKind::Sink, "Sorties"),
+                devices(DeviceKind::Source, "Entrées")
+            ]
+            .spacing(15),
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MicrophoneMessage {
+    ToggleMute,
+    CommandDone(Result<(), String>),
+}
+
+/// Shows whether the default source is muted, and toggles it when clicked. It turns red while an
+/// application records from an unmuted source, which is what matters during a video call. Like
+/// for the volume display, the state of the server is given by the main app.
+#[derive(Default)]
+pub struct MicrophoneDisplay;
+
+impl MicrophoneDisplay {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn update(
+        &mut self,
+        audio: Option<&Audio>,
+        message: MicrophoneMessage,
+    ) -> Command<MicrophoneMessage> {
+        match message {
+            MicrophoneMessage::ToggleMute => {
+                if let Some(Audio { client, state }) = audio {
+                    if let Some(source) = state.default_device(DeviceKind::Source) {
+                        return run(
+                            client.clone().set_mute(
+                                DeviceKind::Source,
+                                source.name.clone(),
+                                !source.muted,
+                            ),
+                            MicrophoneMessage::CommandDone,
+                        );
+                    }
+                }
+            }
+            MicrophoneMessage::CommandDone(Err(e)) => log::error!("{}", e),
+            MicrophoneMessage::CommandDone(Ok(())) => (),
+        }
+        Command::none()
+    }
+
+    pub fn view(&self, audio: Option<&Audio>) -> Element<'_, MicrophoneMessage> {
+        let source = audio.and_then(|audio| audio.state.default_device(DeviceKind::Source));
+        let recording = audio.map_or(&[][..], |audio| &audio.state.recording[..]);
+
+        let (label, background) = match source {
+            Some(source) if source.muted => ("󰍭".to_string(), color!(0x282828)),
+            // the applications are listed, so that the one listening is never a surprise
+            Some(_) if !recording.is_empty() => {
+                let applications = recording.join(", ");
+                let applications = match applications.chars().count() > MAX_LABEL_LENGTH {
+                    true => format!(
+                        "{}…",
+                        applications
+                            .chars()
+                            .take(MAX_LABEL_LENGTH - 1)
+                            .collect::<String>()
+                    ),
+                    false => applications,
+                };
+                (format!("󰍬 {}", applications), color!(0xcc241d))
+            }
+            Some(_) => ("󰍬".to_string(), color!(0x282828)),
+            None => ("󰍮 ?".to_string(), color!(0x282828)),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle { background }.theme());
+
+        mouse_area(content)
+            .on_press(MicrophoneMessage::ToggleMute)
+            .into()
+    }
+}
+
+/// Runs a command in the background, and sends its result with `done`.
+fn run<Message: 'static>(
+    command: impl std::future::Future<Output = Result<(), super::AudioError>> + Send + 'static,
+    done: fn(Result<(), String>) -> Message,
+) -> Command<Message> {
+    Command::perform(command, move |result| {
+        done(result.map_err(|e| e.to_string()))
+    })
+}
+
+struct DeviceButtonStyle {
+    /// whether this is the default device
+    selected: bool,
+}
+
+impl button::StyleSheet for DeviceButtonStyle {
+    type Style = iced::Theme;
+
+    fn active(&self, _style: &Self::Style) -> button::Appearance {
+        button::Appearance {
+            background: Some(iced::Background::Color(match self.selected {
+                true => color!(0x458588),
+                false => color!(0x3c3836),
+            })),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Discovery of the backlights the kernel exposes in `/sys/class/backlight`. The root directory
+//! is always a parameter, so that a fake tree can be used instead.
+
+use std::{
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+pub const BACKLIGHT_ROOT: &str = "/sys/class/backlight";
+
+#[derive(Debug)]
+pub enum BacklightError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParsingError {
+        path: PathBuf,
+        raw: String,
+    },
+    NoBacklight {
+        root: PathBuf,
+    },
+    LogindError(zbus::Error),
+}
+
+impl Display for BacklightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::ParsingError { path, raw } => {
+                writeln!(f, "Unable to parse the content of {}", path.display())?;
+                write!(f, "Got '{}'", raw)
+            }
+            Self::NoBacklight { root } => write!(f, "No backlight in {}", root.display()),
+            Self::LogindError(e) => write!(f, "Error communicating with logind : {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Backlight {
+    pub path: PathBuf,
+}
+
+impl Backlight {
+    /// the name of the device, e.g. `intel_backlight`, which logind needs to set the brightness
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The files that change with the brightness. `brightness` only does when written to, while
+    /// the kernel notifies the changes of `actual_brightness`, including the ones made by the
+    /// firmware when it handles the brightness keys itself.
+    pub fn watched_paths(&self) -> [PathBuf; 2] {
+        [
+            self.path.join("brightness"),
+            self.path.join("actual_brightness"),
+        ]
+    }
+
+    fn read_parsed(&self, attribute: &str) -> Result<u32, BacklightError> {
+        let path = self.path.join(attribute);
+        let raw = read_to_string(&path).map_err(|error| BacklightError::IoError {
+            path: path.clone(),
+            error,
+        })?;
+        raw.trim()
+            .parse()
+            .map_err(|_| BacklightError::ParsingError { path, raw })
+    }
+
+    /// Returns the brightness and the maximum brightness, in the unit of the driver. The actual
+    /// brightness is the one of the hardware, which the firmware may have changed.
+    pub fn read(&self) -> Result<(u32, u32), BacklightError> {
+        Ok((
+            self.read_parsed("actual_brightness")?,
+            self.read_parsed("max_brightness")?,
+        ))
+    }
+}
+
+/// The backlight to control, when there are several, preferring the ones driven by the firmware,
+/// then by the platform, which the kernel documentation advises over the raw ones.
+pub fn discover(root: &Path) -> Result<Backlight, BacklightError> {
+    let io_error = |error| BacklightError::IoError {
+        path: root.into(),
+        error,
+    };
+    let mut backlights = Vec::new();
+    for entry in std::fs::read_dir(root).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        let priority = match read_to_string(path.join("type")).as_deref().map(str::trim) {
+            Ok("firmware") => 0,
+            Ok("platform") => 1,
+            Ok("raw") => 2,
+            // not a backlight, or one going away while we read it
+            _ => continue,
+        };
+        backlights.push((priority, path));
+    }
+    backlights.sort();
+    backlights
+        .into_iter()
+        .next()
+        .map(|(_, path)| Backlight { path })
+        .ok_or(BacklightError::NoBacklight { root: root.into() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+
+    fn discovered(tree: &FakeTree) -> Option<String> {
+        discover(tree.path()).ok().map(|backlight| backlight.name())
+    }
+
+    #[test]
+    fn prefers_the_firmware_then_the_platform_backlights() {
+        let tree = FakeTree::new();
+        tree.write("intel_backlight/type", "raw\n")
+            .write("dell_backlight/type", "platform\n")
+            .write("acpi_video0/type", "firmware\n")
+            // e.g. a LED class device linked here by mistake
+            .mkdir("led");
+        assert_eq!(discovered(&tree).as_deref(), Some("acpi_video0"));
+
+        std::fs::remove_dir_all(tree.path().join("acpi_video0")).unwrap();
+        assert_eq!(discovered(&tree).as_deref(), Some("dell_backlight"));
+
+        std::fs::remove_dir_all(tree.path().join("dell_backlight")).unwrap();
+        assert_eq!(discovered(&tree).as_deref(), Some("intel_backlight"));
+    }
+
+    #[test]
+    fn skips_the_entries_without_a_type() {
+        let tree = FakeTree::new();
+        tree.mkdir("led").write("unknown/type", "something\n");
+        assert!(matches!(
+            discover(tree.path()),
+            Err(BacklightError::NoBacklight { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_the_actual_brightness() {
+        let tree = FakeTree::new();
+        tree.write("intel_backlight/type", "raw\n")
+            .write("intel_backlight/brightness", "19200\n")
+            .write("intel_backlight/actual_brightness", "24000\n")
+            .write("intel_backlight/max_brightness", "96000\n");
+        let backlight = discover(tree.path()).unwrap();
+        assert_eq!(backlight.read().unwrap(), (24000, 96000));
+
+        tree.write("intel_backlight/actual_brightness", "bright\n");
+        assert!(matches!(
+            backlight.read(),
+            Err(BacklightError::ParsingError { .. })
+        ));
+    }
+}
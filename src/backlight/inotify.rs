@@ -0,0 +1,75 @@
+//! Watching the brightness files with inotify, to notice the changes made by other programs or by
+//! the firmware, e.g. for the brightness keys.
+
+use std::{
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+};
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use tokio::io::unix::AsyncFd;
+
+/// Room for a few events, which only tell us that the file changed.
+const BUFFER_SIZE: usize = 1024;
+
+pub struct BrightnessWatch {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl BrightnessWatch {
+    pub fn open(paths: &[PathBuf]) -> std::io::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        // SAFETY: the file descriptor was just created, and `Inotify` doesn't close it
+        let fd = unsafe { OwnedFd::from_raw_fd(inotify.as_raw_fd()) };
+        for path in paths {
+            inotify.add_watch(path.as_path(), AddWatchFlags::IN_MODIFY)?;
+        }
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Waits until one of the brightness files changes.
+    pub async fn changed(&self) -> std::io::Result<()> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let read =
+                guard.try_io(|fd| Ok(nix::unistd::read(fd.get_ref().as_raw_fd(), &mut buffer)?));
+            match read {
+                Ok(Ok(_)) => return Ok(()),
+                Ok(Err(e)) => return Err(e),
+                // spurious wakeup
+                Err(_would_block) => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{block_on, FakeTree};
+
+    #[test]
+    fn wakes_up_when_the_actual_brightness_changes() {
+        let tree = FakeTree::new();
+        tree.write("brightness", "100\n")
+            .write("actual_brightness", "100\n");
+        let paths = [
+            tree.path().join("brightness"),
+            tree.path().join("actual_brightness"),
+        ];
+        block_on(async {
+            let watch = BrightnessWatch::open(&paths).unwrap();
+            let changed = tokio::time::timeout(Duration::from_millis(100), watch.changed()).await;
+            assert!(changed.is_err());
+
+            tree.write("actual_brightness", "50\n");
+            let changed = tokio::time::timeout(Duration::from_secs(5), watch.changed()).await;
+            assert!(changed.unwrap().is_ok());
+        });
+    }
+}
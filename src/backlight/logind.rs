@@ -0,0 +1,47 @@
+//! Setting the brightness through logind, which lets the user of the active session do it
+//! without being root.
+
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use zbus::{dbus_proxy, Connection};
+
+use super::device::BacklightError;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    fn set_brightness(&self, subsystem: &str, name: &str, brightness: u32) -> zbus::Result<()>;
+}
+
+/// The session of logind, connected to on the first change. The changes are sent one at a time,
+/// so that they are applied in the order of the scroll steps.
+#[derive(Clone, Default)]
+pub struct Logind(Arc<Mutex<Option<SessionProxy<'static>>>>);
+
+impl Logind {
+    pub async fn set_brightness(self, name: String, brightness: u32) -> Result<(), BacklightError> {
+        let mut session = self.0.lock().await;
+        let proxy = match session.as_ref() {
+            Some(proxy) => proxy.clone(),
+            None => {
+                let connection = Connection::system()
+                    .await
+                    .map_err(BacklightError::LogindError)?;
+                let proxy = SessionProxy::new(&connection)
+                    .await
+                    .map_err(BacklightError::LogindError)?;
+                session.insert(proxy).clone()
+            }
+        };
+        let result = proxy.set_brightness("backlight", &name, brightness).await;
+        // the connection is opened again for the next change, in case logind restarted
+        if result.is_err() {
+            *session = None;
+        }
+        result.map_err(BacklightError::LogindError)
+    }
+}
@@ -0,0 +1,265 @@
+//! The brightness of the screen backlight, from `/sys/class/backlight`, set through logind.
+
+pub mod device;
+mod inotify;
+mod logind;
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use iced::{
+    subscription,
+    widget::{column, container, text, vertical_space},
+    Command, Element, Length, Padding, Subscription,
+};
+
+use crate::{scroll_area::ScrollArea, style::ContainerStyle};
+use device::{Backlight, BacklightError};
+use inotify::BrightnessWatch;
+use logind::Logind;
+
+/// How often to read the brightness when we can't watch the file.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// The backlight only appears with its driver, so it is looked for again twice a minute.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// How much one scroll step changes the perceived brightness, out of 1.
+const STEP: f64 = 0.05;
+/// The eye is more sensitive to the changes at low brightness, so the steps follow
+/// `brightness = level ^ GAMMA` rather than being linear.
+const GAMMA: f64 = 2.2;
+
+/// The brightness after scrolling by `steps` from `brightness`, on the perceptual scale.
+fn adjusted(brightness: u32, max: u32, steps: i32) -> u32 {
+    if max == 0 {
+        return 0;
+    }
+    let level = (brightness as f64 / max as f64).powf(1.0 / GAMMA);
+    let level = (level + steps as f64 * STEP).clamp(0.0, 1.0);
+    let target = (level.powf(GAMMA) * max as f64).round() as i64;
+    // at low brightness, a step can round to the same value, which would make scrolling stuck
+    let target = match target == brightness as i64 {
+        true => target + steps.signum() as i64,
+        false => target,
+    };
+    // a brightness of 0 turns some screens off
+    target.clamp(1, max as i64) as u32
+}
+
+#[derive(Debug, Clone)]
+pub enum BacklightMessage {
+    /// the name of the device, its brightness and its maximum brightness
+    Changed(String, u32, u32),
+    Error(String),
+    /// change the brightness by this many steps
+    Adjust(i32),
+    BrightnessSet(Result<(), String>),
+}
+
+enum Source {
+    Starting,
+    Watching(Backlight, BrightnessWatch),
+    Polling(Backlight),
+    Failed,
+}
+
+/// Shows the brightness of the backlight, and changes it when scrolled over.
+pub struct BacklightDisplay {
+    /// `/sys/class/backlight`, or another directory with the same layout
+    root: PathBuf,
+    /// the device, once found by the subscription
+    name: Option<String>,
+    brightness: Option<(u32, u32)>,
+    logind: Logind,
+}
+
+impl BacklightDisplay {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            name: None,
+            brightness: None,
+            logind: Logind::default(),
+        }
+    }
+
+    pub fn update(&mut self, message: BacklightMessage) -> Command<BacklightMessage> {
+        match message {
+            BacklightMessage::Changed(name, brightness, max) => {
+                self.name = Some(name);
+                self.brightness = Some((brightness, max));
+            }
+            BacklightMessage::Error(_) => self.brightness = None,
+            BacklightMessage::Adjust(steps) => {
+                let (Some(name), Some((brightness, max))) = (&self.name, self.brightness) else {
+                    return Command::none();
+                };
+                let target = adjusted(brightness, max, steps);
+                // updated right away, so that scrolling quickly adds up before the file changes
+                self.brightness = Some((target, max));
+                return Command::perform(
+                    self.logind.clone().set_brightness(name.clone(), target),
+                    |result| BacklightMessage::BrightnessSet(result.map_err(|e| e.to_string())),
+                );
+            }
+            BacklightMessage::BrightnessSet(Err(e)) => {
+                log::error!("Unable to change the brightness : {}", e);
+                // the brightness shown was updated ahead, so it is read again from the device
+                if let Some(name) = &self.name {
+                    let backlight = Backlight {
+                        path: self.root.join(name),
+                    };
+                    self.brightness = match backlight.read() {
+                        Ok(brightness) => Some(brightness),
+                        Err(e) => {
+                            log::error!("Unable to access the backlight : {}", e);
+                            None
+                        }
+                    };
+                }
+            }
+            // the file changes like for any other change
+            BacklightMessage::BrightnessSet(Ok(())) => (),
+        }
+        Command::none()
+    }
+
+    /// Reads the brightness each time its files change, or every `POLL_INTERVAL` when they can't
+    /// be watched.
+    pub fn subscription(&self) -> Subscription<BacklightMessage> {
+        struct BacklightUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<BacklightUpdates>(),
+            (self.root.clone(), Source::Starting),
+            |(root, source)| async move {
+                let started = match source {
+                    Source::Starting => start(&root),
+                    Source::Watching(backlight, watch) => match watch.changed().await {
+                        Ok(()) => Ok((backlight, Some(watch))),
+                        Err(e) => {
+                            log::error!("Unable to watch the brightness, polling instead : {}", e);
+                            Ok((backlight, None))
+                        }
+                    },
+                    Source::Polling(backlight) => {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                        Ok((backlight, None))
+                    }
+                    Source::Failed => {
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        start(&root)
+                    }
+                };
+                let reading = match started {
+                    Ok((backlight, watch)) => backlight
+                        .read()
+                        .map(|brightness| (brightness, backlight, watch)),
+                    Err(e) => Err(e),
+                };
+                match reading {
+                    Ok(((brightness, max), backlight, watch)) => {
+                        let name = backlight.name();
+                        let source = match watch {
+                            Some(watch) => Source::Watching(backlight, watch),
+                            None => Source::Polling(backlight),
+                        };
+                        (
+                            BacklightMessage::Changed(name, brightness, max),
+                            (root, source),
+                        )
+                    }
+                    Err(e) => {
+                        log::error!("Unable to access the backlight : {}", e);
+                        (
+                            BacklightMessage::Error(e.to_string()),
+                            (root, Source::Failed),
+                        )
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, BacklightMessage> {
+        let label = match self.brightness {
+            Some((brightness, max)) if max > 0 => {
+                format!("󰃟 {}%", (brightness as u64 * 100 / max as u64))
+            }
+            _ => "󰃟 ?".to_string(),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme());
+
+        ScrollArea::new(content)
+            .on_scroll_up(BacklightMessage::Adjust(1))
+            .on_scroll_down(BacklightMessage::Adjust(-1))
+            .into()
+    }
+}
+
+/// Looks for the backlight, and starts watching its brightness, if possible.
+fn start(root: &Path) -> Result<(Backlight, Option<BrightnessWatch>), BacklightError> {
+    let backlight = device::discover(root)?;
+    let watch = match BrightnessWatch::open(&backlight.watched_paths()) {
+        Ok(watch) => Some(watch),
+        Err(e) => {
+            log::error!("Unable to watch the brightness, polling instead : {}", e);
+            None
+        }
+    };
+    Ok((backlight, watch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comes_back_to_the_same_brightness() {
+        for max in [255, 937, 96000] {
+            for brightness in [max / 10, max / 3, max / 2, max * 4 / 5] {
+                let up = adjusted(brightness, max, 1);
+                assert!(up > brightness);
+                assert_eq!(
+                    adjusted(up, max, -1),
+                    brightness,
+                    "{} of {}",
+                    brightness,
+                    max
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn takes_smaller_steps_at_low_brightness() {
+        let max = 96000;
+        let low = adjusted(max / 10, max, 1) - max / 10;
+        let high = adjusted(max * 4 / 5, max, 1) - max * 4 / 5;
+        assert!(low < high);
+    }
+
+    #[test]
+    fn never_gets_stuck_or_turns_the_screen_off_at_the_low_end() {
+        // a step rounds to the same value with few levels
+        assert_eq!(adjusted(1, 15, 1), 2);
+        assert_eq!(adjusted(2, 15, -1), 1);
+        assert_eq!(adjusted(1, 15, -1), 1);
+        assert_eq!(adjusted(0, 255, -1), 1);
+    }
+
+    #[test]
+    fn stops_at_the_maximum() {
+        assert_eq!(adjusted(255, 255, 1), 255);
+        assert_eq!(adjusted(250, 255, 3), 255);
+        assert_eq!(adjusted(0, 0, 1), 0);
+    }
+}
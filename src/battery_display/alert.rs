@@ -0,0 +1,165 @@
+//! Warnings when the batteries run low.
+
+use super::{BatteryConfig, State};
+
+/// How worried we are about the charge of the batteries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    fn threshold(&self, config: &BatteryConfig) -> u32 {
+        match self {
+            Level::Normal => 100,
+            Level::Warning => config.warning_percent,
+            Level::Critical => config.critical_percent,
+        }
+    }
+
+    /// Computes the level after a new reading. Going back to a less severe level requires the
+    /// charge to be `hysteresis_percent` above the threshold, so that a charge oscillating around
+    /// it doesn't trigger a flurry of notifications. Only charging resets the level right away:
+    /// some firmwares report an unknown state for a moment while discharging, which must not
+    /// fire the alerts again.
+    pub fn next(self, percent_charge: u32, state: State, config: &BatteryConfig) -> Level {
+        let target = if percent_charge <= config.critical_percent {
+            Level::Critical
+        } else if percent_charge <= config.warning_percent {
+            Level::Warning
+        } else {
+            Level::Normal
+        };
+
+        let next = if target < self
+            && percent_charge <= self.threshold(config) + config.hysteresis_percent
+        {
+            self
+        } else {
+            target
+        };
+
+        match state {
+            State::Charging | State::Full => Level::Normal,
+            State::Discharging | State::Empty => next,
+            // not discharging, so never more worried than before
+            State::NotCharging | State::Other => next.min(self),
+        }
+    }
+}
+
+/// Tells the user that the batteries crossed a threshold.
+pub fn alert(level: Level, percent_charge: u32, config: &BatteryConfig) {
+    match level {
+        Level::Normal => (),
+        Level::Warning => crate::notification::send(
+            "Batterie faible",
+            &format!("Il reste {}% de batterie", percent_charge),
+        ),
+        Level::Critical => {
+            crate::notification::send(
+                "Batterie critique",
+                &format!(
+                    "Il reste {}% de batterie, branchez le chargeur",
+                    percent_charge
+                ),
+            );
+            if let Some(command) = &config.critical_command {
+                crate::notification::run_command(command);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the levels after each reading, starting from `Level::Normal`
+    fn levels(readings: &[(u32, State)]) -> Vec<Level> {
+        let config = BatteryConfig::default();
+        let mut level = Level::Normal;
+        readings
+            .iter()
+            .map(|(percent_charge, state)| {
+                level = level.next(*percent_charge, *state, &config);
+                level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crosses_the_thresholds_while_discharging() {
+        let levels = levels(&[
+            (21, State::Discharging),
+            (20, State::Discharging),
+            (11, State::Discharging),
+            (10, State::Discharging),
+        ]);
+        assert_eq!(
+            levels,
+            [
+                Level::Normal,
+                Level::Warning,
+                Level::Warning,
+                Level::Critical
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_the_hysteresis_to_go_back() {
+        let levels = levels(&[
+            (20, State::Discharging),
+            (21, State::Discharging),
+            (23, State::Discharging),
+            (24, State::Discharging),
+            (20, State::Discharging),
+        ]);
+        assert_eq!(
+            levels,
+            [
+                Level::Warning,
+                Level::Warning,
+                Level::Warning,
+                Level::Normal,
+                Level::Warning
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_states_while_discharging() {
+        let flapping = levels(&[
+            (10, State::Discharging),
+            (10, State::Other),
+            (10, State::Discharging),
+            (9, State::Other),
+            (9, State::Discharging),
+        ]);
+        assert!(flapping.iter().all(|level| *level == Level::Critical));
+        // an unknown state isn't enough to raise the alert
+        assert_eq!(levels(&[(5, State::Other)]), [Level::Normal]);
+    }
+
+    #[test]
+    fn resets_when_charging() {
+        let levels = levels(&[
+            (10, State::Discharging),
+            (10, State::Charging),
+            (10, State::Discharging),
+            (10, State::Full),
+        ]);
+        assert_eq!(
+            levels,
+            [
+                Level::Critical,
+                Level::Normal,
+                Level::Critical,
+                Level::Normal
+            ]
+        );
+    }
+}
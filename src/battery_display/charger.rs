@@ -0,0 +1,98 @@
+//! The charger plugged in, if any, read from the `Mains` and `USB` power supplies.
+
+use super::sysfs::{PowerSupply, PowerSupplyType};
+
+#[derive(Debug, Clone)]
+pub struct Charger {
+    pub name: String,
+    /// the negotiated USB mode, e.g. `PD`, for USB-C chargers
+    pub usb_type: Option<String>,
+    /// the maximum power of the charger, in µW, when the driver knows the negotiated voltage and
+    /// current
+    pub power: Option<u64>,
+}
+
+impl Charger {
+    /// Returns `None` if this isn't a charger, or if it isn't plugged in.
+    fn read(supply: &PowerSupply) -> Option<Self> {
+        if !matches!(supply.kind, PowerSupplyType::Mains | PowerSupplyType::Usb)
+            || supply.read_optional::<u32>("online") != Some(1)
+        {
+            return None;
+        }
+
+        Some(Self {
+            name: supply.name(),
+            usb_type: supply
+                .read_optional::<String>("usb_type")
+                .and_then(|raw| active_usb_type(&raw)),
+            power: supply
+                .read_optional::<u64>("voltage_now")
+                .zip(supply.read_optional::<u64>("current_max"))
+                .map(|(voltage, current)| voltage * current / 1_000_000)
+                .filter(|power| *power > 0),
+        })
+    }
+
+    /// `power` in W, rounded
+    pub fn watts(&self) -> Option<u64> {
+        self.power.map(|power| (power + 500_000) / 1_000_000)
+    }
+}
+
+/// `usb_type` lists the supported modes, with the active one in brackets, e.g. `C [PD] PD_PPS`.
+fn active_usb_type(raw: &str) -> Option<String> {
+    raw.split_whitespace()
+        .find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))
+        .map(str::to_string)
+}
+
+/// The charger plugged in, preferring the one giving the most power when there are several
+/// (e.g. the `Mains` supply of the ACPI and the `USB` one of the USB-C controller for the same
+/// charger).
+pub fn find(supplies: &[PowerSupply]) -> Option<Charger> {
+    supplies
+        .iter()
+        .filter_map(Charger::read)
+        .max_by_key(|charger| charger.power)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{battery_display::sysfs::discover, testing::fixture};
+
+    fn charger(tree: &str) -> Option<Charger> {
+        find(&discover(&fixture(tree)).unwrap())
+    }
+
+    #[test]
+    fn finds_the_mode_in_brackets() {
+        assert_eq!(active_usb_type("C [PD] PD_PPS").as_deref(), Some("PD"));
+        assert_eq!(active_usb_type("[C] PD PD_PPS").as_deref(), Some("C"));
+        assert_eq!(active_usb_type("C PD PD_PPS"), None);
+        assert_eq!(active_usb_type(""), None);
+    }
+
+    #[test]
+    fn prefers_the_usb_pd_charger_to_the_mains_supply() {
+        let charger = charger("power_supply/usb-pd").unwrap();
+        assert_eq!(charger.name, "ucsi-source-psy-USBC000:001");
+        assert_eq!(charger.usb_type.as_deref(), Some("PD"));
+        assert_eq!(charger.power, Some(65_000_000));
+        assert_eq!(charger.watts(), Some(65));
+    }
+
+    #[test]
+    fn finds_a_mains_adapter_without_usb_type() {
+        let charger = charger("power_supply/mains").unwrap();
+        assert_eq!(charger.name, "AC");
+        assert_eq!(charger.usb_type, None);
+        assert_eq!(charger.watts(), None);
+    }
+
+    #[test]
+    fn finds_nothing_without_an_online_charger() {
+        assert!(charger("power_supply/unplugged").is_none());
+    }
+}
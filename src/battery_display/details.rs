@@ -0,0 +1,106 @@
+//! The details of each battery, to help decide when it needs to be replaced.
+
+use super::{sysfs::PowerSupply, thresholds::ChargeThresholds};
+
+/// The capacity of a battery, in µWh, or in µAh for the drivers that only give the charge.
+#[derive(Debug, Clone, Copy)]
+struct Capacity {
+    full: u64,
+    full_design: u64,
+    unit: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatteryDetails {
+    pub name: String,
+    technology: Option<String>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+    cycle_count: Option<u32>,
+    capacity: Option<Capacity>,
+    /// in µV
+    voltage: Option<u64>,
+    /// in µW
+    power: Option<u64>,
+    /// if the driver supports them
+    pub thresholds: Option<ChargeThresholds>,
+}
+
+impl BatteryDetails {
+    /// Reads the attributes next to `capacity` and `status`. They are all optional, as every
+    /// driver gives a different subset of them.
+    pub fn read(battery: &PowerSupply) -> Self {
+        let capacity = |prefix: &str, unit| {
+            Some(Capacity {
+                full: battery.read_optional(&format!("{}_full", prefix))?,
+                full_design: battery.read_optional(&format!("{}_full_design", prefix))?,
+                unit,
+            })
+        };
+        let voltage = battery.read_optional::<u64>("voltage_now");
+
+        Self {
+            name: battery.name(),
+            technology: battery.read_optional("technology"),
+            manufacturer: battery.read_optional("manufacturer"),
+            model: battery.read_optional("model_name"),
+            // some drivers report 0 when they don't know
+            cycle_count: battery
+                .read_optional("cycle_count")
+                .filter(|count| *count > 0),
+            capacity: capacity("energy", "Wh").or_else(|| capacity("charge", "Ah")),
+            voltage,
+            power: battery.read_optional::<u64>("power_now").or_else(|| {
+                battery
+                    .read_optional::<u64>("current_now")
+                    .zip(voltage)
+                    .map(|(current, voltage)| current * voltage / 1_000_000)
+            }),
+            thresholds: ChargeThresholds::read(battery),
+        }
+    }
+
+    /// the current full capacity relative to the design capacity, in percents
+    fn health(&self) -> Option<u64> {
+        let capacity = self.capacity?;
+        (capacity.full_design > 0).then(|| capacity.full * 100 / capacity.full_design)
+    }
+
+    /// the label and value of each known detail, for display
+    pub fn lines(&self) -> Vec<(&'static str, String)> {
+        let mut lines = Vec::new();
+        if let Some(technology) = &self.technology {
+            lines.push(("Technologie", technology.clone()));
+        }
+        if let Some(manufacturer) = &self.manufacturer {
+            lines.push(("Fabricant", manufacturer.clone()));
+        }
+        if let Some(model) = &self.model {
+            lines.push(("Modèle", model.clone()));
+        }
+        if let Some(cycle_count) = self.cycle_count {
+            lines.push(("Cycles", cycle_count.to_string()));
+        }
+        if let Some(capacity) = self.capacity {
+            lines.push((
+                "Capacité",
+                format!(
+                    "{:.1} / {:.1} {}",
+                    capacity.full as f64 / 1e6,
+                    capacity.full_design as f64 / 1e6,
+                    capacity.unit
+                ),
+            ));
+        }
+        if let Some(health) = self.health() {
+            lines.push(("Santé", format!("{}%", health)));
+        }
+        if let Some(voltage) = self.voltage {
+            lines.push(("Tension", format!("{:.2} V", voltage as f64 / 1e6)));
+        }
+        if let Some(power) = self.power {
+            lines.push(("Puissance", format!("{:.1} W", power as f64 / 1e6)));
+        }
+        lines
+    }
+}
@@ -0,0 +1,38 @@
+use std::{fmt::Display, path::PathBuf};
+
+#[derive(Debug)]
+pub enum BatteryError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// an attribute file without any content, which some drivers give while they update
+    EmptyAttribute {
+        path: PathBuf,
+    },
+    ParsingError {
+        path: PathBuf,
+        raw: String,
+    },
+    NoBattery {
+        root: PathBuf,
+    },
+    UPowerError(zbus::Error),
+}
+
+impl Display for BatteryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::EmptyAttribute { path } => write!(f, "{} is empty", path.display()),
+            Self::ParsingError { path, raw } => {
+                writeln!(f, "Unable to parse the content of {}", path.display())?;
+                write!(f, "Got '{}'", raw)
+            }
+            Self::NoBattery { root } => write!(f, "No battery in {}", root.display()),
+            Self::UPowerError(e) => write!(f, "Error communicating with UPower : {}", e),
+        }
+    }
+}
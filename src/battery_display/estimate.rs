@@ -0,0 +1,192 @@
+//! Estimation of the time left until the batteries are empty or full. The energies are in µWh and
+//! the powers in µW, or the charges in µAh and the currents in µA, which work the same way.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How far back the samples used to compute the rate go.
+const WINDOW: Duration = Duration::from_secs(60);
+/// Below this, the variation of energy is too coarse to give a meaningful rate.
+const MIN_ELAPSED: Duration = Duration::from_secs(10);
+
+struct Sample {
+    at: Instant,
+    /// in µWh
+    energy_now: u64,
+    /// in µW, when the driver reports it
+    power: Option<u64>,
+}
+
+/// Smooths the rate at which the batteries charge or discharge over the last minute, as the
+/// instantaneous power reported by the drivers jumps around a lot.
+#[derive(Default)]
+pub struct RateEstimator {
+    samples: VecDeque<Sample>,
+}
+
+impl RateEstimator {
+    pub fn push(&mut self, at: Instant, energy_now: u64, power: Option<u64>) {
+        self.samples.push_back(Sample {
+            at,
+            energy_now,
+            power,
+        });
+        while self
+            .samples
+            .front()
+            .is_some_and(|first| at.duration_since(first.at) > WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// forget the samples, e.g. when the charger is plugged in and the rate becomes meaningless
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// the average power going in or out of the batteries, in µW
+    pub fn rate(&self) -> Option<u64> {
+        let powers: Option<Vec<u64>> = self.samples.iter().map(|sample| sample.power).collect();
+        match powers {
+            Some(powers) if !powers.is_empty() => {
+                Some(powers.iter().sum::<u64>() / powers.len() as u64)
+            }
+            // without the power, we use the variation of the energy over the window
+            _ => {
+                let (first, last) = (self.samples.front()?, self.samples.back()?);
+                let elapsed = last.at.duration_since(first.at);
+                if elapsed < MIN_ELAPSED {
+                    return None;
+                }
+                let delta = first.energy_now.abs_diff(last.energy_now) as f64;
+                Some((delta * 3600.0 / elapsed.as_secs_f64()) as u64)
+            }
+        }
+    }
+}
+
+/// how long `energy` µWh last at a rate of `rate` µW
+fn time_for(energy: u64, rate: u64) -> Option<Duration> {
+    match rate {
+        0 => None,
+        rate => Some(Duration::from_secs_f64(
+            energy as f64 / rate as f64 * 3600.0,
+        )),
+    }
+}
+
+pub fn time_to_empty(energy_now: u64, rate: u64) -> Option<Duration> {
+    time_for(energy_now, rate)
+}
+
+pub fn time_to_full(energy_now: u64, energy_full: u64, rate: u64) -> Option<Duration> {
+    time_for(energy_full.saturating_sub(energy_now), rate)
+}
+
+/// formats a duration as `H:MM`
+pub fn format_remaining(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seconds since the start, `energy_now` and `power_now`, as read from a ThinkPad T480
+    /// discharging while compiling.
+    const DISCHARGING: [(u64, u64, u64); 6] = [
+        (0, 41_230_000, 9_870_000),
+        (30, 41_150_000, 12_410_000),
+        (60, 41_050_000, 8_020_000),
+        (90, 40_970_000, 10_300_000),
+        (120, 40_880_000, 11_760_000),
+        (150, 40_800_000, 9_140_000),
+    ];
+
+    fn estimator(samples: &[(u64, u64, Option<u64>)]) -> RateEstimator {
+        let start = Instant::now();
+        let mut estimator = RateEstimator::default();
+        for (seconds, energy_now, power) in samples {
+            estimator.push(start + Duration::from_secs(*seconds), *energy_now, *power);
+        }
+        estimator
+    }
+
+    fn recorded(count: usize) -> RateEstimator {
+        let samples = DISCHARGING[..count]
+            .iter()
+            .map(|(seconds, energy_now, power)| (*seconds, *energy_now, Some(*power)))
+            .collect::<Vec<_>>();
+        estimator(&samples)
+    }
+
+    #[test]
+    fn averages_the_power_over_the_window() {
+        assert_eq!(recorded(1).rate(), Some(9_870_000));
+        assert_eq!(recorded(3).rate(), Some(10_100_000));
+        // the first sample is more than a minute old by the fourth one
+        assert_eq!(recorded(4).rate(), Some(10_243_333));
+        assert_eq!(recorded(6).rate(), Some(10_400_000));
+    }
+
+    #[test]
+    fn uses_the_energy_without_the_power() {
+        let samples = DISCHARGING
+            .iter()
+            .map(|(seconds, energy_now, _)| (*seconds, *energy_now, None))
+            .collect::<Vec<_>>();
+        // 80 mWh in 30 s
+        assert_eq!(estimator(&samples[..2]).rate(), Some(9_600_000));
+        // 170 mWh in 60 s, over the last minute only
+        assert_eq!(estimator(&samples).rate(), Some(10_200_000));
+    }
+
+    #[test]
+    fn waits_for_sparse_samples() {
+        assert_eq!(estimator(&[]).rate(), None);
+        assert_eq!(estimator(&[(0, 41_230_000, None)]).rate(), None);
+        assert_eq!(
+            estimator(&[(0, 41_230_000, None), (5, 41_220_000, None)]).rate(),
+            None
+        );
+        // samples further apart than the window can't give a rate either
+        assert_eq!(
+            estimator(&[(0, 41_230_000, None), (90, 40_970_000, None)]).rate(),
+            None
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_energy_when_the_power_goes_missing() {
+        let estimator = estimator(&[(0, 41_230_000, Some(9_870_000)), (30, 41_150_000, None)]);
+        assert_eq!(estimator.rate(), Some(9_600_000));
+    }
+
+    #[test]
+    fn restarts_after_a_switch() {
+        let mut estimator = recorded(3);
+        estimator.clear();
+        assert_eq!(estimator.rate(), None);
+        estimator.push(Instant::now(), 40_000_000, Some(30_000_000));
+        assert_eq!(estimator.rate(), Some(30_000_000));
+    }
+
+    #[test]
+    fn computes_the_time_left() {
+        // 41.05 Wh at 10.1 W, then 8.95 Wh at 30 W
+        let rate = recorded(3).rate().unwrap();
+        let remaining = time_to_empty(41_050_000, rate).unwrap();
+        assert_eq!(format_remaining(remaining), "4:03");
+        let remaining = time_to_full(41_050_000, 50_000_000, 30_000_000).unwrap();
+        assert_eq!(format_remaining(remaining), "0:17");
+        assert_eq!(time_to_empty(41_050_000, 0), None);
+        assert_eq!(
+            time_to_full(50_000_000, 50_000_000, 30_000_000),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -1,16 +1,118 @@
-use std::fs::read_to_string;
+mod alert;
+mod charger;
+mod details;
+mod error;
+mod estimate;
+pub mod sysfs;
+mod thresholds;
+mod uevent;
+mod upower;
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use iced::{
-    color,
-    widget::{column, container, text, vertical_space, Container},
-    Element, Length, Padding, Subscription,
+    color, subscription,
+    widget::{
+        button, column, mouse_area, row, scrollable, text, vertical_space, Column, Container, Row,
+    },
+    Color, Command, Element, Length, Padding, Subscription,
 };
 
-const BATTERY_FOLDER: &str = "/sys/class/power_supply/BAT0";
+use crate::style::{self, ContainerStyle};
+use serde::Deserialize;
+
+use alert::Level;
+use charger::Charger;
+use details::BatteryDetails;
+pub use error::BatteryError;
+use estimate::RateEstimator;
+use sysfs::PowerSupply;
+use thresholds::{ChargeProfile, ChargeThresholds};
+use uevent::UeventSocket;
+use upower::UPower;
+
+/// Some firmwares don't send an event when the capacity changes, only when the status does, so
+/// we still read the batteries from time to time.
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How often to read the batteries when we can't listen to the uevents.
+const POLL_INTERVAL: Duration = Duration::from_millis(600);
+const BLINK_INTERVAL: Duration = Duration::from_millis(600);
+/// A battery that can't be read, e.g. while it is being swapped, is usually back within seconds.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where the battery information comes from.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// the files of the kernel, in `/sys/class/power_supply`
+    #[default]
+    Sysfs,
+    /// the UPower daemon, which also knows about Bluetooth peripherals and UPS units
+    Upower,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub backend: Backend,
+    /// below this charge, the widget turns orange and a notification is sent
+    pub warning_percent: u32,
+    /// below this charge, the widget blinks red and `critical_command` is run
+    pub critical_percent: u32,
+    /// how far above a threshold the charge must go before crossing it again counts
+    pub hysteresis_percent: u32,
+    /// shell command run when reaching the critical level while discharging, e.g.
+    /// `systemctl suspend`
+    pub critical_command: Option<String>,
+    /// the charge thresholds offered in the popup, for the batteries that support them
+    pub charge_profiles: Vec<ChargeProfile>,
+    /// the helper writing the charge thresholds, run as root by `pkexec` with the battery, start
+    /// and end thresholds
+    pub charge_threshold_helper: PathBuf,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            warning_percent: 20,
+            critical_percent: 10,
+            hysteresis_percent: 3,
+            critical_command: None,
+            charge_profiles: thresholds::default_profiles(),
+            charge_threshold_helper: thresholds::DEFAULT_HELPER.into(),
+        }
+    }
+}
 
 pub struct BatteryDisplay {
+    /// the directory containing the power supplies, usually `/sys/class/power_supply`
+    root: PathBuf,
     state: State,
     percent_charge: u32,
+    /// the remaining and full energy of the batteries, in `unit`
+    energy: Option<(u64, u64)>,
+    unit: Unit,
+    estimator: RateEstimator,
+    config: BatteryConfig,
+    level: Level,
+    /// flipped regularly, to make the widget blink at the critical level
+    blink: bool,
+    details: Vec<BatteryDetails>,
+    /// the charger plugged in, which is only known from sysfs
+    charger: Option<Charger>,
+    /// the batteries of mice, headsets, ..., which aren't counted with the batteries of the system
+    peripherals: Vec<Peripheral>,
+    /// whether the charge thresholds are being written
+    applying_profile: bool,
+    /// why the charge thresholds couldn't be written
+    profile_error: Option<String>,
+    /// why the last reading failed, in which case the widget shows an unknown state until the
+    /// next one succeeds
+    error: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -18,49 +120,313 @@ pub enum State {
     Charging,
     Discharging,
     Full,
+    /// plugged in but not charging, e.g. when held below a charge threshold
+    NotCharging,
     Empty,
     Other,
 }
 
-fn query_from_system(what: &str) -> std::io::Result<String> {
-    let path = format!("{}/{}", BATTERY_FOLDER, what);
+/// What the energy and the power of a battery are measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// µWh and µW
+    Energy,
+    /// µAh and µA, for the drivers only giving the charge without the voltage to convert it
+    Charge,
+}
 
-    Ok(read_to_string(path)?.lines().next().unwrap().into())
+/// What we read from a single battery, or the combination of all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryStatus {
+    state: State,
+    percent_charge: u32,
+    /// the remaining and full energy, in `unit`, if the driver gives enough information
+    energy: Option<(u64, u64)>,
+    /// the power going in or out of the battery, in `unit`
+    power: Option<u64>,
+    unit: Unit,
 }
 
-fn get_state_and_percent() -> Result<(State, u32), std::io::Error> {
-    let percent_val = query_from_system("capacity")?.parse().unwrap();
+/// A battery that doesn't power the system, such as the one of a wireless mouse.
+#[derive(Debug, Clone)]
+pub struct Peripheral {
+    name: String,
+    icon: char,
+    percent_charge: u32,
+}
 
-    let state = match query_from_system("status")?.as_str() {
+/// Everything read at each update.
+#[derive(Debug, Clone)]
+pub struct Reading {
+    status: BatteryStatus,
+    /// the details of each battery of the system, for the popup
+    details: Vec<BatteryDetails>,
+    charger: Option<Charger>,
+    peripherals: Vec<Peripheral>,
+}
+
+fn read_battery(battery: &PowerSupply) -> Result<BatteryStatus, BatteryError> {
+    let percent_charge = battery.read_parsed("capacity")?;
+
+    let state = match battery.read("status")?.as_str() {
         "Charging" => State::Charging,
         "Discharging" => State::Discharging,
-        "Not charging" => State::Full,
+        "Not charging" => State::NotCharging,
         "Full" => State::Full,
         "Empty" => State::Empty,
         _ => State::Other,
     };
 
-    Ok((state, percent_val))
+    // some drivers give the charge in µAh rather than the energy, which we convert using the
+    // design voltage (in µV) so that batteries of different voltages can be compared. Without
+    // it, we keep the charge and the current, which still give the time left.
+    let energy = battery
+        .read_optional::<u64>("energy_now")
+        .zip(battery.read_optional::<u64>("energy_full"));
+    let charge = battery
+        .read_optional::<u64>("charge_now")
+        .zip(battery.read_optional::<u64>("charge_full"));
+    let (energy, unit) = match (energy, charge) {
+        (Some(energy), _) => (Some(energy), Unit::Energy),
+        (None, Some((now, full))) => match battery.read_optional::<u64>("voltage_min_design") {
+            Some(voltage) => (
+                Some((now * voltage / 1_000_000, full * voltage / 1_000_000)),
+                Unit::Energy,
+            ),
+            None => (Some((now, full)), Unit::Charge),
+        },
+        (None, None) => (None, Unit::Energy),
+    };
+
+    // likewise, the power may have to be computed from the current (in µA) and voltage
+    let current = battery.read_optional::<u64>("current_now");
+    let power = match unit {
+        Unit::Energy => battery.read_optional::<u64>("power_now").or_else(|| {
+            current
+                .zip(battery.read_optional::<u64>("voltage_now"))
+                .map(|(current, voltage)| current * voltage / 1_000_000)
+        }),
+        Unit::Charge => current,
+    };
+
+    Ok(BatteryStatus {
+        state,
+        percent_charge,
+        energy,
+        power,
+        unit,
+    })
+}
+
+/// Combines the readings of several batteries (e.g. the internal and external ones of some
+/// ThinkPads) into a single state and charge, weighted by the energy of each battery.
+fn combine(readings: &[BatteryStatus]) -> BatteryStatus {
+    let state = if readings.iter().any(|r| matches!(r.state, State::Charging)) {
+        State::Charging
+    } else if readings
+        .iter()
+        .any(|r| matches!(r.state, State::Discharging))
+    {
+        State::Discharging
+    } else if readings.iter().all(|r| matches!(r.state, State::Full)) {
+        State::Full
+    } else if readings.iter().all(|r| matches!(r.state, State::Empty)) {
+        State::Empty
+    } else if readings
+        .iter()
+        .all(|r| matches!(r.state, State::Full | State::NotCharging))
+    {
+        State::NotCharging
+    } else {
+        State::Other
+    };
+
+    // energies and charges can't be added up
+    let unit = readings.first().map_or(Unit::Energy, |r| r.unit);
+    let same_unit = readings.iter().all(|r| r.unit == unit);
+    let energy = readings
+        .iter()
+        .map(|r| r.energy)
+        .collect::<Option<Vec<(u64, u64)>>>()
+        .filter(|_| same_unit)
+        .map(|energies| {
+            energies
+                .iter()
+                .fold((0, 0), |(now, full), (n, f)| (now + n, full + f))
+        })
+        .filter(|(_, full)| *full > 0);
+    let percent_charge = match energy {
+        Some((now, full)) => ((now * 100 + full / 2) / full).min(100) as u32,
+        // without the energies, the best we can do is to give the same weight to each battery
+        None => {
+            readings.iter().map(|r| r.percent_charge).sum::<u32>() / readings.len().max(1) as u32
+        }
+    };
+    let power = match same_unit {
+        true => readings.iter().map(|r| r.power).sum(),
+        false => None,
+    };
+
+    BatteryStatus {
+        state,
+        percent_charge,
+        energy,
+        power,
+        unit,
+    }
+}
+
+/// `supplies` are the power supplies found in `root`.
+fn get_status(root: &Path, supplies: &[PowerSupply]) -> Result<BatteryStatus, BatteryError> {
+    let readings = supplies
+        .iter()
+        .filter(|supply| supply.is_system_battery())
+        .map(read_battery)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if readings.is_empty() {
+        return Err(BatteryError::NoBattery { root: root.into() });
+    }
+
+    Ok(combine(&readings))
+}
+
+/// The details of each battery of the system, which are only available from sysfs.
+fn get_details(supplies: &[PowerSupply]) -> Vec<BatteryDetails> {
+    supplies
+        .iter()
+        .filter(|supply| supply.is_system_battery())
+        .map(BatteryDetails::read)
+        .collect()
+}
+
+/// The batteries with a `scope` of `Device`, which the kernel only knows about for some wireless
+/// peripherals (e.g. Logitech's).
+fn get_peripherals(supplies: &[PowerSupply]) -> Vec<Peripheral> {
+    supplies
+        .iter()
+        .filter(|supply| {
+            supply.kind == sysfs::PowerSupplyType::Battery && !supply.is_system_battery()
+        })
+        .filter_map(|supply| {
+            Some(Peripheral {
+                name: supply
+                    .read_optional("model_name")
+                    .unwrap_or_else(|| supply.name()),
+                icon: '󰁹',
+                percent_charge: supply.read_optional("capacity")?,
+            })
+        })
+        .collect()
+}
+
+/// Reads everything from sysfs, discovering the power supplies only once.
+fn read_sysfs(root: &Path) -> Result<Reading, BatteryError> {
+    let supplies = sysfs::discover(root)?;
+    Ok(Reading {
+        status: get_status(root, &supplies)?,
+        details: get_details(&supplies),
+        charger: charger::find(&supplies),
+        peripherals: get_peripherals(&supplies),
+    })
 }
 
-#[derive(Clone, Copy, Debug)]
+/// The parts of the reading that only sysfs has, for the UPower backend.
+fn read_sysfs_details(root: &Path) -> (Vec<BatteryDetails>, Option<Charger>) {
+    match sysfs::discover(root) {
+        Ok(supplies) => (get_details(&supplies), charger::find(&supplies)),
+        Err(_) => (Vec::new(), None),
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum BatteryMessage {
-    NewState(State, u32),
-    Error,
+    NewState(Reading),
+    Blink,
+    /// open or close the popup with the details of the batteries and the peripherals
+    TogglePopup,
+    /// write the thresholds of this charge profile
+    SetChargeProfile(usize),
+    ChargeProfileSet(Result<(), String>),
+    /// the batteries couldn't be read, but will be again at the next update
+    Error(String),
+}
+
+/// Where the battery subscription gets told that it's time to read the batteries again.
+enum UpdateSource {
+    Starting,
+    Uevents(UeventSocket),
+    Polling,
+}
+
+enum UPowerSource {
+    Starting,
+    Connected(Box<UPower>),
+    Failed,
 }
 
 impl BatteryDisplay {
-    pub fn new() -> Option<Self> {
-        match get_state_and_percent() {
-            Ok((state, percent_charge)) => Some(Self {
-                state,
-                percent_charge,
-            }),
-            Err(e) => {
-                log::error!("Unable to get battery information: {}", e);
-                None
+    /// `root` is the directory containing the power supplies, usually
+    /// [`sysfs::POWER_SUPPLY_ROOT`].
+    /// Returns `None` when the system has no battery at all.
+    pub fn new(root: PathBuf, config: &BatteryConfig) -> Option<Self> {
+        let unknown = BatteryStatus {
+            state: State::Other,
+            percent_charge: 0,
+            energy: None,
+            power: None,
+            unit: Unit::Energy,
+        };
+        let unknown = |details, charger| Reading {
+            status: unknown,
+            details,
+            charger,
+            peripherals: Vec::new(),
+        };
+        let (reading, error) = match config.backend {
+            Backend::Sysfs => match read_sysfs(&root) {
+                Ok(reading) => (reading, None),
+                Err(e @ BatteryError::NoBattery { .. }) => {
+                    log::error!("Unable to get battery information: {}", e);
+                    return None;
+                }
+                // the subscription will try again
+                Err(e) => {
+                    log::error!("Unable to get battery information: {}", e);
+                    (unknown(Vec::new(), None), Some(e.to_string()))
+                }
+            },
+            // UPower can only be reached asynchronously, so the first reading comes from the
+            // subscription
+            Backend::Upower => {
+                let (details, charger) = read_sysfs_details(&root);
+                (unknown(details, charger), None)
             }
-        }
+        };
+        let Reading {
+            status,
+            details,
+            charger,
+            peripherals,
+        } = reading;
+
+        Some(Self {
+            details,
+            charger,
+            root,
+            state: status.state,
+            percent_charge: status.percent_charge,
+            energy: status.energy,
+            unit: status.unit,
+            estimator: RateEstimator::default(),
+            config: config.clone(),
+            level: Level::Normal,
+            blink: false,
+            peripherals,
+            applying_profile: false,
+            profile_error: None,
+            error,
+        })
     }
 
     fn icon(&self) -> char {
@@ -89,51 +455,611 @@ impl BatteryDisplay {
             State::Discharging => '󰁹',
             State::Empty => '󰂎',
             State::Full => '󰁹',
+            State::NotCharging => '󰚥',
             State::Other => '?',
         }
     }
 
-    pub fn update(&mut self, message: BatteryMessage) {
-        if let BatteryMessage::NewState(state, percent_charge) = message {
-            self.state = state;
-            self.percent_charge = percent_charge;
+    pub fn update(&mut self, message: BatteryMessage) -> Command<BatteryMessage> {
+        match message {
+            BatteryMessage::Blink => self.blink = !self.blink,
+            BatteryMessage::NewState(reading) => self.new_state(reading),
+            BatteryMessage::SetChargeProfile(index) => {
+                return self.set_charge_profile(index);
+            }
+            BatteryMessage::ChargeProfileSet(result) => {
+                self.applying_profile = false;
+                self.profile_error = result.err();
+                if let Some(e) = &self.profile_error {
+                    log::error!("Unable to set the charge thresholds : {}", e);
+                }
+                // writing the thresholds doesn't always send a uevent
+                self.details = read_sysfs_details(&self.root).0;
+            }
+            BatteryMessage::Error(e) => self.error = Some(e),
+            BatteryMessage::TogglePopup => (),
+        }
+        Command::none()
+    }
+
+    /// the thresholds of the batteries that support them, which are the same for all of them
+    /// unless they have been set outside of the bar
+    fn charge_thresholds(&self) -> Option<ChargeThresholds> {
+        self.details.iter().find_map(|battery| battery.thresholds)
+    }
+
+    fn set_charge_profile(&mut self, index: usize) -> Command<BatteryMessage> {
+        let Some(profile) = self.config.charge_profiles.get(index) else {
+            return Command::none();
+        };
+        let batteries = self
+            .details
+            .iter()
+            .filter(|battery| battery.thresholds.is_some())
+            .map(|battery| battery.name.clone())
+            .collect();
+        self.applying_profile = true;
+        self.profile_error = None;
+        Command::perform(
+            thresholds::apply(
+                self.config.charge_threshold_helper.clone(),
+                batteries,
+                profile.clone(),
+            ),
+            BatteryMessage::ChargeProfileSet,
+        )
+    }
+
+    fn new_state(&mut self, reading: Reading) {
+        self.error = None;
+        let status = reading.status;
+        // the rate while charging says nothing about the rate while discharging
+        if std::mem::discriminant(&self.state) != std::mem::discriminant(&status.state)
+            || self.unit != status.unit
+        {
+            self.estimator.clear();
+        }
+        if let Some((energy_now, _)) = status.energy {
+            self.estimator
+                .push(Instant::now(), energy_now, status.power);
+        }
+        self.state = status.state;
+        self.percent_charge = status.percent_charge;
+        self.energy = status.energy;
+        self.unit = status.unit;
+        self.details = reading.details;
+        self.charger = reading.charger;
+        self.peripherals = reading.peripherals;
+
+        let level = self
+            .level
+            .next(self.percent_charge, self.state, &self.config);
+        if level > self.level {
+            alert::alert(level, self.percent_charge, &self.config);
+        }
+        self.level = level;
+    }
+
+    /// the estimated time until the batteries are empty or full, depending on the state
+    fn remaining_time(&self) -> Option<std::time::Duration> {
+        let (energy_now, energy_full) = self.energy?;
+        let rate = self.estimator.rate()?;
+        match self.state {
+            State::Discharging => estimate::time_to_empty(energy_now, rate),
+            State::Charging => estimate::time_to_full(energy_now, energy_full, rate),
+            _ => None,
         }
     }
 
     pub fn subscription(&self) -> Subscription<BatteryMessage> {
-        iced::time::every(std::time::Duration::from_millis(600)).map(
-            |_| match get_state_and_percent() {
-                Ok((state, percent_charge)) => BatteryMessage::NewState(state, percent_charge),
-                Err(e) => {
-                    log::error!("Unable to access battery information : {}", e);
-                    BatteryMessage::Error
+        let updates = match self.config.backend {
+            Backend::Sysfs => self.sysfs_subscription(),
+            Backend::Upower => self.upower_subscription(),
+        };
+
+        let blink = match self.level {
+            Level::Critical => iced::time::every(BLINK_INTERVAL).map(|_| BatteryMessage::Blink),
+            _ => Subscription::none(),
+        };
+
+        Subscription::batch([updates, blink])
+    }
+
+    /// Reads the batteries when the kernel tells us that a power supply changed, or every
+    /// `FALLBACK_POLL_INTERVAL` otherwise.
+    fn sysfs_subscription(&self) -> Subscription<BatteryMessage> {
+        struct BatteryUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<BatteryUpdates>(),
+            (self.root.clone(), UpdateSource::Starting, false),
+            |(root, source, failed)| async move {
+                // after an error, there may be no event to tell us that the batteries are back
+                let poll_interval = match failed {
+                    true => RETRY_INTERVAL,
+                    false => FALLBACK_POLL_INTERVAL,
+                };
+                let source = match source {
+                    UpdateSource::Starting => match UeventSocket::open() {
+                        Ok(socket) => UpdateSource::Uevents(socket),
+                        Err(e) => {
+                            log::error!(
+                                "Unable to listen to power supply events, polling instead : {}",
+                                e
+                            );
+                            UpdateSource::Polling
+                        }
+                    },
+                    UpdateSource::Uevents(socket) => {
+                        // a timeout just means it's time for the fallback poll
+                        let event =
+                            tokio::time::timeout(poll_interval, socket.next_power_supply_event())
+                                .await;
+                        match event {
+                            Ok(Err(e)) => {
+                                log::error!(
+                                    "Unable to receive power supply events, polling instead : {}",
+                                    e
+                                );
+                                UpdateSource::Polling
+                            }
+                            _ => UpdateSource::Uevents(socket),
+                        }
+                    }
+                    UpdateSource::Polling => {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                        UpdateSource::Polling
+                    }
+                };
+
+                let (message, failed) = match read_sysfs(&root) {
+                    Ok(reading) => (BatteryMessage::NewState(reading), false),
+                    Err(e) => {
+                        log::error!("Unable to access battery information : {}", e);
+                        (BatteryMessage::Error(e.to_string()), true)
+                    }
+                };
+                (message, (root, source, failed))
+            },
+        )
+    }
+
+    /// Reads the batteries from UPower each time it signals a change.
+    fn upower_subscription(&self) -> Subscription<BatteryMessage> {
+        struct UPowerUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<UPowerUpdates>(),
+            (self.root.clone(), UPowerSource::Starting),
+            |(root, source)| async move {
+                let upower = match source {
+                    UPowerSource::Starting => UPower::connect().await,
+                    UPowerSource::Connected(mut upower) => upower.changed().await.map(|()| *upower),
+                    UPowerSource::Failed => {
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        UPower::connect().await
+                    }
+                };
+                match upower.map_err(BatteryError::UPowerError) {
+                    Ok(upower) => {
+                        let (status, peripherals) = upower.reading();
+                        let (details, charger) = read_sysfs_details(&root);
+                        (
+                            BatteryMessage::NewState(Reading {
+                                status,
+                                details,
+                                charger,
+                                peripherals,
+                            }),
+                            (root, UPowerSource::Connected(Box::new(upower))),
+                        )
+                    }
+                    Err(e) => {
+                        log::error!("Unable to get battery information : {}", e);
+                        (
+                            BatteryMessage::Error(e.to_string()),
+                            (root, UPowerSource::Failed),
+                        )
+                    }
                 }
             },
         )
     }
 
-    pub fn view(&self) -> Element<BatteryMessage> {
-        Container::new(column![
+    pub fn view(&self) -> Element<'_, BatteryMessage> {
+        let remaining = match self.remaining_time() {
+            Some(remaining) => format!(" ({})", estimate::format_remaining(remaining)),
+            None => String::new(),
+        };
+
+        // the charge limit, when the batteries don't charge fully
+        let limit = match self.charge_thresholds() {
+            Some(thresholds) if thresholds.end < 100 => format!("/{}%", thresholds.end),
+            _ => String::new(),
+        };
+
+        // the power of the charger tells apart the ones too weak to charge the batteries
+        let charger = match &self.charger {
+            Some(charger) => match charger.watts() {
+                Some(watts) => format!(" 󰚥 {}W", watts),
+                None => " 󰚥".to_string(),
+            },
+            None => String::new(),
+        };
+
+        // the last reading is stale, the details of the error are in the popup
+        let label = match self.error {
+            Some(_) => "󰂑 ?".to_string(),
+            None => format!(
+                "{} {}%{}{}{}",
+                self.icon(),
+                self.percent_charge,
+                limit,
+                remaining,
+                charger
+            ),
+        };
+
+        let content = Container::new(column![
             vertical_space(Length::Fill),
-            text(format!("{} {}%", self.icon(), self.percent_charge)),
+            text(label),
             vertical_space(Length::Fill),
         ])
         .padding(Padding::from([0, 5, 0, 5]))
-        .style(iced::theme::Container::Custom(Box::new(ContainerStyle {})))
-        .into()
+        .style(
+            ContainerStyle {
+                background: self.background(),
+            }
+            .theme(),
+        );
+
+        mouse_area(content)
+            .on_press(BatteryMessage::TogglePopup)
+            .into()
+    }
+
+    /// the content of the popup opened by clicking on the battery display
+    pub fn view_popup(&self) -> Element<'_, BatteryMessage> {
+        let details = self.details.iter().map(|battery| {
+            let lines = battery.lines().into_iter().map(|(label, value)| {
+                row![
+                    text(label)
+                        .style(iced::Color::WHITE)
+                        .width(Length::Fixed(150.0)),
+                    text(value).style(iced::Color::WHITE)
+                ]
+                .into()
+            });
+            column![
+                text(&battery.name).style(iced::Color::WHITE),
+                Column::with_children(lines.collect::<Vec<Element<_>>>()).spacing(5)
+            ]
+            .spacing(5)
+            .into()
+        });
+
+        let peripherals = self
+            .peripherals
+            .iter()
+            .map(|peripheral| {
+                row![
+                    text(format!("{} {}", peripheral.icon, peripheral.name))
+                        .style(iced::Color::WHITE)
+                        .width(Length::Fill),
+                    text(format!("{}%", peripheral.percent_charge)).style(iced::Color::WHITE)
+                ]
+                .into()
+            })
+            .collect::<Vec<Element<_>>>();
+
+        let peripherals = if peripherals.is_empty() {
+            column![text("Aucun périphérique").style(iced::Color::WHITE)]
+        } else {
+            Column::with_children(peripherals).spacing(5)
+        };
+
+        let error = self
+            .error
+            .iter()
+            .map(|e| text(e).style(color!(0xcc241d)).into());
+
+        let charger = self.charger.iter().map(|charger| {
+            let mut description = charger.name.clone();
+            if let Some(usb_type) = &charger.usb_type {
+                description += &format!(" ({})", usb_type);
+            }
+            if let Some(watts) = charger.watts() {
+                description += &format!(", {} W", watts);
+            }
+            row![
+                text("Chargeur")
+                    .style(iced::Color::WHITE)
+                    .width(Length::Fixed(150.0)),
+                text(description).style(iced::Color::WHITE)
+            ]
+            .into()
+        });
+
+        let content = Column::with_children(
+            error
+                .chain(details)
+                .chain(charger)
+                .collect::<Vec<Element<_>>>(),
+        )
+        .push(self.view_charge_profiles())
+        .push(column![text("Périphériques").style(iced::Color::WHITE), peripherals].spacing(5))
+        .spacing(15);
+
+        style::popup(scrollable(content))
+    }
+
+    /// the menu to switch between the charge profiles
+    fn view_charge_profiles(&self) -> Element<'_, BatteryMessage> {
+        let title = text("Seuils de charge").style(iced::Color::WHITE);
+
+        let Some(current) = self.charge_thresholds() else {
+            return column![
+                title,
+                text("Non pris en charge par cette plateforme").style(color!(0x808080))
+            ]
+            .spacing(5)
+            .into();
+        };
+
+        let buttons = self
+            .config
+            .charge_profiles
+            .iter()
+            .enumerate()
+            .map(|(index, profile)| {
+                button(text(&profile.name).style(iced::Color::WHITE))
+                    .style(iced::theme::Button::custom(ProfileButtonStyle {
+                        selected: profile.matches(current),
+                    }))
+                    // no clicking again while the helper runs
+                    .on_press_maybe(
+                        (!self.applying_profile).then_some(BatteryMessage::SetChargeProfile(index)),
+                    )
+                    .into()
+            })
+            .collect::<Vec<Element<_>>>();
+
+        let mut content = column![title, Row::with_children(buttons).spacing(5)].spacing(5);
+        if let Some(e) = &self.profile_error {
+            content = content.push(text(e).style(color!(0xcc241d)));
+        }
+        content.into()
+    }
+
+    fn background(&self) -> Color {
+        match self.level {
+            Level::Normal => color!(0x282828),
+            Level::Warning => color!(0xd65d0e),
+            Level::Critical if self.blink => color!(0xcc241d),
+            Level::Critical => color!(0x282828),
+        }
     }
 }
 
-struct ContainerStyle;
+struct ProfileButtonStyle {
+    /// whether the batteries currently use this profile
+    selected: bool,
+}
 
-impl container::StyleSheet for ContainerStyle {
+impl button::StyleSheet for ProfileButtonStyle {
     type Style = iced::Theme;
 
-    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
-        container::Appearance {
-            text_color: Some(iced::Color::WHITE),
-            background: Some(iced::Background::Color(color!(0x282828))),
+    fn active(&self, _style: &Self::Style) -> button::Appearance {
+        button::Appearance {
+            background: Some(iced::Background::Color(match self.selected {
+                true => color!(0x458588),
+                false => color!(0x3c3836),
+            })),
             ..Default::default()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+
+    /// Writes a battery of the system, with the given attributes.
+    fn battery(tree: &FakeTree, name: &str, attributes: &[(&str, &str)]) {
+        tree.write(&format!("{}/type", name), "Battery\n");
+        for (attribute, value) in attributes {
+            tree.write(&format!("{}/{}", name, attribute), &format!("{}\n", value));
+        }
+    }
+
+    #[test]
+    fn combines_several_batteries() {
+        let tree = FakeTree::new();
+        tree.write("AC/type", "Mains\n").write("AC/online", "1\n");
+        battery(
+            &tree,
+            "BAT0",
+            &[
+                ("status", "Discharging"),
+                ("capacity", "80"),
+                ("energy_now", "40000000"),
+                ("energy_full", "50000000"),
+                ("power_now", "5000000"),
+            ],
+        );
+        battery(
+            &tree,
+            "BAT1",
+            &[
+                ("status", "Unknown"),
+                ("capacity", "20"),
+                ("energy_now", "2000000"),
+                ("energy_full", "10000000"),
+                ("power_now", "0"),
+            ],
+        );
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert!(matches!(status.state, State::Discharging));
+        assert_eq!(status.energy, Some((42_000_000, 60_000_000)));
+        // weighted by the energies, not 50%
+        assert_eq!(status.percent_charge, 70);
+        assert_eq!(status.power, Some(5_000_000));
+    }
+
+    #[test]
+    fn falls_back_to_the_charge_without_energy_files() {
+        let tree = FakeTree::new();
+        battery(
+            &tree,
+            "BAT0",
+            &[
+                ("status", "Charging"),
+                ("capacity", "50"),
+                ("charge_now", "2000000"),
+                ("charge_full", "4000000"),
+                ("voltage_min_design", "11000000"),
+            ],
+        );
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert!(matches!(status.state, State::Charging));
+        assert_eq!(status.energy, Some((22_000_000, 44_000_000)));
+    }
+
+    #[test]
+    fn keeps_the_charge_without_the_design_voltage() {
+        let tree = FakeTree::new();
+        battery(
+            &tree,
+            "BAT0",
+            &[
+                ("status", "Discharging"),
+                ("capacity", "50"),
+                ("charge_now", "2000000"),
+                ("charge_full", "4000000"),
+                ("current_now", "1000000"),
+                ("voltage_now", "11500000"),
+            ],
+        );
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert_eq!(status.unit, Unit::Charge);
+        assert_eq!(status.energy, Some((2_000_000, 4_000_000)));
+        // the current, not the power, so that the time left is still 2 hours
+        assert_eq!(status.power, Some(1_000_000));
+    }
+
+    #[test]
+    fn does_not_add_up_energies_and_charges() {
+        let tree = FakeTree::new();
+        battery(
+            &tree,
+            "BAT0",
+            &[
+                ("status", "Discharging"),
+                ("capacity", "80"),
+                ("energy_now", "40000000"),
+                ("energy_full", "50000000"),
+                ("power_now", "5000000"),
+            ],
+        );
+        battery(
+            &tree,
+            "BAT1",
+            &[
+                ("status", "Discharging"),
+                ("capacity", "40"),
+                ("charge_now", "2000000"),
+                ("charge_full", "5000000"),
+                ("current_now", "1000000"),
+            ],
+        );
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert_eq!(status.energy, None);
+        assert_eq!(status.power, None);
+        assert_eq!(status.percent_charge, 60);
+    }
+
+    #[test]
+    fn estimates_again_after_plugging_the_charger() {
+        let tree = FakeTree::new();
+        battery(
+            &tree,
+            "BAT0",
+            &[("status", "Discharging"), ("capacity", "80")],
+        );
+        let mut display =
+            BatteryDisplay::new(tree.path().into(), &BatteryConfig::default()).unwrap();
+        let reading = |state, power| {
+            BatteryMessage::NewState(Reading {
+                status: BatteryStatus {
+                    state,
+                    percent_charge: 80,
+                    energy: Some((40_000_000, 50_000_000)),
+                    power: Some(power),
+                    unit: Unit::Energy,
+                },
+                details: Vec::new(),
+                charger: None,
+                peripherals: Vec::new(),
+            })
+        };
+
+        let _ = display.update(reading(State::Discharging, 10_000_000));
+        assert_eq!(
+            display.remaining_time(),
+            Some(Duration::from_secs(4 * 3600))
+        );
+        // the rate while discharging doesn't count any more
+        let _ = display.update(reading(State::Charging, 20_000_000));
+        assert_eq!(display.remaining_time(), Some(Duration::from_secs(1800)));
+    }
+
+    #[test]
+    fn averages_the_capacities_without_any_energy() {
+        let tree = FakeTree::new();
+        battery(&tree, "BAT0", &[("status", "Full"), ("capacity", "100")]);
+        battery(&tree, "BAT1", &[("status", "Full"), ("capacity", "60")]);
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert!(matches!(status.state, State::Full));
+        assert_eq!(status.energy, None);
+        assert_eq!(status.percent_charge, 80);
+    }
+
+    #[test]
+    fn tells_a_battery_held_below_its_threshold_from_a_full_one() {
+        let tree = FakeTree::new();
+        battery(&tree, "BAT0", &[("status", "Full"), ("capacity", "100")]);
+        battery(
+            &tree,
+            "BAT1",
+            &[("status", "Not charging"), ("capacity", "80")],
+        );
+
+        let status = read_sysfs(tree.path()).unwrap().status;
+        assert!(matches!(status.state, State::NotCharging));
+    }
+
+    #[test]
+    fn needs_a_battery() {
+        let tree = FakeTree::new();
+        tree.write("AC/type", "Mains\n");
+        battery(&tree, "hidpp_battery_0", &[("scope", "Device")]);
+
+        let result = read_sysfs(tree.path());
+        assert!(matches!(result, Err(BatteryError::NoBattery { .. })));
+    }
+
+    #[test]
+    fn reports_invalid_batteries() {
+        let tree = FakeTree::new();
+        battery(&tree, "BAT0", &[("status", "Charging"), ("capacity", "")]);
+
+        let result = read_sysfs(tree.path());
+        assert!(matches!(result, Err(BatteryError::ParsingError { .. })));
+    }
+}
@@ -0,0 +1,171 @@
+//! Discovery of the power supplies the kernel exposes in `/sys/class/power_supply`. The root
+//! directory is always a parameter, so that a fake tree can be used instead.
+
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use super::BatteryError;
+
+pub const POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// The kind of a power supply, as given by its `type` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyType {
+    Battery,
+    Mains,
+    Usb,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct PowerSupply {
+    pub path: PathBuf,
+    pub kind: PowerSupplyType,
+}
+
+impl PowerSupply {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// reads the first line of one of the attributes of the power supply
+    pub fn read(&self, attribute: &str) -> Result<String, BatteryError> {
+        read_first_line(&self.path.join(attribute))
+    }
+
+    /// reads and parses an attribute, such as `capacity`
+    pub fn read_parsed<T: std::str::FromStr>(&self, attribute: &str) -> Result<T, BatteryError> {
+        let raw = self.read(attribute)?;
+        raw.trim().parse().map_err(|_| BatteryError::ParsingError {
+            path: self.path.join(attribute),
+            raw,
+        })
+    }
+
+    /// reads an attribute that not every driver provides, such as `energy_now`
+    pub fn read_optional<T: std::str::FromStr>(&self, attribute: &str) -> Option<T> {
+        self.read_parsed(attribute).ok()
+    }
+
+    /// Batteries of peripherals (mice, keyboards, ...) also show up in `power_supply`, but with
+    /// a `scope` of `Device`. They shouldn't be counted with the batteries of the system.
+    pub fn is_system_battery(&self) -> bool {
+        self.kind == PowerSupplyType::Battery
+            && self.read_optional::<String>("scope").as_deref() != Some("Device")
+    }
+}
+
+fn read_first_line(path: &Path) -> Result<String, BatteryError> {
+    let content = read_to_string(path).map_err(|error| BatteryError::IoError {
+        path: path.into(),
+        error,
+    })?;
+    match content.lines().next() {
+        Some(line) => Ok(line.into()),
+        None => Err(BatteryError::EmptyAttribute { path: path.into() }),
+    }
+}
+
+/// Lists the power supplies in `root`, sorted by name.
+pub fn discover(root: &Path) -> Result<Vec<PowerSupply>, BatteryError> {
+    let io_error = |error| BatteryError::IoError {
+        path: root.into(),
+        error,
+    };
+    let mut supplies = Vec::new();
+    for entry in std::fs::read_dir(root).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        let kind = match read_first_line(&path.join("type")) {
+            Ok(kind) => match kind.as_str() {
+                "Battery" => PowerSupplyType::Battery,
+                "Mains" => PowerSupplyType::Mains,
+                "USB" => PowerSupplyType::Usb,
+                _ => PowerSupplyType::Other,
+            },
+            // not a power supply, or one going away while we read it
+            Err(_) => continue,
+        };
+        supplies.push(PowerSupply { path, kind });
+    }
+    supplies.sort_by_key(|supply| supply.path.clone());
+    Ok(supplies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+
+    #[test]
+    fn discovers_the_power_supplies() {
+        let tree = FakeTree::new();
+        tree.write("BAT1/type", "Battery\n")
+            .write("BAT0/type", "Battery\n")
+            .write("AC/type", "Mains\n")
+            .write("ucsi-source-psy-USBC000:001/type", "USB\n")
+            .write("hidpp_battery_0/type", "Battery\n")
+            .write("hidpp_battery_0/scope", "Device\n")
+            // an empty type, as given by some drivers while they update
+            .write("BAT2/type", "")
+            .mkdir("not_a_supply");
+
+        let supplies = discover(tree.path()).unwrap();
+        let found = supplies
+            .iter()
+            .map(|supply| (supply.name(), supply.kind, supply.is_system_battery()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("AC".to_string(), PowerSupplyType::Mains, false),
+                ("BAT0".to_string(), PowerSupplyType::Battery, true),
+                ("BAT1".to_string(), PowerSupplyType::Battery, true),
+                (
+                    "hidpp_battery_0".to_string(),
+                    PowerSupplyType::Battery,
+                    false
+                ),
+                (
+                    "ucsi-source-psy-USBC000:001".to_string(),
+                    PowerSupplyType::Usb,
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_without_a_root() {
+        let tree = FakeTree::new();
+        let result = discover(&tree.path().join("missing"));
+        assert!(matches!(result, Err(BatteryError::IoError { .. })));
+    }
+
+    #[test]
+    fn reports_unreadable_attributes() {
+        let tree = FakeTree::new();
+        tree.write("BAT0/type", "Battery\n")
+            .write("BAT0/capacity", "full\n")
+            .write("BAT0/energy_now", "");
+        let battery = &discover(tree.path()).unwrap()[0];
+
+        assert!(matches!(
+            battery.read_parsed::<u32>("capacity"),
+            Err(BatteryError::ParsingError { .. })
+        ));
+        assert!(matches!(
+            battery.read("energy_now"),
+            Err(BatteryError::EmptyAttribute { .. })
+        ));
+        assert!(matches!(
+            battery.read("energy_full"),
+            Err(BatteryError::IoError { .. })
+        ));
+        assert_eq!(battery.read_optional::<u64>("energy_full"), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! Charge thresholds, which keep the batteries from charging to 100% to make them last longer.
+//! Writing them requires root, which is done by a helper run through `pkexec`, see
+//! `helpers/widgets-charge-threshold` and the polkit policy next to it.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use super::sysfs::PowerSupply;
+
+/// where `helpers/org.widgets.charge-threshold.policy` allows the helper to be run from
+pub const DEFAULT_HELPER: &str = "/usr/local/bin/widgets-charge-threshold";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChargeThresholds {
+    /// the charge below which the battery starts charging, which not every driver supports
+    pub start: Option<u32>,
+    /// the charge at which the battery stops charging
+    pub end: u32,
+}
+
+impl ChargeThresholds {
+    pub fn read(battery: &PowerSupply) -> Option<Self> {
+        Some(Self {
+            start: battery.read_optional("charge_control_start_threshold"),
+            end: battery.read_optional("charge_control_end_threshold")?,
+        })
+    }
+}
+
+/// A choice of thresholds offered in the popup of the battery display.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChargeProfile {
+    pub name: String,
+    /// left as is if not given
+    pub start: Option<u32>,
+    pub end: u32,
+}
+
+impl ChargeProfile {
+    /// whether the battery currently uses this profile
+    pub fn matches(&self, thresholds: ChargeThresholds) -> bool {
+        self.end == thresholds.end && (self.start.is_none() || self.start == thresholds.start)
+    }
+}
+
+pub fn default_profiles() -> Vec<ChargeProfile> {
+    vec![
+        ChargeProfile {
+            name: "Charge complète".into(),
+            start: None,
+            end: 100,
+        },
+        ChargeProfile {
+            name: "Conservation 80%".into(),
+            start: Some(75),
+            end: 80,
+        },
+    ]
+}
+
+/// Runs `pkexec helper BATTERY START END` for each battery, where `START` is `-` to leave the
+/// start threshold as is. The error is what the helper printed, to be shown to the user.
+pub async fn apply(
+    helper: PathBuf,
+    batteries: Vec<String>,
+    profile: ChargeProfile,
+) -> Result<(), String> {
+    let start = profile
+        .start
+        .map_or_else(|| "-".to_string(), |start| start.to_string());
+    for battery in batteries {
+        let output = tokio::process::Command::new("pkexec")
+            .arg(&helper)
+            .arg(&battery)
+            .arg(&start)
+            .arg(profile.end.to_string())
+            .output()
+            .await
+            .map_err(|e| format!("Impossible de lancer {} : {}", helper.display(), e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(match stderr.trim() {
+                "" => format!("{} a échoué ({})", helper.display(), output.status),
+                message => message.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! Kernel uevents for the power supplies, so that we only read sysfs when something changes.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use nix::sys::socket::{
+    bind, recv, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+use tokio::io::unix::AsyncFd;
+
+/// The multicast group on which the kernel itself sends its uevents (udev re-broadcasts them on
+/// another one, but it may not be running).
+const KERNEL_GROUP: u32 = 1;
+/// Uevents are small, but some drivers put every attribute of the power supply in them.
+const BUFFER_SIZE: usize = 8192;
+
+/// A netlink socket receiving the uevents of the kernel.
+pub struct UeventSocket {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl UeventSocket {
+    pub fn open() -> std::io::Result<Self> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Datagram,
+            SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkKObjectUEvent,
+        )?;
+        // SAFETY: the file descriptor was just created, and nothing else owns it
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        bind(fd.as_raw_fd(), &NetlinkAddr::new(0, KERNEL_GROUP))?;
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Waits for the next uevent about a power supply, e.g. a change of status or capacity, or
+    /// a charger being plugged in.
+    pub async fn next_power_supply_event(&self) -> std::io::Result<()> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let read = guard.try_io(|fd| {
+                Ok(recv(
+                    fd.get_ref().as_raw_fd(),
+                    &mut buffer,
+                    MsgFlags::empty(),
+                )?)
+            });
+            match read {
+                Ok(Ok(length)) if is_power_supply_event(&buffer[..length]) => return Ok(()),
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => return Err(e),
+                // spurious wakeup
+                Err(_would_block) => (),
+            }
+        }
+    }
+}
+
+/// A uevent is a header such as `change@/devices/.../power_supply/BAT0` followed by
+/// `KEY=value` lines, all separated by NUL bytes.
+fn is_power_supply_event(message: &[u8]) -> bool {
+    message
+        .split(|byte| *byte == 0)
+        .skip(1)
+        .any(|field| field == b"SUBSYSTEM=power_supply")
+}
@@ -0,0 +1,519 @@
+//! Reading the batteries from UPower on the system bus, which also knows about the batteries of
+//! peripherals such as Bluetooth mice and headsets, and about UPS units. The tests run it against
+//! a mock UPower service on a private `dbus-daemon`, and so can the bar by pointing
+//! `DBUS_SYSTEM_BUS_ADDRESS` to such a bus.
+
+use std::{collections::HashMap, time::Duration};
+
+use iced::futures::StreamExt;
+use zbus::{
+    dbus_proxy, zvariant::OwnedObjectPath, CacheProperties, Connection, MatchRule, Message,
+    MessageStream, MessageType,
+};
+
+use super::{BatteryStatus, Peripheral, State, Unit};
+
+const SERVICE: &str = "org.freedesktop.UPower";
+const PATH: &str = "/org/freedesktop/UPower";
+/// UPower changes several properties one after the other, which we read only once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+// the values of the `Type` property of the devices
+const TYPE_LINE_POWER: u32 = 1;
+const TYPE_UPS: u32 = 3;
+const TYPE_MOUSE: u32 = 5;
+const TYPE_KEYBOARD: u32 = 6;
+const TYPE_PHONE: u32 = 8;
+const TYPE_TABLET: u32 = 10;
+const TYPE_GAMING_INPUT: u32 = 12;
+const TYPE_HEADSET: u32 = 17;
+const TYPE_SPEAKERS: u32 = 18;
+const TYPE_HEADPHONES: u32 = 19;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    fn enumerate_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    /// the composite device that represents all the batteries of the system
+    fn get_display_device(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower"
+)]
+trait Device {
+    #[dbus_proxy(property, name = "Type")]
+    fn kind(&self) -> zbus::Result<u32>;
+
+    #[dbus_proxy(property)]
+    fn model(&self) -> zbus::Result<String>;
+
+    /// whether the device powers the system, as opposed to a peripheral
+    #[dbus_proxy(property)]
+    fn power_supply(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    #[dbus_proxy(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+
+    /// in Wh
+    #[dbus_proxy(property)]
+    fn energy(&self) -> zbus::Result<f64>;
+
+    /// in Wh
+    #[dbus_proxy(property)]
+    fn energy_full(&self) -> zbus::Result<f64>;
+
+    /// in W
+    #[dbus_proxy(property)]
+    fn energy_rate(&self) -> zbus::Result<f64>;
+}
+
+/// A device that changed, appeared or went away, according to the signals of UPower.
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Changed(OwnedObjectPath),
+    Added(OwnedObjectPath),
+    Removed(OwnedObjectPath),
+}
+
+impl Change {
+    fn from_message(message: &Message) -> Option<Self> {
+        let path = OwnedObjectPath::from(message.path()?);
+        match message.member()?.as_str() {
+            "PropertiesChanged" => Some(Self::Changed(path)),
+            "DeviceAdded" => message.body().ok().map(Self::Added),
+            "DeviceRemoved" => message.body().ok().map(Self::Removed),
+            _ => None,
+        }
+    }
+}
+
+/// The devices known to UPower, read once when connecting, and then only when they change.
+pub struct UPower {
+    connection: Connection,
+    /// every signal sent by UPower: `PropertiesChanged` on the devices, `DeviceAdded`, ...
+    signals: MessageStream,
+    /// the composite device that represents all the batteries of the system
+    display_device: OwnedObjectPath,
+    status: BatteryStatus,
+    /// the other devices, with what we show of them when they are peripherals
+    devices: HashMap<OwnedObjectPath, Option<Peripheral>>,
+}
+
+impl UPower {
+    pub async fn connect() -> zbus::Result<Self> {
+        Self::new(Connection::system().await?).await
+    }
+
+    async fn new(connection: Connection) -> zbus::Result<Self> {
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender(SERVICE)?
+            // zbus can't tell whether a signal comes from a well-known name, so the signals of
+            // other services would wake us up too if the connection received them
+            .path_namespace(PATH)?
+            .build();
+        let signals = MessageStream::for_match_rule(rule, &connection, None).await?;
+        let proxy = UPowerProxy::new(&connection).await?;
+        let display_device = proxy.get_display_device().await?;
+        let mut upower = Self {
+            status: read_status(&device(&connection, &display_device).await?).await?,
+            connection,
+            signals,
+            display_device,
+            devices: HashMap::new(),
+        };
+        for path in proxy.enumerate_devices().await? {
+            upower.read_device(path).await;
+        }
+        Ok(upower)
+    }
+
+    /// Waits until UPower says that something changed, and reads the devices that did.
+    pub async fn changed(&mut self) -> zbus::Result<()> {
+        let mut changes = Vec::new();
+        let mut message = match self.signals.next().await {
+            Some(message) => message?,
+            None => return Err(zbus::Error::Failure("the bus connection was closed".into())),
+        };
+        loop {
+            if let Some(change) = Change::from_message(&message) {
+                if !changes.contains(&change) {
+                    changes.push(change);
+                }
+            }
+            match tokio::time::timeout(DEBOUNCE, self.signals.next()).await {
+                Ok(Some(next)) => message = next?,
+                _ => break,
+            }
+        }
+
+        for change in changes {
+            match change {
+                Change::Changed(path) if path == self.display_device => {
+                    let display = device(&self.connection, &self.display_device).await?;
+                    self.status = read_status(&display).await?;
+                }
+                Change::Changed(path) if self.devices.contains_key(&path) => {
+                    self.read_device(path).await
+                }
+                // e.g. the `OnBattery` property of UPower itself
+                Change::Changed(_) => (),
+                Change::Added(path) => self.read_device(path).await,
+                Change::Removed(path) => {
+                    self.devices.remove(&path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a device other than the display device. The ones that can't be read, e.g. because
+    /// they went away in the meantime, are forgotten.
+    async fn read_device(&mut self, path: OwnedObjectPath) {
+        match read_peripheral(&self.connection, &path).await {
+            Ok(peripheral) => {
+                self.devices.insert(path, peripheral);
+            }
+            Err(e) => {
+                log::debug!("Skipping the UPower device {} : {}", path.as_str(), e);
+                self.devices.remove(&path);
+            }
+        }
+    }
+
+    /// the combined status of the batteries of the system, and the peripherals
+    pub fn reading(&self) -> (BatteryStatus, Vec<Peripheral>) {
+        let mut peripherals = self.devices.values().flatten().cloned().collect::<Vec<_>>();
+        peripherals.sort_by(|a, b| a.name.cmp(&b.name));
+        (self.status, peripherals)
+    }
+}
+
+async fn device(
+    connection: &Connection,
+    path: &OwnedObjectPath,
+) -> zbus::Result<DeviceProxy<'static>> {
+    // the properties are read once per change, there is no point in caching them
+    DeviceProxy::builder(connection)
+        .path(path.clone().into_inner())?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+}
+
+async fn read_status(display: &DeviceProxy<'_>) -> zbus::Result<BatteryStatus> {
+    if !display.is_present().await? {
+        return Err(zbus::Error::Failure(
+            "UPower doesn't know any battery".into(),
+        ));
+    }
+
+    let state = match display.state().await? {
+        1 => State::Charging,
+        2 => State::Discharging,
+        3 => State::Empty,
+        4 => State::Full,
+        // pending charge, i.e. plugged in but not charging
+        5 => State::NotCharging,
+        _ => State::Other,
+    };
+    let (energy, energy_full) = (display.energy().await?, display.energy_full().await?);
+    let energy_rate = display.energy_rate().await?;
+    Ok(BatteryStatus {
+        state,
+        percent_charge: display.percentage().await?.round() as u32,
+        energy: (energy_full > 0.0).then_some(((energy * 1e6) as u64, (energy_full * 1e6) as u64)),
+        power: (energy_rate > 0.0).then_some((energy_rate * 1e6) as u64),
+        unit: Unit::Energy,
+    })
+}
+
+/// Reads a device, which is only shown when it is a peripheral rather than a battery of the
+/// system or a charger.
+async fn read_peripheral(
+    connection: &Connection,
+    path: &OwnedObjectPath,
+) -> zbus::Result<Option<Peripheral>> {
+    let device = device(connection, path).await?;
+    let kind = device.kind().await?;
+    if kind == TYPE_LINE_POWER || (device.power_supply().await? && kind != TYPE_UPS) {
+        return Ok(None);
+    }
+    Ok(Some(Peripheral {
+        name: device.model().await?,
+        icon: icon(kind),
+        percent_charge: device.percentage().await?.round() as u32,
+    }))
+}
+
+fn icon(kind: u32) -> char {
+    match kind {
+        TYPE_UPS => '󰚥',
+        TYPE_MOUSE => '󰍽',
+        TYPE_KEYBOARD => '󰌌',
+        TYPE_PHONE => '󰏲',
+        TYPE_TABLET => '󰓶',
+        TYPE_GAMING_INPUT => '󰊴',
+        TYPE_HEADSET => '󰋎',
+        TYPE_SPEAKERS => '󰓃',
+        TYPE_HEADPHONES => '󰋋',
+        _ => '󰁹',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use zbus::{dbus_interface, zvariant::ObjectPath, ConnectionBuilder, SignalContext};
+
+    use super::*;
+    use crate::testing::{block_on, PrivateBus};
+
+    const DEVICES: &str = "/org/freedesktop/UPower/devices";
+
+    struct MockUPower;
+
+    #[dbus_interface(name = "org.freedesktop.UPower")]
+    impl MockUPower {
+        fn enumerate_devices(&self) -> Vec<OwnedObjectPath> {
+            ["battery_BAT0", "line_power_AC", "mouse_0"]
+                .iter()
+                .map(|name| {
+                    ObjectPath::try_from(format!("{}/{}", DEVICES, name))
+                        .unwrap()
+                        .into()
+                })
+                .collect()
+        }
+
+        fn get_display_device(&self) -> OwnedObjectPath {
+            ObjectPath::try_from(format!("{}/DisplayDevice", DEVICES))
+                .unwrap()
+                .into()
+        }
+
+        #[dbus_interface(signal)]
+        async fn device_added(ctxt: &SignalContext<'_>, device: ObjectPath<'_>)
+            -> zbus::Result<()>;
+    }
+
+    struct MockDevice {
+        kind: u32,
+        model: &'static str,
+        power_supply: bool,
+        percentage: f64,
+        /// how many times the percentage was read
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl MockDevice {
+        fn new(kind: u32, model: &'static str, power_supply: bool, percentage: f64) -> Self {
+            Self {
+                kind,
+                model,
+                power_supply,
+                percentage,
+                reads: Arc::default(),
+            }
+        }
+    }
+
+    #[dbus_interface(name = "org.freedesktop.UPower.Device")]
+    impl MockDevice {
+        #[dbus_interface(property, name = "Type")]
+        fn kind(&self) -> u32 {
+            self.kind
+        }
+
+        #[dbus_interface(property)]
+        fn model(&self) -> String {
+            self.model.into()
+        }
+
+        #[dbus_interface(property)]
+        fn power_supply(&self) -> bool {
+            self.power_supply
+        }
+
+        #[dbus_interface(property)]
+        fn is_present(&self) -> bool {
+            true
+        }
+
+        #[dbus_interface(property)]
+        fn state(&self) -> u32 {
+            2
+        }
+
+        #[dbus_interface(property)]
+        fn percentage(&self) -> f64 {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.percentage
+        }
+
+        #[dbus_interface(property)]
+        fn energy(&self) -> f64 {
+            self.percentage / 2.0
+        }
+
+        #[dbus_interface(property)]
+        fn energy_full(&self) -> f64 {
+            50.0
+        }
+
+        #[dbus_interface(property)]
+        fn energy_rate(&self) -> f64 {
+            10.0
+        }
+    }
+
+    fn names(peripherals: &[Peripheral]) -> Vec<(&str, u32)> {
+        peripherals
+            .iter()
+            .map(|peripheral| (peripheral.name.as_str(), peripheral.percent_charge))
+            .collect()
+    }
+
+    #[test]
+    fn reads_only_the_devices_that_change() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        block_on(async {
+            let display = MockDevice::new(2, "", true, 80.0);
+            let display_reads = display.reads.clone();
+            let service = ConnectionBuilder::address(bus.address())
+                .unwrap()
+                .name(SERVICE)
+                .unwrap()
+                .serve_at(PATH, MockUPower)
+                .unwrap()
+                .serve_at(format!("{}/DisplayDevice", DEVICES), display)
+                .unwrap()
+                .serve_at(
+                    format!("{}/battery_BAT0", DEVICES),
+                    MockDevice::new(2, "5B10W13930", true, 80.0),
+                )
+                .unwrap()
+                .serve_at(
+                    format!("{}/line_power_AC", DEVICES),
+                    MockDevice::new(TYPE_LINE_POWER, "", true, 0.0),
+                )
+                .unwrap()
+                .serve_at(
+                    format!("{}/mouse_0", DEVICES),
+                    MockDevice::new(TYPE_MOUSE, "MX Master 3", false, 50.0),
+                )
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+
+            let connection = bus.connect().await;
+            // another user of the connection, which listens to the properties of every service
+            let _properties = MessageStream::for_match_rule(
+                MatchRule::builder()
+                    .msg_type(MessageType::Signal)
+                    .interface("org.freedesktop.DBus.Properties")
+                    .unwrap()
+                    .build(),
+                &connection,
+                None,
+            )
+            .await
+            .unwrap();
+            let mut upower = UPower::new(connection).await.unwrap();
+            let (status, peripherals) = upower.reading();
+            assert_eq!(status.percent_charge, 80);
+            assert_eq!(status.energy, Some((40_000_000, 50_000_000)));
+            assert_eq!(names(&peripherals), [("MX Master 3", 50)]);
+            let reads = display_reads.load(Ordering::Relaxed);
+
+            // the mouse discharges
+            let mouse = service
+                .object_server()
+                .interface::<_, MockDevice>(format!("{}/mouse_0", DEVICES))
+                .await
+                .unwrap();
+            mouse.get_mut().await.percentage = 45.0;
+            mouse
+                .get()
+                .await
+                .percentage_changed(mouse.signal_context())
+                .await
+                .unwrap();
+            upower.changed().await.unwrap();
+            assert_eq!(names(&upower.reading().1), [("MX Master 3", 45)]);
+            assert_eq!(display_reads.load(Ordering::Relaxed), reads);
+
+            // a headset is connected
+            let headset = format!("{}/headset_0", DEVICES);
+            service
+                .object_server()
+                .at(
+                    headset.as_str(),
+                    MockDevice::new(TYPE_HEADSET, "WH-1000XM4", false, 90.0),
+                )
+                .await
+                .unwrap();
+            let context = SignalContext::new(&service, PATH).unwrap();
+            MockUPower::device_added(&context, ObjectPath::try_from(headset.as_str()).unwrap())
+                .await
+                .unwrap();
+            upower.changed().await.unwrap();
+            assert_eq!(
+                names(&upower.reading().1),
+                [("MX Master 3", 45), ("WH-1000XM4", 90)]
+            );
+            assert_eq!(display_reads.load(Ordering::Relaxed), reads);
+
+            // the batteries of the system discharge
+            let display = service
+                .object_server()
+                .interface::<_, MockDevice>(format!("{}/DisplayDevice", DEVICES))
+                .await
+                .unwrap();
+            display.get_mut().await.percentage = 79.0;
+            display
+                .get()
+                .await
+                .percentage_changed(display.signal_context())
+                .await
+                .unwrap();
+            upower.changed().await.unwrap();
+            assert_eq!(upower.reading().0.percent_charge, 79);
+
+            // another service changes its properties
+            let other = ConnectionBuilder::address(bus.address())
+                .unwrap()
+                .serve_at("/org/example/Other", MockDevice::new(2, "", true, 10.0))
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let context = SignalContext::new(&other, "/org/example/Other").unwrap();
+            MockDevice::new(2, "", true, 10.0)
+                .percentage_changed(&context)
+                .await
+                .unwrap();
+            let woken = tokio::time::timeout(DEBOUNCE * 5, upower.changed()).await;
+            assert!(woken.is_err());
+        });
+    }
+}
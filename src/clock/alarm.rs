@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeDelta, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_state, save_state};
+
+const STATE_FILE: &str = "alarms.json";
+/// Alarms missed by more than this while the bar wasn't running are skipped rather than rung.
+const GRACE_PERIOD: TimeDelta = TimeDelta::hours(1);
+
+/// An alarm, as written in the config file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlarmConfig {
+    /// time of day, e.g. "07:30"
+    pub time: String,
+    /// the days the alarm rings, e.g. ["Mon", "Tue"]. Every day if empty.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub label: String,
+    /// shell command to run when the alarm rings, in addition to the notification
+    pub command: Option<String>,
+}
+
+struct Alarm {
+    time: NaiveTime,
+    days: Vec<Weekday>,
+    label: String,
+    command: Option<String>,
+}
+
+impl Alarm {
+    /// identifies the alarm in the state file. Unlike the index in the config, it doesn't change
+    /// when other alarms are added or removed.
+    fn key(&self) -> String {
+        format!("{} {}", self.time.format("%H:%M"), self.label)
+    }
+
+    fn rings_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// all the instants at which the alarm rings in the week around `now`
+    fn occurrences(&self, now: DateTime<Local>) -> impl Iterator<Item = DateTime<Local>> + '_ {
+        let today = now.date_naive();
+        (-7..=7)
+            .filter_map(move |offset| today.checked_add_signed(TimeDelta::days(offset)))
+            .filter(|date| self.rings_on(date.weekday()))
+            .filter_map(|date| {
+                Local
+                    .from_local_datetime(&date.and_time(self.time))
+                    .earliest()
+            })
+    }
+
+    fn last_occurrence(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.occurrences(now).filter(|date| *date <= now).last()
+    }
+
+    fn next_occurrence(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.occurrences(now).find(|date| *date > now)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snoozed {
+    pub label: String,
+    pub until: DateTime<Local>,
+}
+
+/// What needs to survive restarts, saved to `$XDG_STATE_HOME/widgets/alarms.json`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct AlarmState {
+    /// the last occurrence of each alarm that has been rung, by key
+    handled: HashMap<String, DateTime<Local>>,
+    /// the labels of the alarms currently ringing
+    ringing: Vec<String>,
+    snoozed: Vec<Snoozed>,
+}
+
+pub struct Alarms {
+    alarms: Vec<Alarm>,
+    snooze: TimeDelta,
+    state: AlarmState,
+}
+
+impl Alarms {
+    pub fn new(configs: &[AlarmConfig], snooze_minutes: i64, now: DateTime<Local>) -> Self {
+        let alarms: Vec<Alarm> = configs
+            .iter()
+            .filter_map(
+                |config| match NaiveTime::parse_from_str(&config.time, "%H:%M") {
+                    Ok(time) => Some(Alarm {
+                        time,
+                        days: config.days.clone(),
+                        label: config.label.clone(),
+                        command: config.command.clone(),
+                    }),
+                    Err(e) => {
+                        log::error!("Ignoring alarm with invalid time '{}' : {}", config.time, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        let mut state: AlarmState = load_state(STATE_FILE).unwrap_or_default();
+        // a new alarm shouldn't ring for an occurrence that happened before it was configured
+        for alarm in &alarms {
+            state.handled.entry(alarm.key()).or_insert(now);
+        }
+
+        Self {
+            alarms,
+            snooze: TimeDelta::minutes(snooze_minutes),
+            state,
+        }
+    }
+
+    pub fn ringing(&self) -> &[String] {
+        &self.state.ringing
+    }
+
+    /// the label and time of the next alarms to ring, including snoozed ones, in order
+    pub fn pending(&self, now: DateTime<Local>) -> Vec<(DateTime<Local>, &str)> {
+        let mut pending: Vec<_> = self
+            .alarms
+            .iter()
+            .filter_map(|alarm| Some((alarm.next_occurrence(now)?, alarm.label.as_str())))
+            .chain(
+                self.state
+                    .snoozed
+                    .iter()
+                    .map(|snoozed| (snoozed.until, snoozed.label.as_str())),
+            )
+            .collect();
+        pending.sort_by_key(|(date, _)| *date);
+        pending
+    }
+
+    /// the earliest instant at which an alarm rings
+    pub fn next_deadline(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.pending(now).first().map(|(date, _)| *date)
+    }
+
+    /// starts ringing the alarms whose time has come
+    pub fn check(&mut self, now: DateTime<Local>) {
+        let mut changed = false;
+
+        for alarm in &self.alarms {
+            let Some(occurrence) = alarm.last_occurrence(now) else {
+                continue;
+            };
+            let handled = self.state.handled.get(&alarm.key()).copied();
+            if handled.is_some_and(|handled| handled >= occurrence) {
+                continue;
+            }
+            self.state.handled.insert(alarm.key(), occurrence);
+            changed = true;
+
+            if now - occurrence > GRACE_PERIOD {
+                log::info!("Skipping alarm '{}', missed at {}", alarm.label, occurrence);
+                continue;
+            }
+            crate::notification::send("Alarme", &alarm.label);
+            if let Some(command) = &alarm.command {
+                crate::notification::run_command(command);
+            }
+            self.state.ringing.push(alarm.label.clone());
+        }
+
+        let (done, snoozed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.state.snoozed)
+            .into_iter()
+            .partition(|snoozed| snoozed.until <= now);
+        self.state.snoozed = snoozed;
+        for snoozed in done {
+            crate::notification::send("Alarme", &snoozed.label);
+            self.state.ringing.push(snoozed.label);
+            changed = true;
+        }
+
+        if changed {
+            self.save();
+        }
+    }
+
+    /// stops the ringing alarms, and rings them again in a few minutes
+    pub fn snooze(&mut self, now: DateTime<Local>) {
+        let until = now + self.snooze;
+        for label in std::mem::take(&mut self.state.ringing) {
+            self.state.snoozed.push(Snoozed { label, until });
+        }
+        self.save();
+    }
+
+    pub fn dismiss(&mut self) {
+        self.state.ringing.clear();
+        self.save();
+    }
+
+    fn save(&self) {
+        save_state(STATE_FILE, &self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+
+    /// 2024-01-10 is a Wednesday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn alarm(time: &str, days: &[Weekday]) -> AlarmConfig {
+        AlarmConfig {
+            time: time.into(),
+            days: days.into(),
+            label: format!("Réveil {}", time),
+            command: None,
+        }
+    }
+
+    #[test]
+    fn finds_the_next_occurrence_on_the_configured_days() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+        let alarms = Alarms::new(
+            &[alarm("07:30", &[Weekday::Mon, Weekday::Wed])],
+            9,
+            at(8, 0, 0),
+        );
+        assert_eq!(alarms.next_deadline(at(9, 8, 0)), Some(at(10, 7, 30)));
+        assert_eq!(alarms.next_deadline(at(10, 7, 29)), Some(at(10, 7, 30)));
+        assert_eq!(alarms.next_deadline(at(10, 7, 30)), Some(at(15, 7, 30)));
+        assert_eq!(alarms.next_deadline(at(13, 12, 0)), Some(at(15, 7, 30)));
+    }
+
+    #[test]
+    fn snoozes_past_midnight() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+        let mut alarms = Alarms::new(&[alarm("23:55", &[])], 9, at(10, 12, 0));
+        alarms.check(at(10, 23, 54));
+        assert!(alarms.ringing().is_empty());
+        alarms.check(at(10, 23, 55));
+        assert_eq!(alarms.ringing(), ["Réveil 23:55"]);
+
+        alarms.snooze(at(10, 23, 57));
+        assert!(alarms.ringing().is_empty());
+        assert_eq!(alarms.next_deadline(at(10, 23, 57)), Some(at(11, 0, 6)));
+        alarms.check(at(11, 0, 5));
+        assert!(alarms.ringing().is_empty());
+        alarms.check(at(11, 0, 6));
+        assert_eq!(alarms.ringing(), ["Réveil 23:55"]);
+    }
+
+    #[test]
+    fn keeps_a_dismissed_alarm_quiet_after_a_restart() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+        let configs = [alarm("07:00", &[])];
+        let mut alarms = Alarms::new(&configs, 9, at(10, 6, 0));
+        alarms.check(at(10, 7, 0));
+        assert_eq!(alarms.ringing(), ["Réveil 07:00"]);
+        alarms.dismiss();
+        drop(alarms);
+
+        let mut alarms = Alarms::new(&configs, 9, at(10, 7, 10));
+        alarms.check(at(10, 7, 10));
+        assert!(alarms.ringing().is_empty());
+        assert_eq!(alarms.next_deadline(at(10, 7, 10)), Some(at(11, 7, 0)));
+        alarms.check(at(11, 7, 0));
+        assert_eq!(alarms.ringing(), ["Réveil 07:00"]);
+    }
+
+    #[test]
+    fn does_not_ring_an_alarm_added_after_its_time() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+        let mut alarms = Alarms::new(&[alarm("07:00", &[])], 9, at(10, 7, 30));
+        alarms.check(at(10, 7, 30));
+        assert!(alarms.ringing().is_empty());
+    }
+}
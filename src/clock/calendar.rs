@@ -0,0 +1,666 @@
+//! Reads the events from local iCalendar files, as synced by tools like vdirsyncer. We only need
+//! a few properties of `VEVENT`s, so the parsing is done by hand, and the expansion of recurring
+//! events is left to the `rrule` crate.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use rrule::RRuleSet;
+
+/// How often the calendar files are read again, to pick up the changes made by the syncing tools.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+/// Recurring events can have an infinite number of occurrences, so we only compute them on a
+/// window around now.
+const LOOKAHEAD: TimeDelta = TimeDelta::days(2);
+/// Safety net for recurring events with a very high frequency.
+const MAX_OCCURRENCES: u16 = 500;
+/// Outlook and Exchange use Windows time zone names in their TZIDs, which chrono-tz doesn't know.
+/// These are the most common ones, the others fall back to local time.
+const WINDOWS_TIME_ZONES: [(&str, Tz); 16] = [
+    ("UTC", Tz::UTC),
+    ("GMT Standard Time", Tz::Europe__London),
+    ("Romance Standard Time", Tz::Europe__Paris),
+    ("W. Europe Standard Time", Tz::Europe__Berlin),
+    ("Central Europe Standard Time", Tz::Europe__Budapest),
+    ("Central European Standard Time", Tz::Europe__Warsaw),
+    ("E. Europe Standard Time", Tz::Europe__Chisinau),
+    ("FLE Standard Time", Tz::Europe__Kiev),
+    ("Russian Standard Time", Tz::Europe__Moscow),
+    ("Eastern Standard Time", Tz::America__New_York),
+    ("Central Standard Time", Tz::America__Chicago),
+    ("Mountain Standard Time", Tz::America__Denver),
+    ("Pacific Standard Time", Tz::America__Los_Angeles),
+    ("India Standard Time", Tz::Asia__Kolkata),
+    ("China Standard Time", Tz::Asia__Shanghai),
+    ("Tokyo Standard Time", Tz::Asia__Tokyo),
+];
+
+/// One occurrence of an event.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub summary: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub all_day: bool,
+}
+
+#[derive(Debug)]
+pub enum CalendarError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    InvalidDate {
+        path: PathBuf,
+        property: String,
+    },
+
+    RecurrenceError {
+        path: PathBuf,
+        rule: String,
+        error: Box<rrule::RRuleError>,
+    },
+}
+
+impl Display for CalendarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read calendar {} : {}", path.display(), error)
+            }
+            Self::InvalidDate { path, property } => {
+                writeln!(f, "Invalid date in calendar {}", path.display())?;
+                write!(f, "Got property '{}'", property)
+            }
+
+            Self::RecurrenceError { path, rule, error } => {
+                writeln!(
+                    f,
+                    "Unable to expand a recurring event in calendar {}",
+                    path.display()
+                )?;
+                writeln!(f, "Rule : '{}'", rule)?;
+                write!(f, "Got error '{}'", error)
+            }
+        }
+    }
+}
+
+/// A content line, e.g. `DTSTART;TZID=Europe/Paris:20240102T090000`.
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+    raw: &'a str,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        // parameter values can be quoted, and contain ':' when they are
+        let mut in_quotes = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                None
+            }
+            ':' if !in_quotes => Some(i),
+            _ => None,
+        })?;
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+        let mut head = head.split(';');
+        let name = head.next()?.to_ascii_uppercase();
+        let params = head
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key.to_ascii_uppercase(), value.trim_matches('"')))
+            .collect();
+
+        Some(Self {
+            name,
+            params,
+            value,
+            raw: line,
+        })
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find_map(|(k, v)| (k == key).then_some(*v))
+    }
+
+    /// The line for the `rrule` crate, which fails on the TZIDs chrono-tz doesn't know. They are
+    /// replaced by the matching IANA name, or removed to fall back to local time.
+    fn to_rrule_line(&self) -> String {
+        let Some(tzid) = self.param("TZID") else {
+            return self.raw.into();
+        };
+        let mut line = self.name.clone();
+        for (key, value) in &self.params {
+            match (key.as_str(), time_zone(tzid)) {
+                ("TZID", Some(tz)) => line.push_str(&format!(";TZID={}", tz.name())),
+                ("TZID", None) => (),
+                _ => line.push_str(&format!(";{}={}", key, value)),
+            }
+        }
+        format!("{}:{}", line, self.value)
+    }
+}
+
+fn time_zone(tzid: &str) -> Option<Tz> {
+    tzid.parse::<Tz>().ok().or_else(|| {
+        WINDOWS_TIME_ZONES
+            .iter()
+            .find_map(|(name, tz)| (*name == tzid).then_some(*tz))
+    })
+}
+
+/// The properties of a `VEVENT` we care about, before recurrences are expanded.
+#[derive(Default)]
+struct RawEvent {
+    uid: Option<String>,
+    summary: String,
+    start: Option<(DateTime<Local>, bool)>,
+    // the DTSTART line, which the recurrence rules are relative to
+    start_line: String,
+    end: Option<DateTime<Local>>,
+    duration: Option<TimeDelta>,
+    // RRULE, RDATE and EXDATE lines
+    recurrence_lines: Vec<String>,
+    recurrence_id: Option<DateTime<Local>>,
+    /// one of its dates couldn't be parsed, so the event is left out
+    invalid: bool,
+}
+
+impl RawEvent {
+    fn duration(&self, start: DateTime<Local>, all_day: bool) -> TimeDelta {
+        match (self.end, self.duration) {
+            (Some(end), _) => end - start,
+            (None, Some(duration)) => duration,
+            (None, None) if all_day => TimeDelta::days(1),
+            (None, None) => TimeDelta::zero(),
+        }
+    }
+}
+
+/// Lines starting with a space or a tab continue the previous one.
+fn unfold(raw: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in raw.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Parses a DATE or DATE-TIME value, and tells whether it was a DATE (i.e. an all-day event).
+fn parse_date_time(property: &Property) -> Option<(DateTime<Local>, bool)> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        let midnight = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some((midnight, true));
+    }
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((Utc.from_utc_datetime(&naive).with_timezone(&Local), false));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    let date_time = match property.param("TZID").and_then(time_zone) {
+        Some(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()?
+            .with_timezone(&Local),
+        // times without a time zone are 'floating', and are the same in every time zone. Unknown
+        // TZIDs are treated the same way, which is the best we can do.
+        None => Local.from_local_datetime(&naive).earliest()?,
+    };
+    Some((date_time, false))
+}
+
+/// Parses a DURATION value, e.g. `PT1H30M` or `P1D`.
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let (sign, value) = match value.trim().strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => (),
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total += match unit {
+                    'W' => TimeDelta::weeks(n),
+                    'D' => TimeDelta::days(n),
+                    'H' => TimeDelta::hours(n),
+                    'M' => TimeDelta::minutes(n),
+                    'S' => TimeDelta::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(total * sign)
+}
+
+/// Parses the events of a calendar. The events with a date we can't parse are logged and left
+/// out, without the rest of the calendar.
+fn parse_calendar(path: &Path, raw: &str) -> Vec<RawEvent> {
+    let mut events = Vec::new();
+    let mut current: Option<RawEvent> = None;
+    // the name of the component nested in the current event we are in, e.g. VALARM
+    let mut nested: Option<String> = None;
+
+    for line in unfold(raw) {
+        let Some(property) = Property::parse(&line) else {
+            continue;
+        };
+        let invalid_date = |event: &mut RawEvent| {
+            log::error!(
+                "{}",
+                CalendarError::InvalidDate {
+                    path: path.into(),
+                    property: property.raw.into(),
+                }
+            );
+            event.invalid = true;
+        };
+
+        match (property.name.as_str(), &mut current, &nested) {
+            ("BEGIN", None, _) if property.value == "VEVENT" => current = Some(RawEvent::default()),
+            ("BEGIN", Some(_), None) => nested = Some(property.value.into()),
+            ("END", Some(_), Some(component)) if component == property.value => nested = None,
+            ("END", Some(_), None) if property.value == "VEVENT" => {
+                events.extend(current.take().filter(|event| !event.invalid));
+            }
+            (_, Some(_), Some(_)) => (),
+            ("UID", Some(event), None) => event.uid = Some(property.value.into()),
+            ("SUMMARY", Some(event), None) => {
+                event.summary = property.value.replace("\\,", ",").replace("\\;", ";")
+            }
+            ("DTSTART", Some(event), None) => match parse_date_time(&property) {
+                Some(start) => {
+                    event.start = Some(start);
+                    event.start_line = property.to_rrule_line();
+                }
+                None => invalid_date(event),
+            },
+            ("DTEND", Some(event), None) => match parse_date_time(&property) {
+                Some((end, _)) => event.end = Some(end),
+                None => invalid_date(event),
+            },
+            ("DURATION", Some(event), None) => match parse_duration(property.value) {
+                Some(duration) => event.duration = Some(duration),
+                None => invalid_date(event),
+            },
+            ("RECURRENCE-ID", Some(event), None) => match parse_date_time(&property) {
+                Some((recurrence_id, _)) => event.recurrence_id = Some(recurrence_id),
+                None => invalid_date(event),
+            },
+            ("RRULE" | "RDATE" | "EXDATE", Some(event), None) => {
+                event.recurrence_lines.push(property.to_rrule_line())
+            }
+            _ => (),
+        }
+    }
+
+    events
+}
+
+/// Computes the occurrences of the events of a calendar that overlap `[from, to]`.
+fn expand(
+    path: &Path,
+    events: Vec<RawEvent>,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> Vec<Event> {
+    // modified occurrences of a recurring event are separate events with the same UID, and
+    // replace the occurrence given by their RECURRENCE-ID
+    let mut overridden: HashMap<String, Vec<DateTime<Local>>> = HashMap::new();
+    for event in &events {
+        if let (Some(uid), Some(recurrence_id)) = (&event.uid, event.recurrence_id) {
+            overridden
+                .entry(uid.clone())
+                .or_default()
+                .push(recurrence_id);
+        }
+    }
+
+    let mut occurrences = Vec::new();
+    for event in events {
+        let Some((start, all_day)) = event.start else {
+            continue;
+        };
+        let duration = event.duration(start, all_day);
+
+        let starts = if event.recurrence_lines.is_empty() || event.recurrence_id.is_some() {
+            vec![start]
+        } else {
+            let rule = format!(
+                "{}\n{}",
+                event.start_line,
+                event.recurrence_lines.join("\n")
+            );
+            match rule.parse::<RRuleSet>() {
+                Ok(rule_set) => rule_set
+                    .after((from - duration).with_timezone(&rrule::Tz::LOCAL))
+                    .before(to.with_timezone(&rrule::Tz::LOCAL))
+                    .all(MAX_OCCURRENCES)
+                    .dates
+                    .into_iter()
+                    .map(|date| date.with_timezone(&Local))
+                    .filter(|date| {
+                        !event
+                            .uid
+                            .as_ref()
+                            .and_then(|uid| overridden.get(uid))
+                            .is_some_and(|dates| dates.contains(date))
+                    })
+                    .collect(),
+                Err(error) => {
+                    let e = CalendarError::RecurrenceError {
+                        path: path.into(),
+                        rule,
+                        error: Box::new(error),
+                    };
+                    log::error!("{}", e);
+                    vec![start]
+                }
+            }
+        };
+
+        occurrences.extend(
+            starts
+                .into_iter()
+                .map(|start| Event {
+                    summary: event.summary.clone(),
+                    start,
+                    end: start + duration,
+                    all_day,
+                })
+                .filter(|occurrence| occurrence.end >= from && occurrence.start <= to),
+        );
+    }
+    occurrences
+}
+
+/// A path in the config can either be a single `.ics` file, or a directory containing them, as
+/// in a vdir. `visited` holds the directories already listed, which are skipped when symbolic
+/// links lead back to them. Only a path that can't be read at all is an error: the broken
+/// entries of a directory are logged and skipped.
+fn find_calendar_files(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), CalendarError> {
+    let io_error = |error| CalendarError::IoError {
+        path: path.into(),
+        error,
+    };
+    if std::fs::metadata(path).map_err(io_error)?.is_dir() {
+        if !visited.insert(path.canonicalize().map_err(io_error)?) {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(path).map_err(io_error)? {
+            let result = entry
+                .map_err(io_error)
+                .and_then(|entry| find_calendar_files(&entry.path(), files, visited));
+            if let Err(e) = result {
+                log::error!("{}", e);
+            }
+        }
+    } else if path.extension().is_some_and(|extension| extension == "ics") {
+        files.push(path.into());
+    }
+    Ok(())
+}
+
+/// Reads all the calendars, and returns the occurrences of their events around `now`, sorted by
+/// start time. Broken files are logged and skipped.
+pub fn load_events(paths: &[PathBuf], now: DateTime<Local>) -> Vec<Event> {
+    let (from, to) = (now - LOOKAHEAD, now + LOOKAHEAD);
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    for path in paths {
+        if let Err(e) = find_calendar_files(path, &mut files, &mut visited) {
+            log::error!("{}", e);
+        }
+    }
+
+    let mut events: Vec<Event> = files
+        .into_iter()
+        .flat_map(|path| match std::fs::read_to_string(&path) {
+            Ok(raw) => expand(&path, parse_calendar(&path, &raw), from, to),
+            Err(error) => {
+                log::error!("{}", CalendarError::IoError { path, error });
+                Vec::new()
+            }
+        })
+        .collect();
+
+    events.sort_by_key(|event| event.start);
+    events
+}
+
+/// The timed event in progress or the next one today, among events sorted by start time. Events
+/// spanning several days are in progress from their start.
+pub fn next_event(events: &[Event], now: DateTime<Local>) -> Option<&Event> {
+    events.iter().find(|event| {
+        !event.all_day && event.end > now && event.start.date_naive() <= now.date_naive()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn calendar(events: &[&str]) -> String {
+        let events = events
+            .iter()
+            .map(|event| format!("BEGIN:VEVENT\n{}\nEND:VEVENT\n", event.trim()))
+            .collect::<String>();
+        format!("BEGIN:VCALENDAR\nVERSION:2.0\n{}END:VCALENDAR\n", events)
+    }
+
+    fn events(raw: &str, from: DateTime<Local>, to: DateTime<Local>) -> Vec<Event> {
+        let path = Path::new("test.ics");
+        let mut events = expand(path, parse_calendar(path, raw), from, to);
+        events.sort_by_key(|event| event.start);
+        events
+    }
+
+    #[test]
+    fn parses_dates_in_time_zones() {
+        let raw = calendar(&[
+            "SUMMARY:Paris\nDTSTART;TZID=Europe/Paris:20240110T090000\nDTEND;TZID=Europe/Paris:20240110T100000",
+            "SUMMARY:UTC\nDTSTART:20240110T120000Z\nDURATION:PT1H30M",
+            "SUMMARY:Outlook\nDTSTART;TZID=Romance Standard Time:20240110T150000\nDTEND;TZID=Romance Standard Time:20240110T153000",
+        ]);
+        let events = events(&raw, utc(9, 0, 0), utc(11, 0, 0));
+        let times = events
+            .iter()
+            .map(|event| (event.summary.as_str(), event.start, event.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            times,
+            [
+                ("Paris", utc(10, 8, 0), utc(10, 9, 0)),
+                ("UTC", utc(10, 12, 0), utc(10, 13, 30)),
+                ("Outlook", utc(10, 14, 0), utc(10, 14, 30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_all_day_events_and_folded_lines() {
+        let raw = calendar(&["SUMMARY:Long\n  weekend\nDTSTART;VALUE=DATE:20240110"]);
+        let events = events(&raw, utc(9, 0, 0), utc(11, 0, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Long weekend");
+        assert!(events[0].all_day);
+        assert_eq!(events[0].end - events[0].start, TimeDelta::days(1));
+    }
+
+    #[test]
+    fn ignores_nested_components() {
+        let raw = calendar(&[
+            "SUMMARY:Meeting\nDTSTART:20240110T120000Z\nBEGIN:VALARM\nDURATION:PT15M\nSUMMARY:Reminder\nEND:VALARM",
+        ]);
+        let events = events(&raw, utc(9, 0, 0), utc(11, 0, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Meeting");
+        assert_eq!(events[0].end, events[0].start);
+    }
+
+    #[test]
+    fn skips_only_the_events_with_invalid_dates() {
+        let raw = calendar(&[
+            "SUMMARY:Broken start\nDTSTART:2024-01-10 12:00",
+            "SUMMARY:Broken end\nDTSTART:20240110T120000Z\nDTEND:tomorrow",
+            "SUMMARY:Fine\nDTSTART:20240110T140000Z",
+        ]);
+        let events = events(&raw, utc(9, 0, 0), utc(11, 0, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Fine");
+    }
+
+    #[test]
+    fn expands_recurring_events_in_the_window() {
+        let raw = calendar(&[
+            "SUMMARY:Standup\nDTSTART;TZID=Europe/Paris:20240101T093000\nDURATION:PT15M\nRRULE:FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR\nEXDATE;TZID=Europe/Paris:20240110T093000",
+        ]);
+        let starts = events(&raw, utc(8, 0, 0), utc(13, 0, 0))
+            .into_iter()
+            .map(|event| event.start)
+            .collect::<Vec<_>>();
+        // Monday the 8th to Friday the 12th, without Wednesday
+        assert_eq!(
+            starts,
+            [utc(8, 8, 30), utc(9, 8, 30), utc(11, 8, 30), utc(12, 8, 30)]
+        );
+    }
+
+    #[test]
+    fn replaces_modified_occurrences() {
+        let raw = calendar(&[
+            "UID:weekly\nSUMMARY:Review\nDTSTART:20240102T150000Z\nRRULE:FREQ=WEEKLY",
+            "UID:weekly\nSUMMARY:Review (moved)\nRECURRENCE-ID:20240109T150000Z\nDTSTART:20240110T160000Z",
+        ]);
+        let events = events(&raw, utc(8, 0, 0), utc(20, 0, 0));
+        let occurrences = events
+            .iter()
+            .map(|event| (event.summary.as_str(), event.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            occurrences,
+            [
+                ("Review (moved)", utc(10, 16, 0)),
+                ("Review", utc(16, 15, 0))
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_recurring_events_with_windows_or_unknown_time_zones() {
+        let raw = calendar(&[
+            "SUMMARY:Outlook\nDTSTART;TZID=W. Europe Standard Time:20240101T100000\nRRULE:FREQ=DAILY",
+            "SUMMARY:Unknown\nDTSTART;TZID=Somewhere/Else:20240101T100000\nRRULE:FREQ=DAILY",
+        ]);
+        let events = events(&raw, utc(8, 0, 0), utc(10, 0, 0));
+        let outlook = events
+            .iter()
+            .filter(|event| event.summary == "Outlook")
+            .map(|event| event.start)
+            .collect::<Vec<_>>();
+        assert_eq!(outlook, [utc(8, 9, 0), utc(9, 9, 0)]);
+        // in local time, whatever it is here
+        let unknown = events
+            .iter()
+            .filter(|event| event.summary == "Unknown")
+            .count();
+        assert!(unknown >= 2);
+    }
+
+    #[test]
+    fn finds_calendars_through_symbolic_link_loops() {
+        let tree = FakeTree::new();
+        tree.write("calendars/work/a.ics", "")
+            .write("calendars/work/notes.txt", "")
+            .write("calendars/home/b.ics", "")
+            .symlink("calendars", "calendars/work/all");
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        let result = find_calendar_files(&tree.path().join("calendars"), &mut files, &mut visited);
+        assert!(result.is_ok());
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                tree.path().join("calendars/home/b.ics"),
+                tree.path().join("calendars/work/a.ics")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_broken_entries_of_a_calendar_directory() {
+        let tree = FakeTree::new();
+        tree.write("calendars/a.ics", "")
+            .symlink("calendars/gone.ics", "calendars/dangling.ics")
+            .mkdir("calendars/locked");
+        let locked = tree.path().join("calendars/locked");
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        let result = find_calendar_files(&tree.path().join("calendars"), &mut files, &mut visited);
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(files, [tree.path().join("calendars/a.ics")]);
+
+        let result = find_calendar_files(&tree.path().join("missing"), &mut files, &mut visited);
+        assert!(matches!(result, Err(CalendarError::IoError { .. })));
+    }
+
+    #[test]
+    fn shows_events_started_on_previous_days() {
+        let event = |summary: &str, start, end, all_day| Event {
+            summary: summary.into(),
+            start,
+            end,
+            all_day,
+        };
+        let now = utc(10, 12, 0);
+        let events = [
+            event("Holidays", utc(8, 0, 0), utc(12, 0, 0), true),
+            event("Conference", utc(9, 8, 0), utc(11, 17, 0), false),
+            event("Lunch", utc(10, 12, 30), utc(10, 13, 30), false),
+        ];
+        let next = next_event(&events, now).map(|event| event.summary.as_str());
+        assert_eq!(next, Some("Conference"));
+        let next = next_event(&events[2..], now).map(|event| event.summary.as_str());
+        assert_eq!(next, Some("Lunch"));
+    }
+}
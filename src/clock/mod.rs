@@ -1,27 +1,163 @@
-use chrono::{DateTime, Datelike, Local, NaiveDate};
+mod alarm;
+mod calendar;
+mod timer;
+
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
+use chrono_tz::Tz;
 use iced::{
-    color,
-    widget::{button, text, Button},
-    Element, Subscription,
+    color, subscription,
+    widget::{button, column, mouse_area, row, scrollable, text, tooltip, Button, Column},
+    Element, Length, Padding, Subscription,
+};
+use nix::sys::{
+    time::TimeSpec,
+    timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags},
+};
+use serde::Deserialize;
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    scroll_area::ScrollArea,
+    style::{self, ContainerStyle},
 };
 
+pub use alarm::AlarmConfig;
+use alarm::Alarms;
+use calendar::Event;
+pub use timer::TimerConfig;
+use timer::{format_duration, PomodoroPhase, TimerKind, Timers};
+
+/// The longest we sleep between two ticks. Even when the displayed string can only change at
+/// midnight, we wake up this often to catch wall-clock jumps (NTP, manual changes, time zone
+/// changes) that no timer would tell us about.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Wake up slightly after the boundary, so that `Local::now()` is guaranteed to be past it.
+const BOUNDARY_SLACK: Duration = Duration::from_millis(10);
+
+/// An additional time zone to display, as written in the config file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TimeZoneConfig {
+    /// short text displayed before the time, e.g. "NYC"
+    pub label: String,
+    /// IANA name of the time zone, e.g. "America/New_York"
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub time_zones: Vec<TimeZoneConfig>,
+    pub timers: TimerConfig,
+    /// `.ics` files, or directories containing them (e.g. a vdir synced by vdirsyncer)
+    pub calendars: Vec<PathBuf>,
+    pub alarms: Vec<AlarmConfig>,
+    /// how long a snoozed alarm waits before ringing again, in minutes
+    pub snooze_minutes: i64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            time_zones: Vec::new(),
+            timers: TimerConfig::default(),
+            calendars: Vec::new(),
+            alarms: Vec::new(),
+            snooze_minutes: 9,
+        }
+    }
+}
+
 /// The state of the clock widget
 enum State {
     // display the current date
     Date,
     // display the current time
     Time,
+    // display the current time in one of the additional time zones, given by its index
+    TimeZone(usize),
+    // display one of the timers. Right click starts or pauses it, middle click resets it
+    Timer(TimerKind),
+}
+
+/// How often the displayed string can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Granularity {
+    Second,
+    Minute,
+    Day,
+}
+
+/// Computes how long to wait before the displayed string can change, given the current time.
+fn time_until_change(now: DateTime<Local>, granularity: Granularity) -> Duration {
+    let into_minute = Duration::new(now.second() as u64, now.nanosecond() % 1_000_000_000);
+    let until_next_minute = Duration::from_secs(60).saturating_sub(into_minute);
+
+    let until_change = match granularity {
+        Granularity::Second => Duration::from_secs(1).saturating_sub(Duration::from_nanos(
+            now.nanosecond() as u64 % 1_000_000_000,
+        )),
+        Granularity::Minute => until_next_minute,
+        Granularity::Day => now
+            .date_naive()
+            .succ_opt()
+            .and_then(|tomorrow| tomorrow.and_hms_opt(0, 0, 0))
+            // midnight may not exist, or exist twice, on days with a DST transition
+            .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+            .and_then(|midnight| (midnight - now).to_std().ok())
+            .unwrap_or(until_next_minute),
+    };
+
+    until_change.min(MAX_SLEEP) + BOUNDARY_SLACK
+}
+
+/// Sleeps for `duration` according to `CLOCK_BOOTTIME`. Contrary to the monotonic clock used by
+/// tokio's timers, it keeps counting while the system is suspended, so that we wake up right
+/// after a resume instead of displaying a stale time.
+async fn sleep_boottime(duration: Duration) -> std::io::Result<()> {
+    let timer = TimerFd::new(
+        ClockId::CLOCK_BOOTTIME,
+        TimerFlags::TFD_NONBLOCK | TimerFlags::TFD_CLOEXEC,
+    )?;
+    timer.set(
+        Expiration::OneShot(TimeSpec::from_duration(duration)),
+        TimerSetTimeFlags::empty(),
+    )?;
+    let timer = AsyncFd::new(timer)?;
+    loop {
+        let mut guard = timer.readable().await?;
+        if let Ok(result) = guard.try_io(|timer| Ok(timer.get_ref().wait()?)) {
+            return result;
+        }
+    }
 }
 
 pub struct Clock {
     now: DateTime<Local>,
     state: State,
+    time_zones: Vec<(String, Tz)>,
+    timers: Timers,
+    calendars: Vec<PathBuf>,
+    /// the occurrences of the calendar events around now, sorted by start time
+    events: Vec<Event>,
+    alarms: Alarms,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum ClockMessage {
     Tick(DateTime<Local>),
     ChangeState,
+    ToggleTimer,
+    ResetTimer,
+    /// change the countdown duration by this many scroll steps
+    AdjustCountdown(i64),
+    EventsLoaded(Vec<Event>),
+    /// open or close the popup with today's agenda. This is handled by the main application,
+    /// which owns the popup surface.
+    ToggleAgenda,
+    SnoozeAlarm,
+    DismissAlarm,
 }
 
 fn format_date(date: NaiveDate) -> String {
@@ -55,44 +191,397 @@ fn format_date(date: NaiveDate) -> String {
 }
 
 impl Clock {
+    pub fn new(config: &ClockConfig) -> Self {
+        let time_zones = config
+            .time_zones
+            .iter()
+            .filter_map(|zone| match zone.name.parse::<Tz>() {
+                Ok(tz) => Some((zone.label.clone(), tz)),
+                Err(e) => {
+                    log::error!("Ignoring unknown time zone '{}' : {}", zone.name, e);
+                    None
+                }
+            })
+            .collect();
+
+        let now = Local::now();
+
+        Self {
+            now,
+            state: State::Time,
+            time_zones,
+            timers: Timers::load(&config.timers),
+            calendars: config.calendars.clone(),
+            events: Vec::new(),
+            alarms: Alarms::new(&config.alarms, config.snooze_minutes, now),
+        }
+    }
+
     pub fn update(&mut self, message: ClockMessage) {
+        // the subscription only ticks when the displayed string changes, which depends on the
+        // state, so the current value might be a bit old
+        self.now = match message {
+            ClockMessage::Tick(new_time) => new_time,
+            _ => Local::now(),
+        };
+
         match message {
-            ClockMessage::Tick(new_time) => self.now = new_time,
-            ClockMessage::ChangeState => match self.state {
-                State::Date => self.state = State::Time,
-                State::Time => self.state = State::Date,
-            },
+            ClockMessage::Tick(_) => (),
+            ClockMessage::ChangeState => self.cycle_state(),
+            ClockMessage::ToggleTimer => {
+                if let State::Timer(kind) = self.state {
+                    self.timers.toggle(kind, self.now);
+                }
+            }
+            ClockMessage::ResetTimer => {
+                if let State::Timer(kind) = self.state {
+                    self.timers.reset(kind);
+                }
+            }
+            ClockMessage::AdjustCountdown(steps) => self.timers.adjust_countdown(steps),
+            ClockMessage::EventsLoaded(events) => self.events = events,
+            ClockMessage::ToggleAgenda => (),
+            ClockMessage::SnoozeAlarm => self.alarms.snooze(self.now),
+            ClockMessage::DismissAlarm => self.alarms.dismiss(),
+        }
+
+        self.timers.check_deadlines(self.now);
+        self.alarms.check(self.now);
+    }
+
+    /// cycle through the time, the date, each additional time zone, and then the timers
+    fn cycle_state(&mut self) {
+        match self.state {
+            State::Time => self.state = State::Date,
+            State::Date if self.time_zones.is_empty() => {
+                self.state = State::Timer(TimerKind::Countdown)
+            }
+            State::Date => self.state = State::TimeZone(0),
+            State::TimeZone(i) if i + 1 < self.time_zones.len() => {
+                self.state = State::TimeZone(i + 1)
+            }
+            State::TimeZone(_) => self.state = State::Timer(TimerKind::Countdown),
+            State::Timer(TimerKind::Countdown) => self.state = State::Timer(TimerKind::Stopwatch),
+            State::Timer(TimerKind::Stopwatch) => self.state = State::Timer(TimerKind::Pomodoro),
+            State::Timer(TimerKind::Pomodoro) => self.state = State::Time,
+        }
+    }
+
+    fn granularity(&self) -> Granularity {
+        // a ringing alarm makes the clock flash every second
+        if !self.alarms.ringing().is_empty() {
+            return Granularity::Second;
+        }
+
+        match self.state {
+            State::Date => Granularity::Day,
+            State::Time | State::TimeZone(_) => Granularity::Minute,
+            State::Timer(TimerKind::Countdown) if self.timers.countdown.is_running() => {
+                Granularity::Second
+            }
+            State::Timer(TimerKind::Stopwatch) if self.timers.stopwatch.is_running() => {
+                Granularity::Second
+            }
+            State::Timer(TimerKind::Pomodoro) if self.timers.pomodoro.countdown.is_running() => {
+                Granularity::Second
+            }
+            // a paused timer never changes, so we might as well keep the usual pace
+            State::Timer(_) => Granularity::Minute,
         }
     }
 
+    /// Ticks only when the displayed string can change, rather than polling. The granularity is
+    /// part of the subscription id, so that iced restarts it when the state changes.
     pub fn subscription(&self) -> Subscription<ClockMessage> {
-        iced::time::every(std::time::Duration::from_millis(500))
-            .map(|_| ClockMessage::Tick(Local::now()))
+        struct ClockTicks;
+
+        let granularity = self.granularity();
+
+        let ticks = subscription::unfold(
+            (std::any::TypeId::of::<ClockTicks>(), granularity),
+            (),
+            move |()| async move {
+                let delay = time_until_change(Local::now(), granularity);
+                if let Err(e) = sleep_boottime(delay).await {
+                    log::error!(
+                        "Unable to use a boottime timer, falling back to tokio : {}",
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                (ClockMessage::Tick(Local::now()), ())
+            },
+        );
+
+        Subscription::batch([
+            ticks,
+            self.deadline_subscription(),
+            self.calendar_subscription(),
+        ])
     }
 
-    pub fn view(&self) -> Element<ClockMessage> {
+    /// Reads the calendars in the background, and then again every `REFRESH_INTERVAL`.
+    fn calendar_subscription(&self) -> Subscription<ClockMessage> {
+        struct CalendarRefresh;
+
+        if self.calendars.is_empty() {
+            return Subscription::none();
+        }
+
+        subscription::unfold(
+            std::any::TypeId::of::<CalendarRefresh>(),
+            (self.calendars.clone(), true),
+            |(calendars, first)| async move {
+                if !first {
+                    tokio::time::sleep(calendar::REFRESH_INTERVAL).await;
+                }
+                let paths = calendars.clone();
+                let events = match tokio::task::spawn_blocking(move || {
+                    calendar::load_events(&paths, Local::now())
+                })
+                .await
+                {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::error!("Unable to load the calendars : {}", e);
+                        Vec::new()
+                    }
+                };
+                (ClockMessage::EventsLoaded(events), (calendars, false))
+            },
+        )
+    }
+
+    /// Ticks once when the next running countdown ends or the next alarm rings, so that we can
+    /// notify the user even if the clock isn't displaying them.
+    fn deadline_subscription(&self) -> Subscription<ClockMessage> {
+        struct TimerDeadline;
+
+        let deadline = [
+            self.timers.next_deadline(),
+            self.alarms.next_deadline(self.now),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let Some(deadline) = deadline else {
+            return Subscription::none();
+        };
+
+        subscription::unfold(
+            (
+                std::any::TypeId::of::<TimerDeadline>(),
+                deadline.timestamp_millis(),
+            ),
+            false,
+            move |fired| async move {
+                // the tick has been sent, and this subscription will be dropped when the
+                // deadline gets updated
+                if fired {
+                    return iced::futures::future::pending().await;
+                }
+                // sleep in chunks, as the deadline is on the wall clock which can jump around
+                while let Ok(remaining) = (deadline - Local::now()).to_std() {
+                    let delay = remaining.min(MAX_SLEEP) + BOUNDARY_SLACK;
+                    if let Err(e) = sleep_boottime(delay).await {
+                        log::error!(
+                            "Unable to use a boottime timer, falling back to tokio : {}",
+                            e
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+                (ClockMessage::Tick(Local::now()), true)
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, ClockMessage> {
         let button_text = match self.state {
             State::Date => format_date(self.now.date_naive()),
             State::Time => self.now.time().format("%H:%M").to_string(),
+            State::TimeZone(i) => {
+                let (label, tz) = &self.time_zones[i];
+                // going through UTC means DST transitions are taken care of by the tz database
+                let zoned = self.now.with_timezone(tz);
+                format!("{} {}", label, zoned.time().format("%H:%M"))
+            }
+            State::Timer(TimerKind::Countdown) => format!(
+                "{} Minuteur {}",
+                running_icon(self.timers.countdown.is_running()),
+                format_duration(self.timers.countdown.remaining(self.now))
+            ),
+            State::Timer(TimerKind::Stopwatch) => format!(
+                "{} Chrono {}",
+                running_icon(self.timers.stopwatch.is_running()),
+                format_duration(self.timers.stopwatch.elapsed(self.now))
+            ),
+            State::Timer(TimerKind::Pomodoro) => format!(
+                "{} {} {}",
+                running_icon(self.timers.pomodoro.countdown.is_running()),
+                match self.timers.pomodoro.phase {
+                    PomodoroPhase::Work => "Travail",
+                    PomodoroPhase::Break => "Pause",
+                },
+                format_duration(self.timers.pomodoro.countdown.remaining(self.now))
+            ),
         };
-        Button::new(text(button_text).style(iced::Color::WHITE))
+
+        let button = Button::new(text(button_text).style(iced::Color::WHITE))
             .on_press(ClockMessage::ChangeState)
             .style(iced::theme::Button::custom(ButtonStyle {}))
-            .padding(iced::Padding::from(0))
+            .padding(iced::Padding::from(0));
+
+        let clickable = mouse_area(button)
+            .on_right_press(ClockMessage::ToggleTimer)
+            .on_middle_press(ClockMessage::ResetTimer);
+
+        // the duration of the countdown is set by scrolling over it
+        let clock: Element<ClockMessage> = match self.state {
+            _ if !self.alarms.ringing().is_empty() => self.view_ringing(),
+            State::Timer(TimerKind::Countdown) => ScrollArea::new(clickable)
+                .on_scroll_up(ClockMessage::AdjustCountdown(1))
+                .on_scroll_down(ClockMessage::AdjustCountdown(-1))
+                .into(),
+            _ => clickable.into(),
+        };
+
+        let pending_alarms = self.alarms.pending(self.now);
+        let clock: Element<ClockMessage> = if pending_alarms.is_empty() {
+            clock
+        } else {
+            let pending = pending_alarms
+                .iter()
+                .map(|(date, label)| format!("{} {}", date.format("%a %H:%M"), label))
+                .collect::<Vec<_>>()
+                .join("  ·  ");
+            // the bar is too thin for a tooltip below the clock
+            tooltip(
+                clock,
+                text(format!("⏰ {}", pending)),
+                tooltip::Position::Left,
+            )
+            .padding(0)
+            .style(ContainerStyle::default().theme())
+            .into()
+        };
+
+        if self.calendars.is_empty() {
+            return clock;
+        }
+
+        let agenda_text = match calendar::next_event(&self.events, self.now) {
+            Some(event) => format!("󰃭 {}", describe_next_event(event, self.now)),
+            None => "󰃭".to_string(),
+        };
+        let agenda = Button::new(text(agenda_text).style(iced::Color::WHITE))
+            .on_press(ClockMessage::ToggleAgenda)
+            .style(iced::theme::Button::custom(ButtonStyle {}))
+            .padding(Padding::from([0, 0, 0, 10]));
+
+        row![clock, agenda].into()
+    }
+
+    /// A ringing alarm replaces the clock, and flashes. Left click snoozes it, right click
+    /// dismisses it.
+    fn view_ringing(&self) -> Element<'_, ClockMessage> {
+        let style = if self.now.second().is_multiple_of(2) {
+            iced::theme::Button::custom(RingingButtonStyle {})
+        } else {
+            iced::theme::Button::custom(ButtonStyle {})
+        };
+        let button = Button::new(
+            text(format!("⏰ {}", self.alarms.ringing().join(", "))).style(iced::Color::WHITE),
+        )
+        .on_press(ClockMessage::SnoozeAlarm)
+        .style(style)
+        .padding(Padding::from([0, 5]));
+
+        mouse_area(button)
+            .on_right_press(ClockMessage::DismissAlarm)
             .into()
     }
+
+    /// the content of the popup listing today's events
+    pub fn view_agenda(&self) -> Element<'_, ClockMessage> {
+        let today = self.now.date_naive();
+        let lines = self
+            .events
+            .iter()
+            .filter(|event| {
+                event.start.date_naive() <= today && today <= event.end.date_naive()
+                    // all-day events end at midnight the next day
+                    && !(event.all_day && event.end.date_naive() == today)
+            })
+            .map(|event| {
+                let when = if event.all_day {
+                    "Toute la journée".to_string()
+                } else {
+                    format!(
+                        "{} - {}",
+                        event.start.format("%H:%M"),
+                        event.end.format("%H:%M")
+                    )
+                };
+                let style = if !event.all_day && event.end <= self.now {
+                    color!(0x808080)
+                } else {
+                    iced::Color::WHITE
+                };
+                row![
+                    text(when).style(style).width(Length::Fixed(150.0)),
+                    text(&event.summary).style(style)
+                ]
+                .into()
+            })
+            .collect::<Vec<Element<_>>>();
+
+        let content = if lines.is_empty() {
+            column![text("Rien de prévu aujourd'hui").style(iced::Color::WHITE)]
+        } else {
+            Column::with_children(lines).spacing(5)
+        };
+
+        style::popup(
+            column![
+                text(format_date(today)).style(iced::Color::WHITE),
+                scrollable(content)
+            ]
+            .spacing(10),
+        )
+    }
+}
+
+/// e.g. "Standup dans 12 min"
+fn describe_next_event(event: &Event, now: DateTime<Local>) -> String {
+    if event.start <= now {
+        return format!("{} en cours", event.summary);
+    }
+    // round up, so that we never say "in 0 min" for an event that hasn't started
+    let minutes = (((event.start - now).num_seconds() + 59) / 60).max(1);
+    if minutes < 60 {
+        format!("{} dans {} min", event.summary, minutes)
+    } else {
+        format!("{} à {}", event.summary, event.start.format("%H:%M"))
+    }
+}
+
+fn running_icon(running: bool) -> char {
+    if running {
+        '▶'
+    } else {
+        '⏸'
+    }
 }
 
 impl Default for Clock {
     fn default() -> Self {
-        Self {
-            now: Local::now(),
-            state: State::Time,
-        }
+        Self::new(&ClockConfig::default())
     }
 }
 
 struct ButtonStyle;
+struct RingingButtonStyle;
 
 impl button::StyleSheet for ButtonStyle {
     type Style = iced::Theme;
@@ -104,3 +593,89 @@ impl button::StyleSheet for ButtonStyle {
         }
     }
 }
+
+impl button::StyleSheet for RingingButtonStyle {
+    type Style = iced::Theme;
+
+    fn active(&self, _style: &Self::Style) -> button::Appearance {
+        button::Appearance {
+            background: Some(iced::Background::Color(color!(0xcc241d))),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+    use chrono::TimeDelta;
+
+    fn at(hour: u32, minute: u32, second: u32, millis: i64) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, 10, hour, minute, second)
+            .unwrap()
+            + TimeDelta::milliseconds(millis)
+    }
+
+    #[test]
+    fn waits_for_the_next_second() {
+        assert_eq!(
+            time_until_change(at(9, 0, 0, 250), Granularity::Second),
+            Duration::from_millis(750) + BOUNDARY_SLACK
+        );
+        assert_eq!(
+            time_until_change(at(9, 0, 59, 999), Granularity::Second),
+            Duration::from_millis(1) + BOUNDARY_SLACK
+        );
+    }
+
+    #[test]
+    fn waits_for_the_next_minute() {
+        assert_eq!(
+            time_until_change(at(9, 0, 45, 500), Granularity::Minute),
+            Duration::from_millis(14_500) + BOUNDARY_SLACK
+        );
+        assert_eq!(
+            time_until_change(at(9, 1, 0, 0), Granularity::Minute),
+            Duration::from_secs(60) + BOUNDARY_SLACK
+        );
+    }
+
+    #[test]
+    fn waits_for_midnight_but_not_longer_than_a_minute() {
+        assert_eq!(
+            time_until_change(at(23, 59, 30, 0), Granularity::Day),
+            Duration::from_secs(30) + BOUNDARY_SLACK
+        );
+        assert_eq!(
+            time_until_change(at(12, 0, 30, 0), Granularity::Day),
+            MAX_SLEEP + BOUNDARY_SLACK
+        );
+    }
+
+    #[test]
+    fn ticks_every_second_only_while_seconds_are_shown() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+        let mut clock = Clock::default();
+        assert_eq!(clock.granularity(), Granularity::Minute);
+
+        clock.update(ClockMessage::ChangeState);
+        assert_eq!(clock.granularity(), Granularity::Day);
+
+        // a paused countdown shows seconds, but they don't change
+        clock.update(ClockMessage::ChangeState);
+        clock.update(ClockMessage::AdjustCountdown(1));
+        assert_eq!(clock.granularity(), Granularity::Minute);
+        clock.update(ClockMessage::ToggleTimer);
+        assert_eq!(clock.granularity(), Granularity::Second);
+        assert!(time_until_change(at(9, 0, 30, 0), clock.granularity()) <= Duration::from_secs(2));
+        clock.update(ClockMessage::ToggleTimer);
+        assert_eq!(clock.granularity(), Granularity::Minute);
+
+        clock.update(ClockMessage::ToggleTimer);
+        clock.update(ClockMessage::ChangeState);
+        assert_eq!(clock.granularity(), Granularity::Minute);
+    }
+}
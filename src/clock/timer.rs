@@ -0,0 +1,422 @@
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_state, save_state};
+
+const STATE_FILE: &str = "timers.json";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimerConfig {
+    /// length of a pomodoro work session, in minutes
+    pub work_minutes: i64,
+    /// length of a pomodoro break, in minutes
+    pub break_minutes: i64,
+    /// how many minutes one scroll step adds to or removes from the countdown
+    pub scroll_step_minutes: i64,
+    /// shell command to run when a countdown or a pomodoro phase ends. If there is none, a
+    /// notification is sent instead.
+    pub command: Option<String>,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        Self {
+            work_minutes: 25,
+            break_minutes: 5,
+            scroll_step_minutes: 1,
+            command: None,
+        }
+    }
+}
+
+/// A countdown that can be paused. While it runs, we only store its deadline on the wall clock,
+/// so that it keeps going while the bar isn't running.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum Countdown {
+    Paused { remaining_secs: i64 },
+    Running { deadline: DateTime<Local> },
+}
+
+impl Countdown {
+    fn paused(duration: TimeDelta) -> Self {
+        Self::Paused {
+            remaining_secs: duration.num_seconds(),
+        }
+    }
+
+    pub fn remaining(&self, now: DateTime<Local>) -> TimeDelta {
+        match self {
+            Self::Paused { remaining_secs } => TimeDelta::seconds(*remaining_secs),
+            Self::Running { deadline } => (*deadline - now).max(TimeDelta::zero()),
+        }
+    }
+
+    pub fn deadline(&self) -> Option<DateTime<Local>> {
+        match self {
+            Self::Paused { .. } => None,
+            Self::Running { deadline } => Some(*deadline),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self, Self::Running { .. })
+    }
+
+    fn is_finished(&self, now: DateTime<Local>) -> bool {
+        matches!(self, Self::Running { deadline } if *deadline <= now)
+    }
+
+    fn toggle(&mut self, now: DateTime<Local>) {
+        *self = match *self {
+            // there is no point in starting an empty countdown
+            Self::Paused { remaining_secs } if remaining_secs <= 0 => return,
+            Self::Paused { remaining_secs } => Self::Running {
+                deadline: now + TimeDelta::seconds(remaining_secs),
+            },
+            Self::Running { .. } => Self::paused(self.remaining(now)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum Stopwatch {
+    Paused {
+        elapsed_secs: i64,
+    },
+    Running {
+        since: DateTime<Local>,
+        // the time elapsed before the stopwatch was last started
+        elapsed_secs: i64,
+    },
+}
+
+impl Stopwatch {
+    pub fn elapsed(&self, now: DateTime<Local>) -> TimeDelta {
+        match self {
+            Self::Paused { elapsed_secs } => TimeDelta::seconds(*elapsed_secs),
+            Self::Running {
+                since,
+                elapsed_secs,
+            } => TimeDelta::seconds(*elapsed_secs) + (now - *since).max(TimeDelta::zero()),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self, Self::Running { .. })
+    }
+
+    fn toggle(&mut self, now: DateTime<Local>) {
+        *self = match *self {
+            Self::Paused { elapsed_secs } => Self::Running {
+                since: now,
+                elapsed_secs,
+            },
+            Self::Running { .. } => Self::Paused {
+                elapsed_secs: self.elapsed(now).num_seconds(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PomodoroPhase {
+    Work,
+    Break,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Pomodoro {
+    pub phase: PomodoroPhase,
+    pub countdown: Countdown,
+}
+
+/// Which of the timers a message is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerKind {
+    Countdown,
+    Stopwatch,
+    Pomodoro,
+}
+
+/// The state of all the timers, saved to `$XDG_STATE_HOME/widgets/timers.json` each time it
+/// changes, so that they survive restarts.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Timers {
+    pub countdown: Countdown,
+    pub stopwatch: Stopwatch,
+    pub pomodoro: Pomodoro,
+    #[serde(skip)]
+    config: TimerConfig,
+}
+
+impl Timers {
+    fn new(config: &TimerConfig) -> Self {
+        Self {
+            countdown: Countdown::paused(TimeDelta::zero()),
+            stopwatch: Stopwatch::Paused { elapsed_secs: 0 },
+            pomodoro: Pomodoro {
+                phase: PomodoroPhase::Work,
+                countdown: Countdown::paused(TimeDelta::minutes(config.work_minutes)),
+            },
+            config: config.clone(),
+        }
+    }
+
+    /// restore the timers from the state file, or start from scratch if there is none.
+    pub fn load(config: &TimerConfig) -> Self {
+        match load_state::<Self>(STATE_FILE) {
+            Some(timers) => Self {
+                config: config.clone(),
+                ..timers
+            },
+            None => Self::new(config),
+        }
+    }
+
+    fn save(&self) {
+        save_state(STATE_FILE, self);
+    }
+
+    /// the earliest instant at which one of the running countdowns ends
+    pub fn next_deadline(&self) -> Option<DateTime<Local>> {
+        [
+            self.countdown.deadline(),
+            self.pomodoro.countdown.deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    fn phase_length(&self, phase: PomodoroPhase) -> TimeDelta {
+        match phase {
+            PomodoroPhase::Work => TimeDelta::minutes(self.config.work_minutes),
+            PomodoroPhase::Break => TimeDelta::minutes(self.config.break_minutes),
+        }
+    }
+
+    pub fn toggle(&mut self, kind: TimerKind, now: DateTime<Local>) {
+        match kind {
+            TimerKind::Countdown => self.countdown.toggle(now),
+            TimerKind::Stopwatch => self.stopwatch.toggle(now),
+            TimerKind::Pomodoro => self.pomodoro.countdown.toggle(now),
+        }
+        self.save();
+    }
+
+    pub fn reset(&mut self, kind: TimerKind) {
+        match kind {
+            TimerKind::Countdown => self.countdown = Countdown::paused(TimeDelta::zero()),
+            TimerKind::Stopwatch => self.stopwatch = Stopwatch::Paused { elapsed_secs: 0 },
+            TimerKind::Pomodoro => {
+                self.pomodoro = Pomodoro {
+                    phase: PomodoroPhase::Work,
+                    countdown: Countdown::paused(self.phase_length(PomodoroPhase::Work)),
+                }
+            }
+        }
+        self.save();
+    }
+
+    /// add `steps` scroll steps to the countdown. This only works while it is paused, to avoid
+    /// changing a running countdown by mistake.
+    pub fn adjust_countdown(&mut self, steps: i64) {
+        if let Countdown::Paused { remaining_secs } = self.countdown {
+            let step = self.config.scroll_step_minutes * 60;
+            let remaining_secs = (remaining_secs + steps * step).max(0);
+            self.countdown = Countdown::Paused { remaining_secs };
+            self.save();
+        }
+    }
+
+    /// stop the countdowns that reached their deadline, and tell the user about it. A finished
+    /// pomodoro phase starts the next one.
+    pub fn check_deadlines(&mut self, now: DateTime<Local>) {
+        let mut changed = false;
+
+        if self.countdown.is_finished(now) {
+            self.countdown = Countdown::paused(TimeDelta::zero());
+            self.alert("Minuteur", "Le minuteur est terminé");
+            changed = true;
+        }
+
+        if self.pomodoro.countdown.is_finished(now) {
+            let (next_phase, body) = match self.pomodoro.phase {
+                PomodoroPhase::Work => (PomodoroPhase::Break, "C'est l'heure de la pause"),
+                PomodoroPhase::Break => (PomodoroPhase::Work, "La pause est terminée"),
+            };
+            self.pomodoro = Pomodoro {
+                phase: next_phase,
+                countdown: Countdown::Running {
+                    deadline: now + self.phase_length(next_phase),
+                },
+            };
+            self.alert("Pomodoro", body);
+            changed = true;
+        }
+
+        if changed {
+            self.save();
+        }
+    }
+
+    fn alert(&self, summary: &str, body: &str) {
+        match &self.config.command {
+            Some(command) => crate::notification::run_command(command),
+            None => crate::notification::send(summary, body),
+        }
+    }
+}
+
+/// formats a duration as `MM:SS`, or `H:MM:SS` when it is longer than an hour.
+pub fn format_duration(duration: TimeDelta) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, 10, hour, minute, second)
+            .unwrap()
+    }
+
+    fn config() -> TimerConfig {
+        TimerConfig {
+            scroll_step_minutes: 5,
+            ..TimerConfig::default()
+        }
+    }
+
+    #[test]
+    fn sets_the_countdown_by_scrolling_only_while_paused() {
+        let mut timers = Timers::new(&config());
+        timers.adjust_countdown(-1);
+        assert_eq!(timers.countdown.remaining(at(9, 0, 0)), TimeDelta::zero());
+        timers.adjust_countdown(3);
+        timers.adjust_countdown(-1);
+        assert_eq!(
+            timers.countdown.remaining(at(9, 0, 0)),
+            TimeDelta::minutes(10)
+        );
+
+        timers.toggle(TimerKind::Countdown, at(9, 0, 0));
+        timers.adjust_countdown(1);
+        assert_eq!(timers.countdown.deadline(), Some(at(9, 10, 0)));
+    }
+
+    #[test]
+    fn starts_pauses_and_resets_the_countdown() {
+        let mut timers = Timers::new(&config());
+        // an empty countdown doesn't start
+        timers.toggle(TimerKind::Countdown, at(9, 0, 0));
+        assert!(!timers.countdown.is_running());
+
+        timers.adjust_countdown(2);
+        timers.toggle(TimerKind::Countdown, at(9, 0, 0));
+        assert_eq!(
+            timers.countdown.remaining(at(9, 4, 0)),
+            TimeDelta::minutes(6)
+        );
+        timers.toggle(TimerKind::Countdown, at(9, 4, 0));
+        assert!(!timers.countdown.is_running());
+        assert_eq!(
+            timers.countdown.remaining(at(12, 0, 0)),
+            TimeDelta::minutes(6)
+        );
+
+        timers.toggle(TimerKind::Countdown, at(12, 0, 0));
+        assert_eq!(timers.next_deadline(), Some(at(12, 6, 0)));
+        timers.check_deadlines(at(12, 6, 0));
+        assert!(!timers.countdown.is_running());
+        assert_eq!(timers.countdown.remaining(at(12, 6, 0)), TimeDelta::zero());
+
+        timers.adjust_countdown(1);
+        timers.reset(TimerKind::Countdown);
+        assert_eq!(timers.countdown.remaining(at(12, 6, 0)), TimeDelta::zero());
+    }
+
+    #[test]
+    fn starts_pauses_and_resets_the_stopwatch() {
+        let mut timers = Timers::new(&config());
+        timers.toggle(TimerKind::Stopwatch, at(9, 0, 0));
+        assert_eq!(
+            timers.stopwatch.elapsed(at(9, 1, 30)),
+            TimeDelta::seconds(90)
+        );
+        timers.toggle(TimerKind::Stopwatch, at(9, 1, 30));
+        timers.toggle(TimerKind::Stopwatch, at(10, 0, 0));
+        assert_eq!(
+            timers.stopwatch.elapsed(at(10, 0, 30)),
+            TimeDelta::minutes(2)
+        );
+        // the stopwatch has no deadline
+        assert_eq!(timers.next_deadline(), None);
+
+        timers.reset(TimerKind::Stopwatch);
+        assert!(!timers.stopwatch.is_running());
+        assert_eq!(timers.stopwatch.elapsed(at(11, 0, 0)), TimeDelta::zero());
+    }
+
+    #[test]
+    fn alternates_pomodoro_work_and_breaks() {
+        let mut timers = Timers::new(&config());
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Work);
+        timers.toggle(TimerKind::Pomodoro, at(9, 0, 0));
+        assert_eq!(timers.next_deadline(), Some(at(9, 25, 0)));
+
+        timers.check_deadlines(at(9, 24, 59));
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Work);
+        timers.check_deadlines(at(9, 25, 0));
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Break);
+        assert_eq!(timers.pomodoro.countdown.deadline(), Some(at(9, 30, 0)));
+        timers.check_deadlines(at(9, 30, 0));
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Work);
+        assert_eq!(timers.pomodoro.countdown.deadline(), Some(at(9, 55, 0)));
+
+        timers.reset(TimerKind::Pomodoro);
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Work);
+        assert!(!timers.pomodoro.countdown.is_running());
+        assert_eq!(
+            timers.pomodoro.countdown.remaining(at(10, 0, 0)),
+            TimeDelta::minutes(25)
+        );
+    }
+
+    #[test]
+    fn restores_timers_whose_deadline_passed_while_stopped() {
+        let state = FakeTree::new();
+        crate::config::set_state_directory(state.path());
+
+        let mut timers = Timers::load(&config());
+        timers.adjust_countdown(1);
+        timers.toggle(TimerKind::Countdown, at(9, 0, 0));
+        timers.toggle(TimerKind::Stopwatch, at(9, 0, 0));
+        timers.toggle(TimerKind::Pomodoro, at(9, 0, 0));
+        drop(timers);
+
+        let mut timers = Timers::load(&config());
+        assert_eq!(timers.countdown.deadline(), Some(at(9, 5, 0)));
+        assert_eq!(timers.stopwatch.elapsed(at(11, 0, 0)), TimeDelta::hours(2));
+        timers.check_deadlines(at(11, 0, 0));
+        assert!(!timers.countdown.is_running());
+        // the next phase starts when we notice the end of the previous one
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Break);
+        assert_eq!(timers.pomodoro.countdown.deadline(), Some(at(11, 5, 0)));
+
+        let timers = Timers::load(&config());
+        assert!(!timers.countdown.is_running());
+        assert_eq!(timers.pomodoro.phase, PomodoroPhase::Break);
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    battery_display::BatteryConfig, clock::ClockConfig, disk::DiskConfig, memory::MemoryConfig,
+    network::throughput::ThroughputConfig, temperature::TemperatureConfig,
+};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// The user configuration, read from `$XDG_CONFIG_HOME/widgets/config.json`. Every field is
+/// optional, and a missing or broken file just means the defaults are used.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub clock: ClockConfig,
+    pub battery: BatteryConfig,
+    pub throughput: ThroughputConfig,
+    pub memory: MemoryConfig,
+    pub temperature: TemperatureConfig,
+    pub disk: DiskConfig,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = match xdg::BaseDirectories::with_prefix("widgets")
+            .map(|base_directories| base_directories.find_config_file(CONFIG_FILE))
+        {
+            Ok(Some(path)) => path,
+            Ok(None) => return Self::default(),
+            Err(e) => {
+                log::error!("Unable to access config directory : {}", e);
+                return Self::default();
+            }
+        };
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::error!("Unable to read config file {} : {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        match serde_json::from_str(&raw) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Unable to parse config file {} : {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Reads a value saved with `save_state` from `$XDG_STATE_HOME/widgets/`. Returns `None` if
+/// there is no such file, or if it can't be read, which is logged.
+pub fn load_state<T: DeserializeOwned>(file: &str) -> Option<T> {
+    let path = match find_state_file(file) {
+        Ok(path) => path?,
+        Err(e) => {
+            log::error!("Unable to access state directory : {}", e);
+            return None;
+        }
+    };
+
+    match std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|raw| serde_json::from_str(&raw).map_err(|e| e.to_string()))
+    {
+        Ok(state) => Some(state),
+        Err(e) => {
+            log::error!("Unable to restore state from {} : {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Saves a value to `$XDG_STATE_HOME/widgets/`, so that it survives restarts. Errors are logged.
+pub fn save_state<T: Serialize>(file: &str, state: &T) {
+    let result = place_state_file(file).and_then(|path| {
+        let raw = serde_json::to_string(state).map_err(|e| e.to_string())?;
+        std::fs::write(path, raw).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        log::error!("Unable to save {} : {}", file, e);
+    }
+}
+
+#[cfg(not(test))]
+fn find_state_file(file: &str) -> Result<Option<PathBuf>, String> {
+    xdg::BaseDirectories::with_prefix("widgets")
+        .map(|base_directories| base_directories.find_state_file(file))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(test))]
+fn place_state_file(file: &str) -> Result<PathBuf, String> {
+    xdg::BaseDirectories::with_prefix("widgets")
+        .map_err(|e| e.to_string())
+        .and_then(|base_directories| {
+            base_directories
+                .place_state_file(file)
+                .map_err(|e| e.to_string())
+        })
+}
+
+// The tests never touch the state of the user. Each of them can give its own directory, and
+// has no state directory otherwise.
+#[cfg(test)]
+thread_local! {
+    static STATE_DIRECTORY: std::cell::RefCell<Option<PathBuf>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Keeps the state files of the current test in `directory`.
+#[cfg(test)]
+pub fn set_state_directory(directory: &std::path::Path) {
+    STATE_DIRECTORY.with(|state_directory| *state_directory.borrow_mut() = Some(directory.into()));
+}
+
+#[cfg(test)]
+fn find_state_file(file: &str) -> Result<Option<PathBuf>, String> {
+    place_state_file(file).map(|path| path.exists().then_some(path))
+}
+
+#[cfg(test)]
+fn place_state_file(file: &str) -> Result<PathBuf, String> {
+    STATE_DIRECTORY
+        .with(|state_directory| state_directory.borrow().clone())
+        .map(|directory| directory.join(file))
+        .ok_or_else(|| "no state directory in this test".to_string())
+}
@@ -0,0 +1,335 @@
+//! The CPU usage, from the times of `/proc/stat`, and the processes using it the most.
+
+pub mod procfs;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use iced::{
+    subscription,
+    widget::{column, container, mouse_area, row, scrollable, text, vertical_space, Column},
+    Element, Length, Padding, Subscription,
+};
+
+use crate::style::{self, ContainerStyle};
+
+use procfs::{CpuError, ProcessTimes, Stat};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Delay before reading `/proc/stat` again when it fails, which should never last.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// How many processes the popup lists.
+const TOP_PROCESSES: usize = 10;
+/// the characters of the per-core bars, from idle to fully busy
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug, Clone)]
+pub struct TopProcess {
+    pub pid: u32,
+    pub name: String,
+    /// in percents of one core, like `top` does, so it goes above 100% for several threads
+    pub percent: f32,
+}
+
+/// The usage between two readings, in percents.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub total: f32,
+    pub cores: Vec<f32>,
+    /// from the busiest, `None` when the processes weren't read
+    pub top: Option<Vec<TopProcess>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CpuMessage {
+    NewUsage(Usage),
+    Error(String),
+    /// open or close the popup with the top processes
+    TogglePopup,
+}
+
+/// A reading of the CPU times, which the usage is computed from with the next one.
+struct Sample {
+    stat: Stat,
+    /// only read while the popup is open, as it goes through every process
+    processes: Option<HashMap<u32, ProcessTimes>>,
+}
+
+fn read_sample(root: &Path, with_processes: bool) -> Result<Sample, CpuError> {
+    Ok(Sample {
+        stat: procfs::read_stat(root)?,
+        processes: match with_processes {
+            true => Some(procfs::read_processes(root)?),
+            false => None,
+        },
+    })
+}
+
+/// Reads a sample on a blocking thread, so that going through the processes doesn't hold up the
+/// other subscriptions.
+async fn read_sample_in_background(root: PathBuf, with_processes: bool) -> Result<Sample, String> {
+    match tokio::task::spawn_blocking(move || read_sample(&root, with_processes)).await {
+        Ok(Ok(sample)) => Ok(sample),
+        Ok(Err(e)) => Err(e.to_string()),
+        // the blocking task panicked
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn usage(previous: &Sample, current: &Sample) -> Usage {
+    let cores = current
+        .stat
+        .cores
+        .iter()
+        .zip(&previous.stat.cores)
+        .map(|(current, previous)| current.usage_since(previous))
+        .collect::<Vec<_>>();
+
+    // the time of one core during the interval
+    let core_ticks = current
+        .stat
+        .total
+        .total
+        .saturating_sub(previous.stat.total.total) as f32
+        / cores.len().max(1) as f32;
+    let top = previous
+        .processes
+        .as_ref()
+        .zip(current.processes.as_ref())
+        .map(|(before, processes)| {
+            let mut top = processes
+                .iter()
+                .filter_map(|(pid, process)| {
+                    // the processes that started during the interval are counted from the next one
+                    let before = before.get(pid)?;
+                    let ticks = process.ticks.saturating_sub(before.ticks);
+                    (ticks > 0 && core_ticks > 0.0).then(|| TopProcess {
+                        pid: *pid,
+                        name: process.name.clone(),
+                        percent: ticks as f32 * 100.0 / core_ticks,
+                    })
+                })
+                .collect::<Vec<_>>();
+            top.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+            top.truncate(TOP_PROCESSES);
+            top
+        });
+
+    Usage {
+        total: current.stat.total.usage_since(&previous.stat.total),
+        cores,
+        top,
+    }
+}
+
+/// Shows the total CPU usage with a bar per core, and opens a popup with the processes using it
+/// the most when clicked.
+pub struct CpuDisplay {
+    /// `/proc`, or another directory with the same layout
+    root: PathBuf,
+    usage: Option<Usage>,
+    error: Option<String>,
+}
+
+impl CpuDisplay {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            usage: None,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: CpuMessage) {
+        match message {
+            CpuMessage::NewUsage(usage) => {
+                self.usage = Some(usage);
+                self.error = None;
+            }
+            CpuMessage::Error(e) => {
+                self.usage = None;
+                self.error = Some(e);
+            }
+            CpuMessage::TogglePopup => (),
+        }
+    }
+
+    /// Reads the times every `POLL_INTERVAL`, and sends the usage since the previous reading. The
+    /// processes are only read `with_processes`, i.e. while the popup is open; the subscription
+    /// starts over when that changes.
+    pub fn subscription(&self, with_processes: bool) -> Subscription<CpuMessage> {
+        struct CpuUpdates;
+
+        subscription::unfold(
+            (std::any::TypeId::of::<CpuUpdates>(), with_processes),
+            (self.root.clone(), None),
+            move |(root, previous): (PathBuf, Option<Sample>)| async move {
+                let previous = match previous {
+                    Some(previous) => previous,
+                    None => match read_sample_in_background(root.clone(), with_processes).await {
+                        Ok(sample) => sample,
+                        Err(e) => {
+                            log::error!("Unable to read the CPU times : {}", e);
+                            tokio::time::sleep(RETRY_INTERVAL).await;
+                            return (CpuMessage::Error(e), (root, None));
+                        }
+                    },
+                };
+                tokio::time::sleep(POLL_INTERVAL).await;
+                match read_sample_in_background(root.clone(), with_processes).await {
+                    Ok(current) => (
+                        CpuMessage::NewUsage(usage(&previous, &current)),
+                        (root, Some(current)),
+                    ),
+                    Err(e) => {
+                        log::error!("Unable to read the CPU times : {}", e);
+                        (CpuMessage::Error(e), (root, None))
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, CpuMessage> {
+        let label = match &self.usage {
+            Some(usage) => {
+                let bars = usage
+                    .cores
+                    .iter()
+                    .map(|core| {
+                        BARS[((core.min(100.0) / 100.0) * (BARS.len() - 1) as f32).round() as usize]
+                    })
+                    .collect::<String>();
+                format!("󰻠 {:.0}% {}", usage.total, bars)
+            }
+            None => "󰻠 ?".to_string(),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme());
+
+        mouse_area(content).on_press(CpuMessage::TogglePopup).into()
+    }
+
+    /// the content of the popup, with the usage of each core and the top processes
+    pub fn view_popup(&self) -> Element<'_, CpuMessage> {
+        let content = match (&self.usage, &self.error) {
+            (Some(usage), _) => {
+                let cores = usage
+                    .cores
+                    .iter()
+                    .enumerate()
+                    .map(|(index, core)| {
+                        row![
+                            text(format!("Cœur {}", index))
+                                .style(iced::Color::WHITE)
+                                .width(Length::Fill),
+                            text(format!("{:.0}%", core)).style(iced::Color::WHITE)
+                        ]
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+
+                let processes = usage
+                    .top
+                    .iter()
+                    .flatten()
+                    .map(|process| {
+                        row![
+                            text(format!("{} ({})", process.name, process.pid))
+                                .style(iced::Color::WHITE)
+                                .width(Length::Fill),
+                            text(format!("{:.0}%", process.percent)).style(iced::Color::WHITE)
+                        ]
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+                let processes = if usage.top.is_none() {
+                    column![text("Mesure en cours").style(iced::Color::WHITE)]
+                } else if processes.is_empty() {
+                    column![text("Aucun processus actif").style(iced::Color::WHITE)]
+                } else {
+                    Column::with_children(processes).spacing(5)
+                };
+
+                column![
+                    text("Processus").style(iced::Color::WHITE),
+                    processes,
+                    text("Cœurs").style(iced::Color::WHITE),
+                    Column::with_children(cores).spacing(5)
+                ]
+                .spacing(10)
+            }
+            (None, Some(e)) => column![text(e).style(iced::Color::WHITE)],
+            (None, None) => column![text("Mesure en cours").style(iced::Color::WHITE)],
+        };
+
+        style::popup(scrollable(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{procfs::CpuTimes, *};
+    use crate::testing::fixture;
+
+    fn sample(busy: u64, total: u64, processes: Option<&[(u32, u64)]>) -> Sample {
+        Sample {
+            stat: Stat {
+                total: CpuTimes { busy, total },
+                cores: vec![
+                    CpuTimes {
+                        busy: busy / 2,
+                        total: total / 2,
+                    };
+                    2
+                ],
+            },
+            processes: processes.map(|processes| {
+                processes
+                    .iter()
+                    .map(|&(pid, ticks)| {
+                        let name = format!("process {}", pid);
+                        (pid, ProcessTimes { name, ticks })
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    #[test]
+    fn computes_the_usage_between_two_samples() {
+        // a process starts during the interval
+        let previous = sample(100, 400, Some(&[(1, 10), (2, 50)]));
+        let current = sample(300, 800, Some(&[(1, 10), (2, 150), (3, 20)]));
+        let usage = usage(&previous, &current);
+        assert_eq!(usage.total, 50.0);
+        assert_eq!(usage.cores, [50.0, 50.0]);
+        let top = usage.top.unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].pid, top[0].percent), (2, 50.0));
+    }
+
+    #[test]
+    fn leaves_out_the_processes_until_they_are_read_twice() {
+        let previous = sample(100, 400, None);
+        let current = sample(300, 800, Some(&[(1, 10)]));
+        assert!(usage(&previous, &current).top.is_none());
+    }
+
+    #[test]
+    fn reads_a_sample_with_the_processes_only_when_asked() {
+        let sample = read_sample(&fixture("proc"), false).unwrap();
+        assert!(sample.processes.is_none());
+        let sample = read_sample(&fixture("proc"), true).unwrap();
+        assert_eq!(sample.processes.unwrap().len(), 3);
+    }
+}
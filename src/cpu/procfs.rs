@@ -0,0 +1,250 @@
+//! Parsing the CPU times of `/proc/stat` and `/proc/[pid]/stat`, in clock ticks.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+pub const PROC_ROOT: &str = "/proc";
+
+#[derive(Debug)]
+pub enum CpuError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParsingError {
+        path: PathBuf,
+        line: String,
+    },
+}
+
+impl Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::ParsingError { path, line } => {
+                writeln!(f, "Unable to parse a line of {}", path.display())?;
+                write!(f, "Got '{}'", line)
+            }
+        }
+    }
+}
+
+/// The time a CPU spent since boot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// The share of the time spent busy since `previous`, in percents.
+    pub fn usage_since(&self, previous: &CpuTimes) -> f32 {
+        let total = self.total.saturating_sub(previous.total);
+        let busy = self.busy.saturating_sub(previous.busy);
+        match total {
+            0 => 0.0,
+            _ => busy as f32 * 100.0 / total as f32,
+        }
+    }
+}
+
+/// The times of all the CPUs together, and of each core.
+#[derive(Debug, Clone, Default)]
+pub struct Stat {
+    pub total: CpuTimes,
+    pub cores: Vec<CpuTimes>,
+}
+
+/// Parses the content of `/proc/stat`, whose `cpu` line sums up the `cpuN` ones, such as
+/// `cpu0 4705 356 584 3699176 23060 0 277 0 0 0`, with the user, nice, system, idle, iowait, irq,
+/// softirq, steal, guest and guest_nice times. The guest times are already part of the user ones.
+pub fn parse_stat(path: &Path, raw: &str) -> Result<Stat, CpuError> {
+    let mut stat = Stat::default();
+    for line in raw.lines().filter(|line| line.starts_with("cpu")) {
+        let parsing_error = || CpuError::ParsingError {
+            path: path.into(),
+            line: line.into(),
+        };
+        let mut columns = line.split_whitespace();
+        let name = columns.next().ok_or_else(parsing_error)?;
+        let times = columns
+            .take(8)
+            .map(str::parse::<u64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| parsing_error())?;
+        // only the first seven times are required, as kernels older than 2.6.11 don't have the
+        // steal one
+        if times.len() < 7 {
+            return Err(parsing_error());
+        }
+        let idle = times[3] + times[4];
+        let total = times.iter().sum::<u64>();
+        let times = CpuTimes {
+            busy: total - idle,
+            total,
+        };
+        match name {
+            "cpu" => stat.total = times,
+            _ => stat.cores.push(times),
+        }
+    }
+    Ok(stat)
+}
+
+/// The CPU time of a process since it started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessTimes {
+    pub name: String,
+    /// the user and system times
+    pub ticks: u64,
+}
+
+/// Parses the content of `/proc/[pid]/stat`, such as `1234 (my process) S 1 ...`, where the
+/// name is in parentheses and may contain spaces and parentheses itself. The user and system
+/// times are the 14th and 15th fields.
+pub fn parse_process_stat(path: &Path, raw: &str) -> Result<ProcessTimes, CpuError> {
+    let parsing_error = || CpuError::ParsingError {
+        path: path.into(),
+        line: raw.trim().into(),
+    };
+    let (start, end) = raw
+        .find('(')
+        .zip(raw.rfind(')'))
+        .ok_or_else(parsing_error)?;
+    let name = raw.get(start + 1..end).ok_or_else(parsing_error)?;
+    // the fields after the name start with the third one, the state
+    let fields = raw[end + 1..].split_whitespace().collect::<Vec<_>>();
+    let time = |index: usize| {
+        fields
+            .get(index - 3)
+            .and_then(|field| field.parse::<u64>().ok())
+            .ok_or_else(parsing_error)
+    };
+    Ok(ProcessTimes {
+        name: name.into(),
+        ticks: time(14)? + time(15)?,
+    })
+}
+
+fn read(path: PathBuf) -> Result<String, CpuError> {
+    std::fs::read_to_string(&path).map_err(|error| CpuError::IoError { path, error })
+}
+
+pub fn read_stat(root: &Path) -> Result<Stat, CpuError> {
+    let path = root.join("stat");
+    parse_stat(&path, &read(path.clone())?)
+}
+
+/// Reads the times of all the processes, by pid. The processes that exit while they are read are
+/// just left out, which is common enough to only log it for debugging.
+pub fn read_processes(root: &Path) -> Result<HashMap<u32, ProcessTimes>, CpuError> {
+    let entries = std::fs::read_dir(root).map_err(|error| CpuError::IoError {
+        path: root.into(),
+        error,
+    })?;
+    Ok(entries
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse::<u32>().ok()?;
+            let path = root.join(pid.to_string()).join("stat");
+            match read(path.clone()).and_then(|raw| parse_process_stat(&path, &raw)) {
+                Ok(times) => Some((pid, times)),
+                Err(e) => {
+                    log::debug!("{}", e);
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn parses_the_times_with_the_steal_one() {
+        let stat = read_stat(&fixture("proc")).unwrap();
+        assert_eq!(
+            stat.total,
+            CpuTimes {
+                busy: 1513544,
+                total: 19991306
+            }
+        );
+        assert_eq!(stat.cores.len(), 4);
+        assert_eq!(
+            stat.cores[0],
+            CpuTimes {
+                busy: 381736,
+                total: 4998810
+            }
+        );
+    }
+
+    #[test]
+    fn parses_the_times_without_the_steal_one() {
+        let stat = read_stat(&fixture("proc-without-steal")).unwrap();
+        assert_eq!(
+            stat.total,
+            CpuTimes {
+                busy: 102566,
+                total: 3301190
+            }
+        );
+        assert_eq!(stat.cores.len(), 2);
+    }
+
+    #[test]
+    fn rejects_a_line_with_too_few_times() {
+        let raw = "cpu  79523 1067 20931 3187240 11384 214\n";
+        assert!(matches!(
+            parse_stat(Path::new("stat"), raw),
+            Err(CpuError::ParsingError { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_a_name_with_spaces_and_parentheses() {
+        let path = fixture("proc/4242/stat");
+        let times = parse_process_stat(&path, &std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            times,
+            ProcessTimes {
+                name: "Web Content (1)".into(),
+                ticks: 91824 + 12377
+            }
+        );
+    }
+
+    #[test]
+    fn reads_every_process() {
+        let processes = read_processes(&fixture("proc")).unwrap();
+        let mut pids = processes.keys().copied().collect::<Vec<_>>();
+        pids.sort();
+        assert_eq!(pids, [1, 2, 4242]);
+        assert_eq!(processes[&1].name, "systemd");
+        assert_eq!(processes[&1].ticks, 2841 + 1570);
+    }
+
+    #[test]
+    fn computes_the_usage_since_a_previous_reading() {
+        let previous = CpuTimes {
+            busy: 1_000,
+            total: 4_000,
+        };
+        let current = CpuTimes {
+            busy: 1_300,
+            total: 4_400,
+        };
+        assert_eq!(current.usage_since(&previous), 75.0);
+        assert_eq!(current.usage_since(&current), 0.0);
+        // the times never go back, unless the reading is broken
+        assert_eq!(previous.usage_since(&current), 0.0);
+    }
+}
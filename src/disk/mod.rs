@@ -0,0 +1,246 @@
+//! The free space of the filesystems.
+
+pub mod mounts;
+
+use std::time::Duration;
+
+use iced::{
+    color,
+    futures::future::join_all,
+    subscription,
+    widget::{column, container, mouse_area, row, scrollable, text, vertical_space, Column},
+    Element, Length, Padding, Subscription,
+};
+
+use crate::{
+    format::format_size,
+    style::{self, ContainerStyle},
+};
+use serde::Deserialize;
+
+use mounts::{Mount, Pending, Usage};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// the filesystems shown in the bar
+    pub mount_points: Vec<String>,
+    /// below this share of free space, the widget turns orange
+    pub warning_percent: u32,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            mount_points: vec!["/".into(), "/home".into()],
+            warning_percent: 10,
+        }
+    }
+}
+
+/// The usage of a filesystem, or why it couldn't be read.
+#[derive(Debug, Clone)]
+pub struct FilesystemUsage {
+    pub mount: Mount,
+    pub usage: Result<Usage, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reading {
+    /// the configured mount points, in order, if they exist
+    pub configured: Vec<FilesystemUsage>,
+    /// all the real filesystems, for the popup
+    pub all: Vec<FilesystemUsage>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DiskMessage {
+    NewReading(Reading),
+    Error(String),
+    /// open or close the popup with all the filesystems
+    TogglePopup,
+}
+
+async fn read(mount_points: &[String], pending: &Pending) -> Result<Reading, mounts::DiskError> {
+    let all = mounts::read_mounts(mount_points)?;
+    // the filesystems are read at once, so that an unreachable one only delays the others by
+    // the timeout once
+    let usages = join_all(
+        all.iter()
+            .map(|mount| mounts::usage(mount.mount_point.clone(), pending.clone())),
+    )
+    .await;
+    let all = all
+        .into_iter()
+        .zip(usages)
+        .map(|(mount, usage)| FilesystemUsage {
+            mount,
+            usage: usage.map_err(|e| {
+                log::error!("{}", e);
+                e.to_string()
+            }),
+        })
+        .collect::<Vec<_>>();
+    let configured = mount_points
+        .iter()
+        .filter_map(|mount_point| {
+            all.iter()
+                .find(|filesystem| filesystem.mount.mount_point == *mount_point)
+                .cloned()
+        })
+        .collect();
+    Ok(Reading { configured, all })
+}
+
+/// Shows the free space of the configured filesystems, and opens a popup with all of them when
+/// clicked. It turns orange when one of them is almost full.
+pub struct DiskDisplay {
+    config: DiskConfig,
+    reading: Option<Reading>,
+    error: Option<String>,
+}
+
+impl DiskDisplay {
+    pub fn new(config: &DiskConfig) -> Self {
+        Self {
+            config: config.clone(),
+            reading: None,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: DiskMessage) {
+        match message {
+            DiskMessage::NewReading(reading) => {
+                self.reading = Some(reading);
+                self.error = None;
+            }
+            DiskMessage::Error(e) => {
+                self.reading = None;
+                self.error = Some(e);
+            }
+            DiskMessage::TogglePopup => (),
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<DiskMessage> {
+        struct DiskUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<DiskUpdates>(),
+            (self.config.mount_points.clone(), Pending::default(), true),
+            |(mount_points, pending, first)| async move {
+                if !first {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                let message = match read(&mount_points, &pending).await {
+                    Ok(reading) => DiskMessage::NewReading(reading),
+                    Err(e) => {
+                        log::error!("Unable to list the filesystems : {}", e);
+                        DiskMessage::Error(e.to_string())
+                    }
+                };
+                (message, (mount_points, pending, false))
+            },
+        )
+    }
+
+    fn is_low(&self, usage: &Usage) -> bool {
+        usage.free_percent() < self.config.warning_percent
+    }
+
+    pub fn view(&self) -> Element<'_, DiskMessage> {
+        let (label, low) = match &self.reading {
+            Some(reading) => {
+                let filesystems = reading
+                    .configured
+                    .iter()
+                    .map(|filesystem| match &filesystem.usage {
+                        Ok(usage) => format!(
+                            "{} {}",
+                            filesystem.mount.mount_point,
+                            format_size(usage.free)
+                        ),
+                        Err(_) => format!("{} ?", filesystem.mount.mount_point),
+                    })
+                    .collect::<Vec<_>>();
+                let low = reading.configured.iter().any(|filesystem| {
+                    filesystem
+                        .usage
+                        .as_ref()
+                        .is_ok_and(|usage| self.is_low(usage))
+                });
+                (format!("󰋊 {}", filesystems.join(" ")), low)
+            }
+            None => ("󰋊 ?".to_string(), false),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(
+            ContainerStyle {
+                background: match low {
+                    true => color!(0xd65d0e),
+                    false => color!(0x282828),
+                },
+            }
+            .theme(),
+        );
+
+        mouse_area(content)
+            .on_press(DiskMessage::TogglePopup)
+            .into()
+    }
+
+    /// the content of the popup, with the free space of all the real filesystems
+    pub fn view_popup(&self) -> Element<'_, DiskMessage> {
+        let content = match (&self.reading, &self.error) {
+            (Some(reading), _) => {
+                let filesystems = reading
+                    .all
+                    .iter()
+                    .map(|filesystem| {
+                        let (usage, color) = match &filesystem.usage {
+                            Ok(usage) => (
+                                format!(
+                                    "{} / {}",
+                                    format_size(usage.free),
+                                    format_size(usage.total)
+                                ),
+                                match self.is_low(usage) {
+                                    true => color!(0xd65d0e),
+                                    false => iced::Color::WHITE,
+                                },
+                            ),
+                            Err(_) => ("indisponible".to_string(), color!(0xcc241d)),
+                        };
+                        row![
+                            column![
+                                text(&filesystem.mount.mount_point).style(iced::Color::WHITE),
+                                text(format!(
+                                    "{} ({})",
+                                    filesystem.mount.device, filesystem.mount.filesystem
+                                ))
+                                .style(color!(0x808080))
+                            ]
+                            .width(Length::Fill),
+                            text(usage).style(color)
+                        ]
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+                Column::with_children(filesystems).spacing(10)
+            }
+            (None, Some(e)) => column![text(e).style(iced::Color::WHITE)],
+            (None, None) => column![text("Lecture en cours").style(iced::Color::WHITE)],
+        };
+
+        style::popup(scrollable(content))
+    }
+}
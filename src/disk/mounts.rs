@@ -0,0 +1,272 @@
+//! The mounted filesystems, from `/proc/self/mounts`, and their usage, from `statvfs`.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use nix::sys::statvfs::statvfs;
+
+pub const MOUNTS: &str = "/proc/self/mounts";
+/// How long to wait for `statvfs`, which hangs on unreachable network filesystems.
+const STATVFS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The filesystems which don't store anything on a disk, and only clutter the list.
+const PSEUDO_FILESYSTEMS: [&str; 25] = [
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "fuse.gvfsd-fuse",
+    "fuse.portal",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "squashfs",
+    "sysfs",
+    "tracefs",
+];
+
+#[derive(Debug)]
+pub enum DiskError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    StatvfsError {
+        mount_point: String,
+        error: nix::Error,
+    },
+    /// `statvfs` didn't answer in time, e.g. for a network filesystem whose server is down
+    Unreachable { mount_point: String },
+}
+
+impl Display for DiskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::StatvfsError { mount_point, error } => {
+                write!(f, "Unable to get the usage of {} : {}", mount_point, error)
+            }
+            Self::Unreachable { mount_point } => write!(f, "{} doesn't answer", mount_point),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub device: String,
+    pub mount_point: String,
+    pub filesystem: String,
+}
+
+/// The fields of `/proc/self/mounts` escape the spaces, tabs, newlines and backslashes in octal,
+/// e.g. `\040` for a space.
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let code = rest
+            .get(index + 1..index + 4)
+            .and_then(|code| u8::from_str_radix(code, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(code as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Parses the content of `/proc/self/mounts`, such as `/dev/nvme0n1p2 / ext4 rw,relatime 0 0`,
+/// and keeps the real filesystems, each once even when it is mounted at several places, unless
+/// the mount point is one of `kept`. A btrfs `/home` is on the same device as `/`, but it is still
+/// shown when configured.
+pub fn parse_mounts(raw: &str, kept: &[String]) -> Vec<Mount> {
+    let mut mounts: Vec<Mount> = Vec::new();
+    for line in raw.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(device), Some(mount_point), Some(filesystem)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (device, mount_point) = (unescape(device), unescape(mount_point));
+        // the memory backed filesystems are everywhere, only the one of `/tmp` can fill up
+        if PSEUDO_FILESYSTEMS.contains(&filesystem)
+            || (filesystem == "tmpfs" && mount_point != "/tmp")
+        {
+            continue;
+        }
+        // bind mounts and btrfs subvolumes show the same device several times
+        if device.starts_with('/')
+            && !kept.contains(&mount_point)
+            && mounts.iter().any(|mount| mount.device == device)
+        {
+            continue;
+        }
+        mounts.push(Mount {
+            device,
+            mount_point,
+            filesystem: filesystem.into(),
+        });
+    }
+    mounts
+}
+
+pub fn read_mounts(kept: &[String]) -> Result<Vec<Mount>, DiskError> {
+    std::fs::read_to_string(MOUNTS)
+        .map(|raw| parse_mounts(&raw, kept))
+        .map_err(|error| DiskError::IoError {
+            path: MOUNTS.into(),
+            error,
+        })
+}
+
+/// The free and total space of a filesystem, in bytes. The free space is what unprivileged
+/// users can use, without the blocks reserved for root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub free: u64,
+    pub total: u64,
+}
+
+impl Usage {
+    pub fn free_percent(&self) -> u32 {
+        match self.total {
+            0 => 100,
+            total => (self.free * 100 / total) as u32,
+        }
+    }
+}
+
+/// The mount points whose `statvfs` hasn't returned yet, shared by the readings.
+#[derive(Debug, Clone, Default)]
+pub struct Pending(Arc<Mutex<HashSet<String>>>);
+
+/// Calls `statvfs` on a blocking thread, so that an unreachable network filesystem doesn't block
+/// the bar. The thread is left hanging in that case, and the next readings of that filesystem
+/// fail right away until it returns, rather than leaving one more thread hanging each time.
+pub async fn usage(mount_point: String, pending: Pending) -> Result<Usage, DiskError> {
+    if !pending.0.lock().unwrap().insert(mount_point.clone()) {
+        return Err(DiskError::Unreachable { mount_point });
+    }
+    let path = mount_point.clone();
+    let stat = tokio::task::spawn_blocking(move || {
+        let stat = statvfs(path.as_str());
+        pending.0.lock().unwrap().remove(&path);
+        stat
+    });
+    match tokio::time::timeout(STATVFS_TIMEOUT, stat).await {
+        // the types of the statvfs fields depend on the platform
+        #[allow(clippy::unnecessary_cast)]
+        Ok(Ok(Ok(stat))) => Ok(Usage {
+            free: stat.blocks_available() as u64 * stat.fragment_size() as u64,
+            total: stat.blocks() as u64 * stat.fragment_size() as u64,
+        }),
+        Ok(Ok(Err(error))) => Err(DiskError::StatvfsError { mount_point, error }),
+        // the blocking task panicked, or took too long
+        Ok(Err(_)) | Err(_) => Err(DiskError::Unreachable { mount_point }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+
+    const MOUNTS: &str = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0
+/dev/nvme0n1p2 / btrfs rw,relatime,ssd,subvol=/@ 0 0
+tmpfs /run tmpfs rw,nosuid,nodev,size=3264436k,mode=755 0 0
+/dev/nvme0n1p2 /home btrfs rw,relatime,ssd,subvol=/@home 0 0
+tmpfs /tmp tmpfs rw,nosuid,nodev,nr_inodes=1048576 0 0
+/dev/nvme0n1p1 /boot/efi vfat rw,relatime,fmask=0022,dmask=0022 0 0
+/dev/sda1 /run/media/alex/Mes\\040photos ext4 rw,nosuid,nodev,relatime 0 0
+nas:/volume1/films /mnt/films nfs4 rw,relatime,vers=4.1 0 0
+";
+
+    fn mount_points(mounts: &[Mount]) -> Vec<&str> {
+        mounts
+            .iter()
+            .map(|mount| mount.mount_point.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn unescapes_the_fields() {
+        assert_eq!(unescape("Mes\\040photos"), "Mes photos");
+        assert_eq!(unescape("a\\011b\\012c\\134d"), "a\tb\nc\\d");
+        assert_eq!(unescape("plain"), "plain");
+        // not an octal code, left as it is
+        assert_eq!(unescape("end\\"), "end\\");
+        assert_eq!(unescape("\\09x"), "\\09x");
+    }
+
+    #[test]
+    fn keeps_the_real_filesystems_once() {
+        let mounts = parse_mounts(MOUNTS, &[]);
+        assert_eq!(
+            mount_points(&mounts),
+            [
+                "/",
+                "/tmp",
+                "/boot/efi",
+                "/run/media/alex/Mes photos",
+                "/mnt/films"
+            ]
+        );
+        assert_eq!(
+            mounts[4],
+            Mount {
+                device: "nas:/volume1/films".into(),
+                mount_point: "/mnt/films".into(),
+                filesystem: "nfs4".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_the_configured_subvolumes() {
+        let mounts = parse_mounts(MOUNTS, &["/".into(), "/home".into()]);
+        assert_eq!(&mount_points(&mounts)[..2], ["/", "/home"]);
+    }
+
+    #[test]
+    fn waits_for_a_pending_statvfs() {
+        let pending = Pending::default();
+        pending.0.lock().unwrap().insert("/mnt/films".into());
+        let hung = block_on(usage("/mnt/films".into(), pending.clone()));
+        assert!(matches!(hung, Err(DiskError::Unreachable { .. })));
+
+        let root = block_on(usage("/".into(), pending.clone())).unwrap();
+        assert!(root.total > 0);
+        assert!(!pending.0.lock().unwrap().contains("/"));
+    }
+}
@@ -0,0 +1,30 @@
+//! Formatting shared by the widgets.
+
+/// e.g. `45 Go`, from a size in bytes
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["o", "Ko", "Mo", "Go", "To"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match size < 10.0 && unit > 0 {
+        true => format!("{:.1} {}", size, UNITS[unit]),
+        false => format!("{:.0} {}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_unit_and_the_precision() {
+        assert_eq!(format_size(512), "512 o");
+        assert_eq!(format_size(1536), "1.5 Ko");
+        assert_eq!(format_size(5_452_595_200), "5.1 Go");
+        assert_eq!(format_size(48_318_382_080), "45 Go");
+        assert_eq!(format_size(3 << 50), "3072 To");
+    }
+}
@@ -98,7 +98,7 @@ impl WorkspaceDisplay {
         }
     }
 
-    pub fn view(&self) -> Element<WorkspaceDisplayMessage> {
+    pub fn view(&self) -> Element<'_, WorkspaceDisplayMessage> {
         let buttons = self
             .window_count
             .iter()
@@ -1,3 +1,20 @@
+pub mod audio;
+pub mod backlight;
 pub mod battery_display;
 pub mod clock;
+pub mod config;
+pub mod cpu;
+pub mod disk;
+pub mod format;
 pub mod hyprland;
+pub mod memory;
+pub mod mpris;
+pub mod network;
+pub mod notification;
+pub mod power_profile;
+pub mod scroll_area;
+pub mod style;
+pub mod temperature;
+
+#[cfg(test)]
+mod testing;
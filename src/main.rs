@@ -1,17 +1,43 @@
 use iced::{
-    color, executor,
-    wayland::layer_surface::Anchor,
+    color,
+    event::wayland::{Event as WaylandEvent, PopupEvent},
+    event::PlatformSpecific,
+    executor,
+    wayland::{
+        actions::popup::{SctkPopupSettings, SctkPositioner},
+        layer_surface::Anchor,
+        popup::{destroy_popup, get_popup, Anchor as PopupAnchor, Gravity},
+    },
     widget::{container, horizontal_space, row, text, Container},
-    Application, Background, Command, Element, Length, Settings, Subscription, Theme,
+    window, Application, Background, Command, Element, Length, Rectangle, Settings, Subscription,
+    Theme,
 };
 
 use widgets::{
-    battery_display::{BatteryDisplay, BatteryMessage},
+    audio::{
+        subscription::AudioEvent,
+        ui::{MicrophoneDisplay, MicrophoneMessage, VolumeDisplay, VolumeMessage},
+        Audio,
+    },
+    backlight::{device::BACKLIGHT_ROOT, BacklightDisplay, BacklightMessage},
+    battery_display::{sysfs::POWER_SUPPLY_ROOT, BatteryDisplay, BatteryMessage},
     clock::{Clock, ClockMessage},
+    config::Config,
+    cpu::{procfs::PROC_ROOT, CpuDisplay, CpuMessage},
+    disk::{DiskDisplay, DiskMessage},
     hyprland::{
         subscription::HyprlandWorkspaceEvent,
         ui::{WorkspaceDisplay, WorkspaceDisplayMessage},
     },
+    memory::{MemoryDisplay, MemoryMessage},
+    mpris::{MprisDisplay, MprisMessage},
+    network::{
+        throughput::{ThroughputDisplay, ThroughputMessage},
+        NetworkDisplay, NetworkMessage,
+    },
+    power_profile::{PowerProfileDisplay, PowerProfileMessage},
+    style,
+    temperature::{sensors::SYSFS_CLASS_ROOT, TemperatureDisplay, TemperatureMessage},
 };
 
 use log::error;
@@ -19,19 +45,120 @@ use log::error;
 const HEIGHT: u32 = 25;
 const MARGIN: u32 = 5;
 const SCREEN_WIDTH: u32 = 1366;
+const POPUP_WIDTH: u32 = 400;
+const POPUP_HEIGHT: u32 = 300;
 
 #[derive(Debug, Clone)]
 enum ApplicationMessage {
     Workspace(WorkspaceDisplayMessage),
     Clock(ClockMessage),
     Battery(BatteryMessage),
+    PowerProfile(PowerProfileMessage),
+    Audio(AudioEvent),
+    Volume(VolumeMessage),
+    Microphone(MicrophoneMessage),
+    Network(NetworkMessage),
+    Throughput(ThroughputMessage),
+    Cpu(CpuMessage),
+    Memory(MemoryMessage),
+    Temperature(TemperatureMessage),
+    Backlight(BacklightMessage),
+    Disk(DiskMessage),
+    Mpris(MprisMessage),
+    PopupClosed(window::Id),
+}
+
+/// What is displayed in the popup surface. Only one popup can be open at a time.
+///
+/// The widgets can't create surfaces, so they only send a `TogglePopup` message when they are
+/// clicked. `MyWidgets::update` intercepts it to open or close the popup, and `MyWidgets::view`
+/// draws the popup with the `view_popup` of the widget that owns it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PopupContent {
+    Agenda,
+    Battery,
+    Audio,
+    Network,
+    Cpu,
+    Memory,
+    Temperature,
+    Disk,
+}
+
+impl PopupContent {
+    /// the horizontal position of the center of the widget that opened the popup, which the popup
+    /// is attached to
+    fn anchor_x(&self) -> i32 {
+        match self {
+            PopupContent::Agenda => (SCREEN_WIDTH / 2 - MARGIN) as i32,
+            // the battery display is at the right end of the bar, the compositor slides the
+            // popup back into the screen
+            PopupContent::Battery => (SCREEN_WIDTH - 2 * MARGIN - 50) as i32,
+            PopupContent::Audio => (SCREEN_WIDTH - 2 * MARGIN - 200) as i32,
+            PopupContent::Network => (SCREEN_WIDTH - 2 * MARGIN - 350) as i32,
+            PopupContent::Cpu => (SCREEN_WIDTH - 2 * MARGIN - 600) as i32,
+            PopupContent::Memory => (SCREEN_WIDTH - 2 * MARGIN - 700) as i32,
+            PopupContent::Temperature => (SCREEN_WIDTH - 2 * MARGIN - 780) as i32,
+            PopupContent::Disk => (SCREEN_WIDTH - 2 * MARGIN - 950) as i32,
+        }
+    }
 }
 
 /// the main app, that represents all of the widgets
 struct MyWidgets {
     workspace_display: Option<WorkspaceDisplay>,
     battery_display: Option<BatteryDisplay>,
+    power_profile: PowerProfileDisplay,
+    /// the state of the audio server, shown by both the volume and the microphone displays
+    audio: Option<Audio>,
+    volume: VolumeDisplay,
+    microphone: MicrophoneDisplay,
+    network: NetworkDisplay,
+    throughput: ThroughputDisplay,
+    cpu: CpuDisplay,
+    memory: MemoryDisplay,
+    temperature: TemperatureDisplay,
+    backlight: BacklightDisplay,
+    disk: DiskDisplay,
+    mpris: MprisDisplay,
     clock: Clock,
+    popup: Option<(window::Id, PopupContent)>,
+}
+
+impl MyWidgets {
+    /// Opens the popup with the given content, or closes it if it is already open.
+    fn toggle_popup(&mut self, content: PopupContent) -> Command<ApplicationMessage> {
+        match self.popup.take() {
+            Some((id, open_content)) if open_content == content => destroy_popup(id),
+            previous => {
+                let id = window::Id::unique();
+                self.popup = Some((id, content));
+                let open = get_popup(SctkPopupSettings {
+                    parent: window::Id::MAIN,
+                    id,
+                    positioner: SctkPositioner {
+                        size: Some((POPUP_WIDTH, POPUP_HEIGHT)),
+                        anchor_rect: Rectangle {
+                            x: content.anchor_x(),
+                            y: 0,
+                            width: 1,
+                            height: HEIGHT as i32,
+                        },
+                        anchor: PopupAnchor::Bottom,
+                        gravity: Gravity::Bottom,
+                        reactive: true,
+                        ..Default::default()
+                    },
+                    parent_size: None,
+                    grab: true,
+                });
+                match previous {
+                    Some((previous_id, _)) => Command::batch([destroy_popup(previous_id), open]),
+                    None => open,
+                }
+            }
+        }
+    }
 }
 
 impl Application for MyWidgets {
@@ -41,6 +168,8 @@ impl Application for MyWidgets {
     type Flags = ();
 
     fn new(_flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        let config = Config::load();
+
         let workspace_display = match WorkspaceDisplay::create_from_commands() {
             Err(e) => {
                 error!("Error communicating with Hyprland : {}", e);
@@ -52,8 +181,21 @@ impl Application for MyWidgets {
         (
             Self {
                 workspace_display,
-                clock: Clock::default(),
-                battery_display: BatteryDisplay::new(),
+                clock: Clock::new(&config.clock),
+                battery_display: BatteryDisplay::new(POWER_SUPPLY_ROOT.into(), &config.battery),
+                power_profile: PowerProfileDisplay::new(),
+                audio: None,
+                volume: VolumeDisplay::new(),
+                microphone: MicrophoneDisplay::new(),
+                network: NetworkDisplay::new(),
+                throughput: ThroughputDisplay::new(PROC_ROOT.into(), &config.throughput),
+                cpu: CpuDisplay::new(PROC_ROOT.into()),
+                memory: MemoryDisplay::new(PROC_ROOT.into(), &config.memory),
+                temperature: TemperatureDisplay::new(SYSFS_CLASS_ROOT.into(), &config.temperature),
+                backlight: BacklightDisplay::new(BACKLIGHT_ROOT.into()),
+                disk: DiskDisplay::new(&config.disk),
+                mpris: MprisDisplay::new(),
+                popup: None,
             },
             Command::none(),
         )
@@ -71,20 +213,140 @@ impl Application for MyWidgets {
                     display.update(msg);
                 }
             }
+            ApplicationMessage::Clock(ClockMessage::ToggleAgenda) => {
+                return self.toggle_popup(PopupContent::Agenda);
+            }
             ApplicationMessage::Clock(msg) => {
                 self.clock.update(msg);
             }
-            ApplicationMessage::Battery(BatteryMessage::Error) => self.battery_display = None,
+            ApplicationMessage::Battery(BatteryMessage::TogglePopup)
+                if self.battery_display.is_some() =>
+            {
+                return self.toggle_popup(PopupContent::Battery);
+            }
             ApplicationMessage::Battery(msg) => {
                 if let Some(display) = self.battery_display.as_mut() {
-                    display.update(msg);
+                    return display.update(msg).map(ApplicationMessage::Battery);
+                }
+            }
+            ApplicationMessage::PowerProfile(msg) => {
+                return self
+                    .power_profile
+                    .update(msg)
+                    .map(ApplicationMessage::PowerProfile);
+            }
+            ApplicationMessage::Audio(AudioEvent::State(audio)) => {
+                self.audio = Some(audio);
+            }
+            ApplicationMessage::Audio(AudioEvent::Error) => {
+                self.audio = None;
+            }
+            ApplicationMessage::Volume(VolumeMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Audio);
+            }
+            ApplicationMessage::Volume(msg) => {
+                return self
+                    .volume
+                    .update(self.audio.as_mut(), msg)
+                    .map(ApplicationMessage::Volume);
+            }
+            ApplicationMessage::Microphone(msg) => {
+                return self
+                    .microphone
+                    .update(self.audio.as_ref(), msg)
+                    .map(ApplicationMessage::Microphone);
+            }
+            ApplicationMessage::Network(NetworkMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Network);
+            }
+            ApplicationMessage::Network(msg) => {
+                self.network.update(msg);
+            }
+            ApplicationMessage::Throughput(msg) => {
+                self.throughput.update(msg);
+            }
+            ApplicationMessage::Cpu(CpuMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Cpu);
+            }
+            ApplicationMessage::Cpu(msg) => {
+                self.cpu.update(msg);
+            }
+            ApplicationMessage::Memory(MemoryMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Memory);
+            }
+            ApplicationMessage::Memory(msg) => {
+                self.memory.update(msg);
+            }
+            ApplicationMessage::Temperature(TemperatureMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Temperature);
+            }
+            ApplicationMessage::Temperature(msg) => {
+                self.temperature.update(msg);
+            }
+            ApplicationMessage::Backlight(msg) => {
+                return self
+                    .backlight
+                    .update(msg)
+                    .map(ApplicationMessage::Backlight);
+            }
+            ApplicationMessage::Disk(DiskMessage::TogglePopup) => {
+                return self.toggle_popup(PopupContent::Disk);
+            }
+            ApplicationMessage::Disk(msg) => {
+                self.disk.update(msg);
+            }
+            ApplicationMessage::Mpris(msg) => {
+                return self.mpris.update(msg).map(ApplicationMessage::Mpris);
+            }
+            ApplicationMessage::PopupClosed(id) => {
+                if self.popup.is_some_and(|(popup_id, _)| popup_id == id) {
+                    self.popup = None;
                 }
             }
         };
         Command::none()
     }
 
-    fn view(&self, _id: iced::window::Id) -> Element<Self::Message> {
+    fn view(&self, id: iced::window::Id) -> Element<'_, Self::Message> {
+        match self.popup {
+            Some((popup_id, PopupContent::Agenda)) if popup_id == id => {
+                return self.clock.view_agenda().map(ApplicationMessage::Clock)
+            }
+            Some((popup_id, PopupContent::Battery)) if popup_id == id => {
+                return match &self.battery_display {
+                    Some(battery_display) => battery_display
+                        .view_popup()
+                        .map(ApplicationMessage::Battery),
+                    None => style::popup(text("Battery isn't working. Check the logs.")),
+                };
+            }
+            Some((popup_id, PopupContent::Audio)) if popup_id == id => {
+                return self
+                    .volume
+                    .view_popup(self.audio.as_ref())
+                    .map(ApplicationMessage::Volume)
+            }
+            Some((popup_id, PopupContent::Network)) if popup_id == id => {
+                return self.network.view_popup().map(ApplicationMessage::Network)
+            }
+            Some((popup_id, PopupContent::Cpu)) if popup_id == id => {
+                return self.cpu.view_popup().map(ApplicationMessage::Cpu)
+            }
+            Some((popup_id, PopupContent::Memory)) if popup_id == id => {
+                return self.memory.view_popup().map(ApplicationMessage::Memory)
+            }
+            Some((popup_id, PopupContent::Temperature)) if popup_id == id => {
+                return self
+                    .temperature
+                    .view_popup()
+                    .map(ApplicationMessage::Temperature)
+            }
+            Some((popup_id, PopupContent::Disk)) if popup_id == id => {
+                return self.disk.view_popup().map(ApplicationMessage::Disk)
+            }
+            _ => (),
+        }
+
         let workspace = if let Some(workspace_display) = &self.workspace_display {
             workspace_display.view().map(ApplicationMessage::Workspace)
         } else {
@@ -97,6 +359,37 @@ impl Application for MyWidgets {
             text("Battery isn't working. Check the logs.").into()
         };
 
+        let power_profile = self
+            .power_profile
+            .view()
+            .map(ApplicationMessage::PowerProfile);
+
+        let volume = self
+            .volume
+            .view(self.audio.as_ref())
+            .map(ApplicationMessage::Volume);
+
+        let microphone = self
+            .microphone
+            .view(self.audio.as_ref())
+            .map(ApplicationMessage::Microphone);
+
+        let network = self.network.view().map(ApplicationMessage::Network);
+
+        let throughput = self.throughput.view().map(ApplicationMessage::Throughput);
+
+        let cpu = self.cpu.view().map(ApplicationMessage::Cpu);
+
+        let memory = self.memory.view().map(ApplicationMessage::Memory);
+
+        let temperature = self.temperature.view().map(ApplicationMessage::Temperature);
+
+        let backlight = self.backlight.view().map(ApplicationMessage::Backlight);
+
+        let disk = self.disk.view().map(ApplicationMessage::Disk);
+
+        let mpris = self.mpris.view().map(ApplicationMessage::Mpris);
+
         let clock = self.clock.view().map(ApplicationMessage::Clock);
 
         Container::new(row!(
@@ -107,8 +400,22 @@ impl Application for MyWidgets {
                 horizontal_space(Length::Fill)
             ])
             .width(Length::FillPortion(1)),
-            Container::new(row![horizontal_space(Length::Fill), battery])
-                .width(Length::FillPortion(1)),
+            Container::new(row![
+                horizontal_space(Length::Fill),
+                mpris,
+                disk,
+                backlight,
+                temperature,
+                memory,
+                cpu,
+                throughput,
+                network,
+                microphone,
+                volume,
+                power_profile,
+                battery
+            ])
+            .width(Length::FillPortion(1)),
         ))
         .style(iced::theme::Container::Custom(Box::new(MainContainerStyle)))
         .into()
@@ -131,12 +438,74 @@ impl Application for MyWidgets {
             Subscription::none()
         };
 
+        let power_profile_subscription = self
+            .power_profile
+            .subscription()
+            .map(ApplicationMessage::PowerProfile);
+
+        let audio_subscription =
+            widgets::audio::subscription::watch().map(ApplicationMessage::Audio);
+
+        let network_subscription = self.network.subscription().map(ApplicationMessage::Network);
+
+        let throughput_subscription = self
+            .throughput
+            .subscription()
+            .map(ApplicationMessage::Throughput);
+
+        let cpu_popup_open = matches!(self.popup, Some((_, PopupContent::Cpu)));
+        let cpu_subscription = self
+            .cpu
+            .subscription(cpu_popup_open)
+            .map(ApplicationMessage::Cpu);
+
+        let memory_popup_open = matches!(self.popup, Some((_, PopupContent::Memory)));
+        let memory_subscription = self
+            .memory
+            .subscription(memory_popup_open)
+            .map(ApplicationMessage::Memory);
+
+        let temperature_subscription = self
+            .temperature
+            .subscription()
+            .map(ApplicationMessage::Temperature);
+
+        let backlight_subscription = self
+            .backlight
+            .subscription()
+            .map(ApplicationMessage::Backlight);
+
+        let disk_subscription = self.disk.subscription().map(ApplicationMessage::Disk);
+
+        let mpris_subscription = self.mpris.subscription().map(ApplicationMessage::Mpris);
+
         let clock_subscription = self.clock.subscription().map(ApplicationMessage::Clock);
 
+        // the compositor closes the popup when the user clicks outside of it
+        let popup_subscription = iced::event::listen_with(|event, _status| match event {
+            iced::Event::PlatformSpecific(PlatformSpecific::Wayland(WaylandEvent::Popup(
+                PopupEvent::Done,
+                _,
+                id,
+            ))) => Some(ApplicationMessage::PopupClosed(id)),
+            _ => None,
+        });
+
         Subscription::batch([
             workspace_subscription,
             battery_subscription,
+            power_profile_subscription,
+            audio_subscription,
+            network_subscription,
+            throughput_subscription,
+            cpu_subscription,
+            memory_subscription,
+            temperature_subscription,
+            backlight_subscription,
+            disk_subscription,
+            mpris_subscription,
             clock_subscription,
+            popup_subscription,
         ])
     }
 
@@ -0,0 +1,249 @@
+//! Parsing `/proc/meminfo` and the `/proc/[pid]/status` files, whose values are in kB.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum MemoryError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParsingError {
+        path: PathBuf,
+        line: String,
+    },
+    MissingField {
+        path: PathBuf,
+        field: &'static str,
+    },
+}
+
+impl Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::ParsingError { path, line } => {
+                writeln!(f, "Unable to parse a line of {}", path.display())?;
+                write!(f, "Got '{}'", line)
+            }
+            Self::MissingField { path, field } => {
+                write!(f, "{} has no {} field", path.display(), field)
+            }
+        }
+    }
+}
+
+/// The memory and the swap, in kB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    pub total: u64,
+    /// what can be allocated without swapping, which counts most of the caches as free
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+
+    pub fn used_percent(&self) -> u32 {
+        match self.total {
+            0 => 0,
+            total => (self.used() * 100 / total) as u32,
+        }
+    }
+}
+
+/// Parses lines such as `MemTotal:       16310516 kB` into a map from the field names to their
+/// values, without the unit.
+fn parse_fields(path: &Path, raw: &str) -> Result<HashMap<String, u64>, MemoryError> {
+    raw.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parsing_error = || MemoryError::ParsingError {
+                path: path.into(),
+                line: line.into(),
+            };
+            let (name, value) = line.split_once(':').ok_or_else(parsing_error)?;
+            let value = value
+                .split_whitespace()
+                .next()
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or_else(parsing_error)?;
+            Ok((name.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// Parses the content of `/proc/meminfo`. Only `MemTotal` is required: the kernels older than
+/// 3.14 have no `MemAvailable`, which is then estimated from the free memory and the caches, and
+/// the ones without swap support have no `Swap*` fields.
+pub fn parse_meminfo(path: &Path, raw: &str) -> Result<MemInfo, MemoryError> {
+    let fields = parse_fields(path, raw)?;
+    let field = |name: &str| fields.get(name).copied();
+
+    let total = field("MemTotal").ok_or(MemoryError::MissingField {
+        path: path.into(),
+        field: "MemTotal",
+    })?;
+    let available = field("MemAvailable").unwrap_or_else(|| {
+        ["MemFree", "Buffers", "Cached", "SReclaimable"]
+            .iter()
+            .filter_map(|name| field(name))
+            .sum::<u64>()
+            .min(total)
+    });
+    Ok(MemInfo {
+        total,
+        available,
+        swap_total: field("SwapTotal").unwrap_or(0),
+        swap_free: field("SwapFree").unwrap_or(0),
+    })
+}
+
+/// The resident memory of a process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessMemory {
+    pub pid: u32,
+    pub name: String,
+    /// in kB
+    pub rss: u64,
+}
+
+/// Parses the content of `/proc/[pid]/status`. Returns `None` for the kernel threads, which have
+/// no `VmRSS` field.
+pub fn parse_process_status(pid: u32, raw: &str) -> Option<ProcessMemory> {
+    let mut name = None;
+    let mut rss = None;
+    for line in raw.lines() {
+        match line.split_once(':') {
+            Some(("Name", value)) => name = Some(value.trim().to_string()),
+            Some(("VmRSS", value)) => {
+                rss = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|value| value.parse::<u64>().ok())
+            }
+            _ => (),
+        }
+    }
+    Some(ProcessMemory {
+        pid,
+        name: name?,
+        rss: rss?,
+    })
+}
+
+pub fn read_meminfo(root: &Path) -> Result<MemInfo, MemoryError> {
+    let path = root.join("meminfo");
+    let raw = std::fs::read_to_string(&path).map_err(|error| MemoryError::IoError {
+        path: path.clone(),
+        error,
+    })?;
+    parse_meminfo(&path, &raw)
+}
+
+/// Reads the resident memory of all the processes. The processes that exit while they are read
+/// are just left out.
+pub fn read_processes(root: &Path) -> Result<Vec<ProcessMemory>, MemoryError> {
+    let entries = std::fs::read_dir(root).map_err(|error| MemoryError::IoError {
+        path: root.into(),
+        error,
+    })?;
+    Ok(entries
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse::<u32>().ok()?;
+            let raw = std::fs::read_to_string(root.join(pid.to_string()).join("status")).ok()?;
+            parse_process_status(pid, &raw)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn parses_the_memory_and_the_swap() {
+        let meminfo = read_meminfo(&fixture("proc")).unwrap();
+        assert_eq!(
+            meminfo,
+            MemInfo {
+                total: 16310516,
+                available: 9482356,
+                swap_total: 8388604,
+                swap_free: 8126460,
+            }
+        );
+        assert_eq!(meminfo.used_percent(), 41);
+        assert_eq!(meminfo.swap_used(), 262144);
+    }
+
+    #[test]
+    fn estimates_the_available_memory_on_old_kernels() {
+        let meminfo = read_meminfo(&fixture("proc-without-available")).unwrap();
+        assert_eq!(meminfo.total, 4046412);
+        assert_eq!(meminfo.available, 812344 + 104532 + 1402116 + 118320);
+        assert_eq!(meminfo.swap_used(), 0);
+    }
+
+    #[test]
+    fn reads_no_swap_without_the_swap_fields() {
+        let meminfo = read_meminfo(&fixture("proc-without-swap")).unwrap();
+        assert_eq!(meminfo.available, 352916);
+        assert_eq!((meminfo.swap_total, meminfo.swap_free), (0, 0));
+    }
+
+    #[test]
+    fn requires_the_total_memory() {
+        let raw = "MemFree:          187440 kB\nMemAvailable:     352916 kB\n";
+        assert!(matches!(
+            parse_meminfo(Path::new("meminfo"), raw),
+            Err(MemoryError::MissingField {
+                field: "MemTotal",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_meminfo(Path::new("meminfo"), "MemTotal: lots\n"),
+            Err(MemoryError::ParsingError { .. })
+        ));
+    }
+
+    #[test]
+    fn skips_the_kernel_threads() {
+        let raw = std::fs::read_to_string(fixture("proc/2/status")).unwrap();
+        assert_eq!(parse_process_status(2, &raw), None);
+
+        let mut processes = read_processes(&fixture("proc")).unwrap();
+        processes.sort_by_key(|process| process.pid);
+        assert_eq!(
+            processes,
+            [
+                ProcessMemory {
+                    pid: 1,
+                    name: "systemd".into(),
+                    rss: 12804
+                },
+                ProcessMemory {
+                    pid: 4242,
+                    name: "Web Content (1)".into(),
+                    rss: 392448
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! The memory and swap usage, from `/proc/meminfo`, and the processes using the most memory.
+
+pub mod meminfo;
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use iced::{
+    color, subscription,
+    widget::{column, container, mouse_area, row, scrollable, text, vertical_space, Column},
+    Color, Element, Length, Padding, Subscription,
+};
+
+use crate::{
+    format::format_size,
+    style::{self, ContainerStyle},
+};
+use serde::Deserialize;
+
+use meminfo::{MemInfo, MemoryError, ProcessMemory};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How many processes the popup lists.
+const TOP_PROCESSES: usize = 10;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// above this share of the memory in use, the widget turns orange
+    pub warning_percent: u32,
+    /// above this share of the memory in use, the widget turns red
+    pub critical_percent: u32,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            warning_percent: 80,
+            critical_percent: 95,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub meminfo: MemInfo,
+    /// from the biggest, `None` when the processes weren't read
+    pub top: Option<Vec<ProcessMemory>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum MemoryMessage {
+    NewReading(Reading),
+    Error(String),
+    /// open or close the popup with the biggest processes
+    TogglePopup,
+}
+
+/// Reads the memory, and the biggest processes `with_processes`, as it goes through every one.
+fn read(root: &Path, with_processes: bool) -> Result<Reading, MemoryError> {
+    let meminfo = meminfo::read_meminfo(root)?;
+    let top = match with_processes {
+        true => {
+            let mut top = meminfo::read_processes(root)?;
+            top.sort_by_key(|process| std::cmp::Reverse(process.rss));
+            top.truncate(TOP_PROCESSES);
+            Some(top)
+        }
+        false => None,
+    };
+    Ok(Reading { meminfo, top })
+}
+
+/// Reads on a blocking thread, so that going through the processes doesn't hold up the other
+/// subscriptions.
+async fn read_in_background(root: PathBuf, with_processes: bool) -> Result<Reading, String> {
+    match tokio::task::spawn_blocking(move || read(&root, with_processes)).await {
+        Ok(Ok(reading)) => Ok(reading),
+        Ok(Err(e)) => Err(e.to_string()),
+        // the blocking task panicked
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Shows the memory in use, and the swap when some is used, and opens a popup with the biggest
+/// processes when clicked. It turns orange then red as the memory fills up.
+pub struct MemoryDisplay {
+    /// `/proc`, or another directory with the same layout
+    root: PathBuf,
+    config: MemoryConfig,
+    reading: Option<Reading>,
+    error: Option<String>,
+}
+
+impl MemoryDisplay {
+    pub fn new(root: PathBuf, config: &MemoryConfig) -> Self {
+        Self {
+            root,
+            config: config.clone(),
+            reading: None,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: MemoryMessage) {
+        match message {
+            MemoryMessage::NewReading(reading) => {
+                self.reading = Some(reading);
+                self.error = None;
+            }
+            MemoryMessage::Error(e) => {
+                self.reading = None;
+                self.error = Some(e);
+            }
+            MemoryMessage::TogglePopup => (),
+        }
+    }
+
+    /// Reads the memory every `POLL_INTERVAL`. The processes are only read `with_processes`, i.e.
+    /// while the popup is open; the subscription starts over when that changes.
+    pub fn subscription(&self, with_processes: bool) -> Subscription<MemoryMessage> {
+        struct MemoryUpdates;
+
+        subscription::unfold(
+            (std::any::TypeId::of::<MemoryUpdates>(), with_processes),
+            (self.root.clone(), true),
+            move |(root, first)| async move {
+                if !first {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                match read_in_background(root.clone(), with_processes).await {
+                    Ok(reading) => (MemoryMessage::NewReading(reading), (root, false)),
+                    Err(e) => {
+                        log::error!("Unable to read the memory usage : {}", e);
+                        (MemoryMessage::Error(e), (root, false))
+                    }
+                }
+            },
+        )
+    }
+
+    fn background(&self) -> Color {
+        let used_percent = self
+            .reading
+            .as_ref()
+            .map_or(0, |reading| reading.meminfo.used_percent());
+        if used_percent >= self.config.critical_percent {
+            color!(0xcc241d)
+        } else if used_percent >= self.config.warning_percent {
+            color!(0xd65d0e)
+        } else {
+            color!(0x282828)
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, MemoryMessage> {
+        let label = match &self.reading {
+            Some(Reading { meminfo, .. }) => {
+                let memory = format!(
+                    "󰍛 {}/{}",
+                    format_size(meminfo.used() * 1024),
+                    format_size(meminfo.total * 1024)
+                );
+                match meminfo.swap_used() {
+                    0 => memory,
+                    swap => format!("{} 󰾴 {}", memory, format_size(swap * 1024)),
+                }
+            }
+            None => "󰍛 ?".to_string(),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(
+            ContainerStyle {
+                background: self.background(),
+            }
+            .theme(),
+        );
+
+        mouse_area(content)
+            .on_press(MemoryMessage::TogglePopup)
+            .into()
+    }
+
+    /// the content of the popup, with the memory and the swap, and the biggest processes
+    pub fn view_popup(&self) -> Element<'_, MemoryMessage> {
+        let content = match (&self.reading, &self.error) {
+            (Some(Reading { meminfo, top }), _) => {
+                let line = |label: &str, value: String| {
+                    row![
+                        text(label)
+                            .style(iced::Color::WHITE)
+                            .width(Length::Fixed(150.0)),
+                        text(value).style(iced::Color::WHITE)
+                    ]
+                };
+                let summary = column![
+                    line(
+                        "Utilisée",
+                        format!(
+                            "{} ({}%)",
+                            format_size(meminfo.used() * 1024),
+                            meminfo.used_percent()
+                        )
+                    ),
+                    line("Disponible", format_size(meminfo.available * 1024)),
+                    line("Totale", format_size(meminfo.total * 1024)),
+                    line(
+                        "Swap",
+                        match meminfo.swap_total {
+                            0 => "aucun".to_string(),
+                            total => format!(
+                                "{}/{}",
+                                format_size(meminfo.swap_used() * 1024),
+                                format_size(total * 1024)
+                            ),
+                        }
+                    ),
+                ]
+                .spacing(5);
+
+                let processes = top
+                    .iter()
+                    .flatten()
+                    .map(|process| {
+                        row![
+                            text(format!("{} ({})", process.name, process.pid))
+                                .style(iced::Color::WHITE)
+                                .width(Length::Fill),
+                            text(format_size(process.rss * 1024)).style(iced::Color::WHITE)
+                        ]
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+                // the processes are only read while the popup is open
+                let processes = match top {
+                    Some(_) => Column::with_children(processes).spacing(5),
+                    None => column![text("Lecture en cours").style(iced::Color::WHITE)],
+                };
+
+                column![
+                    summary,
+                    text("Processus").style(iced::Color::WHITE),
+                    processes
+                ]
+                .spacing(10)
+            }
+            (None, Some(e)) => column![text(e).style(iced::Color::WHITE)],
+            (None, None) => column![text("Lecture en cours").style(iced::Color::WHITE)],
+        };
+
+        style::popup(scrollable(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn reads_the_processes_only_when_asked() {
+        let reading = read(&fixture("proc"), false).unwrap();
+        assert!(reading.top.is_none());
+        let reading = read(&fixture("proc"), true).unwrap();
+        let pids = reading
+            .top
+            .unwrap()
+            .iter()
+            .map(|process| process.pid)
+            .collect::<Vec<_>>();
+        assert_eq!(pids, [4242, 1]);
+    }
+}
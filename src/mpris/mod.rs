@@ -0,0 +1,237 @@
+//! A "now playing" display for the media players implementing MPRIS.
+
+pub mod player;
+
+use std::time::Duration;
+
+use iced::{
+    color, subscription,
+    widget::{column, container, mouse_area, row, text, vertical_space},
+    Command, Element, Length, Padding, Subscription,
+};
+
+use crate::{scroll_area::ScrollArea, style::ContainerStyle};
+use player::{Action, Mpris, Player, Status};
+use zbus::Connection;
+
+/// Delay before connecting again when the session bus goes away, e.g. while the session ends.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// How far one scroll step seeks, in µs.
+const SEEK_STEP: i64 = 5_000_000;
+/// Longer titles are cut, so that the widget doesn't push the others away.
+const MAX_LABEL_LENGTH: usize = 40;
+
+#[derive(Debug, Clone)]
+pub enum MprisMessage {
+    /// the players, and the connection on which to control them
+    PlayersChanged(Connection, Vec<Player>),
+    Error,
+    Control(Action),
+    /// seek by this many steps
+    Seek(i32),
+    /// show the next player instead of the one picked automatically
+    CyclePlayer,
+    ControlDone(Result<(), String>),
+}
+
+enum Source {
+    Starting,
+    Connected(Mpris),
+    Failed,
+}
+
+/// Shows the artist and the title of the active player, with buttons to control it. Scroll over
+/// the title to seek, and right click it to switch to another player.
+///
+/// When there are several players, the one picked by the user is shown while it exists.
+/// Otherwise, the one that played last keeps being shown while it is playing. Once it stops, the
+/// first one playing is shown instead, and it stays shown when none is.
+#[derive(Default)]
+pub struct MprisDisplay {
+    /// `None` until the session bus has been reached, or after an error
+    connection: Option<Connection>,
+    players: Vec<Player>,
+    /// the bus name of the player picked with a right click
+    pinned: Option<String>,
+    /// the bus name of the player picked automatically last time
+    active: Option<String>,
+}
+
+impl MprisDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn find(&self, bus_name: &Option<String>) -> Option<&Player> {
+        self.players
+            .iter()
+            .find(|player| Some(&player.bus_name) == bus_name.as_ref())
+    }
+
+    /// the player to show, following the policy described on `MprisDisplay`
+    fn selected(&self) -> Option<&Player> {
+        self.find(&self.pinned).or_else(|| self.automatic())
+    }
+
+    /// the last active player if it is still playing, otherwise the first one playing, otherwise
+    /// the last active one even if paused
+    fn automatic(&self) -> Option<&Player> {
+        let playing = |player: &&Player| player.status == Status::Playing;
+        self.find(&self.active)
+            .filter(playing)
+            .or_else(|| self.players.iter().find(playing))
+            .or_else(|| self.find(&self.active))
+            .or_else(|| self.players.first())
+    }
+
+    pub fn update(&mut self, message: MprisMessage) -> Command<MprisMessage> {
+        match message {
+            MprisMessage::PlayersChanged(connection, players) => {
+                self.connection = Some(connection);
+                self.players = players;
+                if self.find(&self.pinned).is_none() {
+                    // the pinned player left
+                    self.pinned = None;
+                }
+                self.active = self.automatic().map(|player| player.bus_name.clone());
+            }
+            MprisMessage::Error => {
+                self.connection = None;
+                self.players.clear();
+            }
+            MprisMessage::Control(action) => return self.control(action),
+            MprisMessage::Seek(steps) => {
+                if self.selected().is_some_and(|player| player.can_seek) {
+                    return self.control(Action::Seek(steps as i64 * SEEK_STEP));
+                }
+            }
+            MprisMessage::CyclePlayer => {
+                let index = self
+                    .selected()
+                    .and_then(|selected| {
+                        self.players
+                            .iter()
+                            .position(|player| player.bus_name == selected.bus_name)
+                    })
+                    .map_or(0, |index| index + 1);
+                self.pinned = self
+                    .players
+                    .get(index % self.players.len().max(1))
+                    .map(|player| player.bus_name.clone());
+            }
+            MprisMessage::ControlDone(Err(e)) => {
+                log::error!("Unable to control the media player : {}", e);
+            }
+            // the player tells us about the changes like for any other
+            MprisMessage::ControlDone(Ok(())) => (),
+        }
+        Command::none()
+    }
+
+    fn control(&self, action: Action) -> Command<MprisMessage> {
+        let (Some(connection), Some(player)) = (&self.connection, self.selected()) else {
+            return Command::none();
+        };
+        Command::perform(
+            player::control(connection.clone(), player.bus_name.clone(), action),
+            |result| MprisMessage::ControlDone(result.map_err(|e| e.to_string())),
+        )
+    }
+
+    pub fn subscription(&self) -> Subscription<MprisMessage> {
+        struct MprisUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<MprisUpdates>(),
+            Source::Starting,
+            |source| async move {
+                let mpris = match source {
+                    Source::Starting => Mpris::connect().await,
+                    Source::Connected(mut mpris) => mpris.changed().await.map(|()| mpris),
+                    Source::Failed => {
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        Mpris::connect().await
+                    }
+                };
+                let reading = match mpris {
+                    Ok(mpris) => mpris.read().await.map(|players| (players, mpris)),
+                    Err(e) => Err(e),
+                };
+                match reading {
+                    Ok((players, mpris)) => (
+                        MprisMessage::PlayersChanged(mpris.connection().clone(), players),
+                        Source::Connected(mpris),
+                    ),
+                    Err(e) => {
+                        log::error!("Error communicating with the media players : {}", e);
+                        (MprisMessage::Error, Source::Failed)
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, MprisMessage> {
+        let Some(player) = self.selected() else {
+            return column![].into();
+        };
+
+        let label = match (&player.title, player.artists.is_empty()) {
+            (Some(title), false) => format!("{} - {}", player.artists.join(", "), title),
+            (Some(title), true) => title.clone(),
+            (None, _) => player.identity.clone(),
+        };
+        let label = match label.chars().count() > MAX_LABEL_LENGTH {
+            true => format!(
+                "{}…",
+                label.chars().take(MAX_LABEL_LENGTH - 1).collect::<String>()
+            ),
+            false => label,
+        };
+
+        let button = |icon: char, action: Option<Action>| {
+            let icon = text(icon).style(match action {
+                Some(_) => iced::Color::WHITE,
+                None => color!(0x808080),
+            });
+            let icon = mouse_area(centered(icon.into()));
+            match action {
+                Some(action) => icon.on_press(MprisMessage::Control(action)),
+                None => icon,
+            }
+        };
+        let play_pause = match player.status {
+            Status::Playing => '󰏤',
+            Status::Paused | Status::Stopped => '󰐊',
+        };
+
+        let title = ScrollArea::new(
+            mouse_area(centered(text(label).into())).on_right_press(MprisMessage::CyclePlayer),
+        )
+        .on_scroll_up(MprisMessage::Seek(1))
+        .on_scroll_down(MprisMessage::Seek(-1));
+
+        container(
+            row![
+                button('󰒮', player.can_go_previous.then_some(Action::Previous)),
+                button(play_pause, Some(Action::PlayPause)),
+                button('󰒭', player.can_go_next.then_some(Action::Next)),
+                title
+            ]
+            .spacing(5),
+        )
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme())
+        .into()
+    }
+}
+
+/// Centers an element vertically in the bar.
+fn centered(content: Element<MprisMessage>) -> Element<MprisMessage> {
+    column![
+        vertical_space(Length::Fill),
+        content,
+        vertical_space(Length::Fill),
+    ]
+    .into()
+}
@@ -0,0 +1,361 @@
+//! The media players implementing MPRIS on the session bus. To try it against a mock player on a
+//! private `dbus-daemon`, point `DBUS_SESSION_BUS_ADDRESS` to that bus.
+
+use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
+
+use iced::futures::{stream, Stream, StreamExt};
+use zbus::{
+    dbus_proxy, fdo::DBusProxy, zvariant::OwnedValue, CacheProperties, Connection, MatchRule,
+    Message, MessageStream, MessageType,
+};
+
+/// the namespace of the bus names of the players, e.g. `org.mpris.MediaPlayer2.firefox.instance42`
+const BUS_NAME_NAMESPACE: &str = "org.mpris.MediaPlayer2";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// Players change several properties at once when the track changes, which we read only once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+#[dbus_proxy(
+    interface = "org.mpris.MediaPlayer2",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait MediaPlayer2 {
+    /// the name of the player, e.g. `Firefox`
+    #[dbus_proxy(property)]
+    fn identity(&self) -> zbus::Result<String>;
+}
+
+#[dbus_proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait MediaPlayer2Player {
+    fn play_pause(&self) -> zbus::Result<()>;
+
+    fn next(&self) -> zbus::Result<()>;
+
+    fn previous(&self) -> zbus::Result<()>;
+
+    /// moves forward or backward by this many µs
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+
+    /// `Playing`, `Paused` or `Stopped`
+    #[dbus_proxy(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    #[dbus_proxy(property)]
+    fn can_go_next(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn can_go_previous(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn can_seek(&self) -> zbus::Result<bool>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub bus_name: String,
+    pub identity: String,
+    pub status: Status,
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+    pub can_seek: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    /// in µs
+    Seek(i64),
+}
+
+type Signals = Pin<Box<dyn Stream<Item = zbus::Result<Arc<Message>>> + Send + Sync>>;
+
+pub struct Mpris {
+    connection: Connection,
+    bus: DBusProxy<'static>,
+    /// the signals of the players, and the ones of the bus about players appearing or leaving
+    signals: Signals,
+}
+
+async fn player_proxy(
+    connection: &Connection,
+    bus_name: String,
+) -> zbus::Result<MediaPlayer2PlayerProxy<'static>> {
+    // the proxy is only used once, there is no point in watching its properties
+    MediaPlayer2PlayerProxy::builder(connection)
+        .destination(bus_name)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+}
+
+impl Mpris {
+    pub async fn connect() -> zbus::Result<Self> {
+        Self::new(Connection::session().await?).await
+    }
+
+    async fn new(connection: Connection) -> zbus::Result<Self> {
+        let players = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .path(OBJECT_PATH)?
+            .build();
+        let players = MessageStream::for_match_rule(players, &connection, None).await?;
+        let owners = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .arg0ns(BUS_NAME_NAMESPACE)?
+            .build();
+        let owners = MessageStream::for_match_rule(owners, &connection, None).await?;
+        let bus = DBusProxy::new(&connection).await?;
+        Ok(Self {
+            connection,
+            bus,
+            signals: Box::pin(stream::select(players, owners)),
+        })
+    }
+
+    /// The connection to the session bus, on which the players are controlled too.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Waits until a player changes, appears or leaves.
+    pub async fn changed(&mut self) -> zbus::Result<()> {
+        match self.signals.next().await {
+            Some(message) => message?,
+            None => return Err(zbus::Error::Failure("the bus connection was closed".into())),
+        };
+        while let Ok(Some(message)) = tokio::time::timeout(DEBOUNCE, self.signals.next()).await {
+            message?;
+        }
+        Ok(())
+    }
+
+    async fn player(&self, bus_name: String) -> zbus::Result<Player> {
+        let root = MediaPlayer2Proxy::builder(&self.connection)
+            .destination(bus_name.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let player = player_proxy(&self.connection, bus_name.clone()).await?;
+        let metadata = player.metadata().await?;
+        Ok(Player {
+            identity: root.identity().await.unwrap_or_else(|_| bus_name.clone()),
+            bus_name,
+            status: match player.playback_status().await?.as_str() {
+                "Playing" => Status::Playing,
+                "Paused" => Status::Paused,
+                _ => Status::Stopped,
+            },
+            title: metadata
+                .get("xesam:title")
+                .and_then(|title| title.downcast_ref::<str>())
+                .map(str::to_string),
+            artists: metadata
+                .get("xesam:artist")
+                .and_then(|artists| artists.downcast_ref::<zbus::zvariant::Array>())
+                .map(|artists| {
+                    artists
+                        .get()
+                        .iter()
+                        .filter_map(|artist| artist.downcast_ref::<str>())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            can_go_next: player.can_go_next().await?,
+            can_go_previous: player.can_go_previous().await?,
+            can_seek: player.can_seek().await?,
+        })
+    }
+
+    /// Reads all the players, sorted by bus name. The ones that leave or misbehave while they
+    /// are read are left out.
+    pub async fn read(&self) -> zbus::Result<Vec<Player>> {
+        let mut players = Vec::new();
+        for name in self.bus.list_names().await? {
+            if !name
+                .as_str()
+                .strip_prefix(BUS_NAME_NAMESPACE)
+                .is_some_and(|rest| rest.starts_with('.'))
+            {
+                continue;
+            }
+            match self.player(name.as_str().to_string()).await {
+                Ok(player) => players.push(player),
+                Err(e) => log::debug!("Skipping the media player {} : {}", name.as_str(), e),
+            }
+        }
+        players.sort_by(|a, b| a.bus_name.cmp(&b.bus_name));
+        Ok(players)
+    }
+}
+
+pub async fn control(connection: Connection, bus_name: String, action: Action) -> zbus::Result<()> {
+    let player = player_proxy(&connection, bus_name).await?;
+    match action {
+        Action::PlayPause => player.play_pause().await,
+        Action::Next => player.next().await,
+        Action::Previous => player.previous().await,
+        Action::Seek(offset) => player.seek(offset).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::{dbus_interface, zvariant::Value, ConnectionBuilder};
+
+    use super::*;
+    use crate::testing::{block_on, PrivateBus};
+
+    struct MockRoot;
+
+    #[dbus_interface(name = "org.mpris.MediaPlayer2")]
+    impl MockRoot {
+        #[dbus_interface(property)]
+        fn identity(&self) -> String {
+            "Mock Player".into()
+        }
+    }
+
+    struct MockPlayer {
+        playing: bool,
+    }
+
+    #[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl MockPlayer {
+        async fn play_pause(
+            &mut self,
+            #[zbus(signal_context)] context: zbus::SignalContext<'_>,
+        ) -> zbus::fdo::Result<()> {
+            self.playing = !self.playing;
+            self.playback_status_changed(&context).await?;
+            Ok(())
+        }
+
+        fn next(&self) {}
+
+        fn previous(&self) {}
+
+        fn seek(&self, _offset: i64) {}
+
+        #[dbus_interface(property)]
+        fn playback_status(&self) -> String {
+            match self.playing {
+                true => "Playing".into(),
+                false => "Paused".into(),
+            }
+        }
+
+        #[dbus_interface(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            HashMap::from([
+                ("xesam:title".into(), Value::from("Teardrop").into()),
+                (
+                    "xesam:artist".into(),
+                    Value::from(vec!["Massive Attack"]).into(),
+                ),
+            ])
+        }
+
+        #[dbus_interface(property)]
+        fn can_go_next(&self) -> bool {
+            true
+        }
+
+        #[dbus_interface(property)]
+        fn can_go_previous(&self) -> bool {
+            false
+        }
+
+        #[dbus_interface(property)]
+        fn can_seek(&self) -> bool {
+            true
+        }
+    }
+
+    async fn start_player(bus: &PrivateBus, name: &str) -> Connection {
+        ConnectionBuilder::address(bus.address())
+            .unwrap()
+            .name(name)
+            .unwrap()
+            .serve_at(OBJECT_PATH, MockRoot)
+            .unwrap()
+            .serve_at(OBJECT_PATH, MockPlayer { playing: false })
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_and_controls_the_players() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        block_on(async {
+            let _player = start_player(&bus, "org.mpris.MediaPlayer2.mock").await;
+            let mut mpris = Mpris::new(bus.connect().await).await.unwrap();
+
+            let players = mpris.read().await.unwrap();
+            assert_eq!(players.len(), 1);
+            assert_eq!(players[0].identity, "Mock Player");
+            assert_eq!(players[0].status, Status::Paused);
+            assert_eq!(players[0].title.as_deref(), Some("Teardrop"));
+            assert_eq!(players[0].artists, ["Massive Attack"]);
+            assert!(players[0].can_go_next && !players[0].can_go_previous);
+
+            // on the connection of the subscription
+            control(
+                mpris.connection().clone(),
+                players[0].bus_name.clone(),
+                Action::PlayPause,
+            )
+            .await
+            .unwrap();
+            mpris.changed().await.unwrap();
+            assert_eq!(mpris.read().await.unwrap()[0].status, Status::Playing);
+
+            // a name which isn't a player
+            let _other = ConnectionBuilder::address(bus.address())
+                .unwrap()
+                .name("org.mpris.MediaPlayer2Extra")
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let changed = tokio::time::timeout(Duration::from_millis(300), mpris.changed()).await;
+            assert!(changed.is_err());
+
+            // another player appears
+            let _second = start_player(&bus, "org.mpris.MediaPlayer2.second").await;
+            mpris.changed().await.unwrap();
+            let players = mpris.read().await.unwrap();
+            let names = players.iter().map(|player| player.bus_name.as_str());
+            assert_eq!(
+                names.collect::<Vec<_>>(),
+                [
+                    "org.mpris.MediaPlayer2.mock",
+                    "org.mpris.MediaPlayer2.second"
+                ]
+            );
+        });
+    }
+}
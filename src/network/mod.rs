@@ -0,0 +1,268 @@
+//! The network connections of NetworkManager, updated from its signals.
+
+mod networkmanager;
+pub mod throughput;
+
+use std::time::Duration;
+
+use iced::{
+    subscription,
+    widget::{column, container, mouse_area, row, scrollable, text, vertical_space, Column},
+    Element, Length, Padding, Subscription,
+};
+
+use crate::style::{self, ContainerStyle};
+
+use networkmanager::NetworkManager;
+
+/// How long to wait before connecting to NetworkManager again after an error, e.g. while it
+/// restarts.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionKind {
+    Wired,
+    Wifi,
+    Vpn,
+    /// bridges, mobile broadband, ...
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessPoint {
+    pub ssid: String,
+    /// in percents
+    pub strength: u8,
+    pub secured: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveConnection {
+    pub name: String,
+    pub kind: ConnectionKind,
+    /// the access point, for Wi-Fi connections
+    pub access_point: Option<AccessPoint>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkState {
+    /// the index in `active` of the connection that has the default route, `None` while
+    /// disconnected
+    pub primary: Option<usize>,
+    pub active: Vec<ActiveConnection>,
+    /// the networks seen during the last scan, from the strongest
+    pub access_points: Vec<AccessPoint>,
+}
+
+impl NetworkState {
+    pub fn primary(&self) -> Option<&ActiveConnection> {
+        self.active.get(self.primary?)
+    }
+
+    /// whether a VPN is up, even when it doesn't have the default route
+    pub fn vpn(&self) -> bool {
+        self.active
+            .iter()
+            .any(|connection| connection.kind == ConnectionKind::Vpn)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum NetworkMessage {
+    NewState(NetworkState),
+    Error,
+    /// open or close the popup with the networks
+    TogglePopup,
+}
+
+enum Source {
+    Starting,
+    Connected(Box<NetworkManager>),
+    Failed,
+}
+
+/// Shows the primary connection, with the SSID and the signal strength for Wi-Fi, and opens a
+/// popup with the active connections and the available networks when clicked.
+#[derive(Default)]
+pub struct NetworkDisplay {
+    /// `None` until NetworkManager has been reached, or after an error
+    state: Option<NetworkState>,
+}
+
+fn wifi_icon(strength: u8) -> char {
+    match strength {
+        0..=20 => '󰤯',
+        21..=40 => '󰤟',
+        41..=60 => '󰤢',
+        61..=80 => '󰤥',
+        _ => '󰤨',
+    }
+}
+
+fn connection_icon(connection: &ActiveConnection) -> char {
+    match (connection.kind, &connection.access_point) {
+        (ConnectionKind::Wired, _) => '󰈀',
+        (ConnectionKind::Wifi, Some(access_point)) => wifi_icon(access_point.strength),
+        (ConnectionKind::Wifi, None) => '󰤨',
+        (ConnectionKind::Vpn, _) => '󰖂',
+        (ConnectionKind::Other, _) => '󰛳',
+    }
+}
+
+impl NetworkDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, message: NetworkMessage) {
+        match message {
+            NetworkMessage::NewState(state) => self.state = Some(state),
+            NetworkMessage::Error => self.state = None,
+            NetworkMessage::TogglePopup => (),
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<NetworkMessage> {
+        struct NetworkManagerUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<NetworkManagerUpdates>(),
+            Source::Starting,
+            |source| async move {
+                let network_manager = match source {
+                    Source::Starting => NetworkManager::connect().await,
+                    Source::Connected(mut network_manager) => {
+                        network_manager.changed().await.map(|()| *network_manager)
+                    }
+                    Source::Failed => {
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        NetworkManager::connect().await
+                    }
+                };
+                let reading = match network_manager {
+                    Ok(mut network_manager) => network_manager
+                        .read()
+                        .await
+                        .map(|state| (state, network_manager)),
+                    Err(e) => Err(e),
+                };
+                match reading {
+                    Ok((state, network_manager)) => (
+                        NetworkMessage::NewState(state),
+                        Source::Connected(Box::new(network_manager)),
+                    ),
+                    Err(e) => {
+                        log::error!("Error communicating with NetworkManager : {}", e);
+                        (NetworkMessage::Error, Source::Failed)
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, NetworkMessage> {
+        let label = match &self.state {
+            None => "󰤫 ?".to_string(),
+            Some(state) => {
+                let primary = match state.primary() {
+                    None => "󰤮".to_string(),
+                    Some(connection) => match &connection.access_point {
+                        Some(access_point) => format!(
+                            "{} {} {}%",
+                            connection_icon(connection),
+                            access_point.ssid,
+                            access_point.strength
+                        ),
+                        None => format!("{} {}", connection_icon(connection), connection.name),
+                    },
+                };
+                // the VPN is shown apart, as it usually isn't the primary connection
+                match state.vpn()
+                    && state
+                        .primary()
+                        .is_some_and(|primary| primary.kind != ConnectionKind::Vpn)
+                {
+                    true => format!("{} 󰖂", primary),
+                    false => primary,
+                }
+            }
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme());
+
+        mouse_area(content)
+            .on_press(NetworkMessage::TogglePopup)
+            .into()
+    }
+
+    /// the content of the popup, with the active connections and the available networks
+    pub fn view_popup(&self) -> Element<'_, NetworkMessage> {
+        let content = match &self.state {
+            None => column![text("NetworkManager ne répond pas").style(iced::Color::WHITE)],
+            Some(state) => {
+                let active = state
+                    .active
+                    .iter()
+                    .map(|connection| {
+                        text(format!(
+                            "{} {}",
+                            connection_icon(connection),
+                            connection.name
+                        ))
+                        .style(iced::Color::WHITE)
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+                let active = if active.is_empty() {
+                    column![text("Aucune connexion").style(iced::Color::WHITE)]
+                } else {
+                    Column::with_children(active).spacing(5)
+                };
+
+                let access_points = state
+                    .access_points
+                    .iter()
+                    .map(|access_point| {
+                        let lock = match access_point.secured {
+                            true => " 󰌾",
+                            false => "",
+                        };
+                        row![
+                            text(format!(
+                                "{} {}{}",
+                                wifi_icon(access_point.strength),
+                                access_point.ssid,
+                                lock
+                            ))
+                            .style(iced::Color::WHITE)
+                            .width(Length::Fill),
+                            text(format!("{}%", access_point.strength)).style(iced::Color::WHITE)
+                        ]
+                        .into()
+                    })
+                    .collect::<Vec<Element<_>>>();
+                let access_points = if access_points.is_empty() {
+                    column![text("Aucun réseau").style(iced::Color::WHITE)]
+                } else {
+                    Column::with_children(access_points).spacing(5)
+                };
+
+                column![
+                    text("Connexions actives").style(iced::Color::WHITE),
+                    active,
+                    text("Réseaux disponibles").style(iced::Color::WHITE),
+                    access_points
+                ]
+                .spacing(10)
+            }
+        };
+
+        style::popup(scrollable(content))
+    }
+}
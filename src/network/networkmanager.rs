@@ -0,0 +1,548 @@
+//! Reading the connections from NetworkManager on the system bus. To try it against a mock
+//! NetworkManager service on a private `dbus-daemon`, point `DBUS_SYSTEM_BUS_ADDRESS` to that
+//! bus.
+
+use std::{sync::Arc, time::Duration};
+
+use iced::futures::{
+    stream::{select, SelectAll},
+    StreamExt,
+};
+use zbus::{
+    dbus_proxy, zvariant::OwnedObjectPath, CacheProperties, Connection, MatchRule, Message,
+    MessageStream, MessageType, ProxyBuilder, ProxyDefault,
+};
+
+use super::{AccessPoint, ActiveConnection, ConnectionKind, NetworkState};
+
+const SERVICE: &str = "org.freedesktop.NetworkManager";
+const PATH: &str = "/org/freedesktop/NetworkManager";
+/// The objects whose properties we show, besides the manager itself. The access points are left
+/// out, as their strength changes all the time; the devices tell when they appear or vanish. Only
+/// the access point of the primary connection is watched, as its strength is shown on the bar.
+const WATCHED_PATHS: [&str; 2] = [
+    "/org/freedesktop/NetworkManager/Devices",
+    "/org/freedesktop/NetworkManager/ActiveConnection",
+];
+/// NetworkManager changes several properties one after the other, e.g. when connecting, which we
+/// read only once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// the value of the `DeviceType` property of the Wi-Fi devices
+const DEVICE_TYPE_WIFI: u32 = 2;
+/// the path NetworkManager gives for the objects that don't exist, e.g. the primary connection
+/// while disconnected
+const NO_OBJECT: &str = "/";
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager"
+)]
+trait NetworkManager {
+    fn get_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    /// the connection that has the default route
+    #[dbus_proxy(property)]
+    fn primary_connection(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[dbus_proxy(property)]
+    fn active_connections(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager.Connection.Active",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Active {
+    #[dbus_proxy(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    /// the type of the settings, e.g. `802-11-wireless` or `vpn`
+    #[dbus_proxy(property, name = "Type")]
+    fn kind(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn vpn(&self) -> zbus::Result<bool>;
+
+    /// the access point, for Wi-Fi connections
+    #[dbus_proxy(property)]
+    fn specific_object(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager.Device",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Device {
+    #[dbus_proxy(property)]
+    fn device_type(&self) -> zbus::Result<u32>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager.Device.Wireless",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait Wireless {
+    /// the access points seen during the last scan
+    #[dbus_proxy(property)]
+    fn access_points(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager.AccessPoint",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait AccessPointInfo {
+    /// raw bytes, which are usually but not always UTF-8
+    #[dbus_proxy(property)]
+    fn ssid(&self) -> zbus::Result<Vec<u8>>;
+
+    /// in percents
+    #[dbus_proxy(property)]
+    fn strength(&self) -> zbus::Result<u8>;
+
+    #[dbus_proxy(property)]
+    fn wpa_flags(&self) -> zbus::Result<u32>;
+
+    #[dbus_proxy(property)]
+    fn rsn_flags(&self) -> zbus::Result<u32>;
+}
+
+pub struct NetworkManager {
+    connection: Connection,
+    proxy: NetworkManagerProxy<'static>,
+    /// the `PropertiesChanged` signals of the manager, the devices and the active connections
+    signals: SelectAll<MessageStream>,
+    /// the access point of the primary connection, and its `PropertiesChanged` signals
+    access_point: Option<(OwnedObjectPath, MessageStream)>,
+}
+
+/// The rule for the `PropertiesChanged` signals of NetworkManager, on the objects under `path`.
+fn properties_changed(path: &str, namespace: bool) -> zbus::Result<MatchRule<'static>> {
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender(SERVICE)?
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?;
+    Ok(match namespace {
+        true => rule.path_namespace(path)?,
+        false => rule.path(path)?,
+    }
+    .build()
+    .into_owned())
+}
+
+impl NetworkManager {
+    pub async fn connect() -> zbus::Result<Self> {
+        Self::new(Connection::system().await?).await
+    }
+
+    async fn new(connection: Connection) -> zbus::Result<Self> {
+        let mut signals = SelectAll::new();
+        signals.push(
+            MessageStream::for_match_rule(properties_changed(PATH, false)?, &connection, None)
+                .await?,
+        );
+        for path in WATCHED_PATHS {
+            signals.push(
+                MessageStream::for_match_rule(properties_changed(path, true)?, &connection, None)
+                    .await?,
+            );
+        }
+        // the properties are read again after each signal anyway
+        let proxy = NetworkManagerProxy::builder(&connection)
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(Self {
+            connection,
+            proxy,
+            signals,
+            access_point: None,
+        })
+    }
+
+    async fn next_signal(&mut self) -> Option<zbus::Result<Arc<Message>>> {
+        match &mut self.access_point {
+            Some((_, access_point)) => select(&mut self.signals, access_point).next().await,
+            None => self.signals.next().await,
+        }
+    }
+
+    /// Waits until NetworkManager says that something changed.
+    pub async fn changed(&mut self) -> zbus::Result<()> {
+        match self.next_signal().await {
+            Some(message) => message?,
+            None => return Err(zbus::Error::Failure("the bus connection was closed".into())),
+        };
+        while let Ok(Some(message)) = tokio::time::timeout(DEBOUNCE, self.next_signal()).await {
+            message?;
+        }
+        Ok(())
+    }
+
+    /// Follows the signals of the access point of the primary connection instead of the previous
+    /// one, if it changed.
+    async fn watch_access_point(&mut self, path: Option<OwnedObjectPath>) -> zbus::Result<()> {
+        if self.access_point.as_ref().map(|(watched, _)| watched) == path.as_ref() {
+            return Ok(());
+        }
+        self.access_point = match path {
+            Some(path) => {
+                let rule = properties_changed(path.as_str(), false)?;
+                let signals = MessageStream::for_match_rule(rule, &self.connection, None).await?;
+                Some((path, signals))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Builds a proxy for an object of NetworkManager, which is only used once, so there is no
+    /// point in watching its properties.
+    async fn object<T>(&self, path: OwnedObjectPath) -> zbus::Result<T>
+    where
+        T: From<zbus::Proxy<'static>> + ProxyDefault,
+    {
+        ProxyBuilder::new(&self.connection)
+            .path(path.into_inner())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+    }
+
+    async fn access_point(&self, path: OwnedObjectPath) -> zbus::Result<AccessPoint> {
+        let access_point: AccessPointInfoProxy = self.object(path).await?;
+        Ok(AccessPoint {
+            ssid: String::from_utf8_lossy(&access_point.ssid().await?).into(),
+            strength: access_point.strength().await?,
+            secured: access_point.wpa_flags().await? != 0 || access_point.rsn_flags().await? != 0,
+        })
+    }
+
+    /// Reads an active connection, and gives the path of its access point along with it.
+    async fn active_connection(
+        &self,
+        path: OwnedObjectPath,
+    ) -> zbus::Result<(ActiveConnection, Option<OwnedObjectPath>)> {
+        let active: ActiveProxy = self.object(path).await?;
+        let kind = match active.kind().await?.as_str() {
+            "802-3-ethernet" => ConnectionKind::Wired,
+            "802-11-wireless" => ConnectionKind::Wifi,
+            "vpn" | "wireguard" => ConnectionKind::Vpn,
+            _ if active.vpn().await? => ConnectionKind::Vpn,
+            _ => ConnectionKind::Other,
+        };
+        let (access_point, access_point_path) = match kind {
+            ConnectionKind::Wifi => {
+                let path = active.specific_object().await?;
+                match path.as_str() {
+                    NO_OBJECT => (None, None),
+                    _ => (Some(self.access_point(path.clone()).await?), Some(path)),
+                }
+            }
+            _ => (None, None),
+        };
+        let connection = ActiveConnection {
+            name: active.id().await?,
+            kind,
+            access_point,
+        };
+        Ok((connection, access_point_path))
+    }
+
+    /// The access points seen by all the Wi-Fi devices, with only the strongest one for each
+    /// network, sorted from the strongest.
+    async fn access_points(&self) -> zbus::Result<Vec<AccessPoint>> {
+        let mut access_points: Vec<AccessPoint> = Vec::new();
+        for path in self.proxy.get_devices().await? {
+            let device: DeviceProxy = self.object(path.clone()).await?;
+            if device.device_type().await? != DEVICE_TYPE_WIFI {
+                continue;
+            }
+            let wireless: WirelessProxy = self.object(path).await?;
+            for path in wireless.access_points().await? {
+                // the access points come and go between two scans, e.g. with `UnknownObject`
+                // when one vanished after being listed
+                let access_point = match self.access_point(path.clone()).await {
+                    Ok(access_point) => access_point,
+                    Err(e) => {
+                        log::debug!("Skipping the access point {} : {}", path.as_str(), e);
+                        continue;
+                    }
+                };
+                // hidden networks
+                if access_point.ssid.is_empty() {
+                    continue;
+                }
+                match access_points
+                    .iter_mut()
+                    .find(|known| known.ssid == access_point.ssid)
+                {
+                    Some(known) if known.strength >= access_point.strength => (),
+                    Some(known) => *known = access_point,
+                    None => access_points.push(access_point),
+                }
+            }
+        }
+        access_points.sort_by_key(|ap| std::cmp::Reverse(ap.strength));
+        Ok(access_points)
+    }
+
+    pub async fn read(&mut self) -> zbus::Result<NetworkState> {
+        let primary_path = self.proxy.primary_connection().await?;
+        let mut primary = None;
+        let mut primary_access_point = None;
+        let mut active = Vec::new();
+        for path in self.proxy.active_connections().await? {
+            let is_primary = path == primary_path;
+            // the connections being torn down, e.g. a VPN, vanish while we read them
+            let (connection, access_point) = match self.active_connection(path.clone()).await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::debug!("Skipping the active connection {} : {}", path.as_str(), e);
+                    continue;
+                }
+            };
+            if is_primary {
+                primary = Some(active.len());
+                primary_access_point = access_point;
+            }
+            active.push(connection);
+        }
+        self.watch_access_point(primary_access_point).await?;
+
+        Ok(NetworkState {
+            primary,
+            active,
+            access_points: self.access_points().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::{dbus_interface, zvariant::ObjectPath, ConnectionBuilder};
+
+    use super::*;
+    use crate::testing::{block_on, PrivateBus};
+
+    const WIFI: &str = "/org/freedesktop/NetworkManager/Devices/1";
+    const ACTIVE: &str = "/org/freedesktop/NetworkManager/ActiveConnection/1";
+    /// an active connection that vanished after being listed
+    const GONE: &str = "/org/freedesktop/NetworkManager/ActiveConnection/2";
+    const ACCESS_POINTS: &str = "/org/freedesktop/NetworkManager/AccessPoint";
+
+    fn path(path: &str) -> OwnedObjectPath {
+        ObjectPath::try_from(path.to_string()).unwrap().into()
+    }
+
+    struct MockManager;
+
+    #[dbus_interface(name = "org.freedesktop.NetworkManager")]
+    impl MockManager {
+        fn get_devices(&self) -> Vec<OwnedObjectPath> {
+            vec![path(WIFI)]
+        }
+
+        #[dbus_interface(property)]
+        fn primary_connection(&self) -> OwnedObjectPath {
+            path(ACTIVE)
+        }
+
+        #[dbus_interface(property)]
+        fn active_connections(&self) -> Vec<OwnedObjectPath> {
+            vec![path(GONE), path(ACTIVE)]
+        }
+    }
+
+    struct MockDevice;
+
+    #[dbus_interface(name = "org.freedesktop.NetworkManager.Device")]
+    impl MockDevice {
+        #[dbus_interface(property)]
+        fn device_type(&self) -> u32 {
+            DEVICE_TYPE_WIFI
+        }
+    }
+
+    struct MockWireless;
+
+    #[dbus_interface(name = "org.freedesktop.NetworkManager.Device.Wireless")]
+    impl MockWireless {
+        #[dbus_interface(property)]
+        fn access_points(&self) -> Vec<OwnedObjectPath> {
+            // the last one vanished since the scan
+            (1..=4)
+                .map(|index| path(&format!("{}/{}", ACCESS_POINTS, index)))
+                .collect()
+        }
+    }
+
+    struct MockActive {
+        id: String,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.NetworkManager.Connection.Active")]
+    impl MockActive {
+        #[dbus_interface(property)]
+        fn id(&self) -> String {
+            self.id.clone()
+        }
+
+        #[dbus_interface(property, name = "Type")]
+        fn kind(&self) -> String {
+            "802-11-wireless".into()
+        }
+
+        #[dbus_interface(property)]
+        fn vpn(&self) -> bool {
+            false
+        }
+
+        #[dbus_interface(property)]
+        fn specific_object(&self) -> OwnedObjectPath {
+            path(&format!("{}/1", ACCESS_POINTS))
+        }
+    }
+
+    struct MockAccessPoint {
+        ssid: &'static str,
+        strength: u8,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.NetworkManager.AccessPoint")]
+    impl MockAccessPoint {
+        #[dbus_interface(property)]
+        fn ssid(&self) -> Vec<u8> {
+            self.ssid.into()
+        }
+
+        #[dbus_interface(property)]
+        fn strength(&self) -> u8 {
+            self.strength
+        }
+
+        #[dbus_interface(property)]
+        fn wpa_flags(&self) -> u32 {
+            0
+        }
+
+        #[dbus_interface(property)]
+        fn rsn_flags(&self) -> u32 {
+            0x188
+        }
+    }
+
+    fn ssids(access_points: &[AccessPoint]) -> Vec<(&str, u8)> {
+        access_points
+            .iter()
+            .map(|access_point| (access_point.ssid.as_str(), access_point.strength))
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_connections_and_wakes_up_only_for_them() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        block_on(async {
+            let access_point = |ssid, strength| MockAccessPoint { ssid, strength };
+            let service = ConnectionBuilder::address(bus.address())
+                .unwrap()
+                .name(SERVICE)
+                .unwrap()
+                .serve_at(PATH, MockManager)
+                .unwrap()
+                .serve_at(WIFI, MockDevice)
+                .unwrap()
+                .serve_at(WIFI, MockWireless)
+                .unwrap()
+                .serve_at(
+                    ACTIVE,
+                    MockActive {
+                        id: "Maison".into(),
+                    },
+                )
+                .unwrap()
+                .serve_at(format!("{}/1", ACCESS_POINTS), access_point("Maison", 70))
+                .unwrap()
+                .serve_at(format!("{}/2", ACCESS_POINTS), access_point("Maison", 40))
+                .unwrap()
+                .serve_at(format!("{}/3", ACCESS_POINTS), access_point("", 90))
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+
+            let mut network_manager = NetworkManager::new(bus.connect().await).await.unwrap();
+            let state = network_manager.read().await.unwrap();
+            assert_eq!(state.active.len(), 1);
+            let primary = state.primary().unwrap();
+            assert_eq!(primary.name, "Maison");
+            assert_eq!(primary.kind, ConnectionKind::Wifi);
+            assert!(primary.access_point.as_ref().unwrap().secured);
+            // only the strongest access point of each network, without the hidden one
+            assert_eq!(ssids(&state.access_points), [("Maison", 70)]);
+
+            // the strength of another access point changes
+            let weakest = service
+                .object_server()
+                .interface::<_, MockAccessPoint>(format!("{}/2", ACCESS_POINTS))
+                .await
+                .unwrap();
+            weakest.get_mut().await.strength = 45;
+            weakest
+                .get()
+                .await
+                .strength_changed(weakest.signal_context())
+                .await
+                .unwrap();
+            let changed =
+                tokio::time::timeout(Duration::from_millis(300), network_manager.changed()).await;
+            assert!(changed.is_err());
+
+            // the strength of the access point of the primary connection changes
+            let primary = service
+                .object_server()
+                .interface::<_, MockAccessPoint>(format!("{}/1", ACCESS_POINTS))
+                .await
+                .unwrap();
+            primary.get_mut().await.strength = 65;
+            primary
+                .get()
+                .await
+                .strength_changed(primary.signal_context())
+                .await
+                .unwrap();
+            network_manager.changed().await.unwrap();
+            let state = network_manager.read().await.unwrap();
+            let strength = state
+                .primary()
+                .unwrap()
+                .access_point
+                .as_ref()
+                .unwrap()
+                .strength;
+            assert_eq!(strength, 65);
+
+            // the connection is renamed
+            let active = service
+                .object_server()
+                .interface::<_, MockActive>(ACTIVE)
+                .await
+                .unwrap();
+            active.get_mut().await.id = "Bureau".into();
+            active
+                .get()
+                .await
+                .id_changed(active.signal_context())
+                .await
+                .unwrap();
+            network_manager.changed().await.unwrap();
+            let state = network_manager.read().await.unwrap();
+            assert_eq!(state.primary().unwrap().name, "Bureau");
+        });
+    }
+}
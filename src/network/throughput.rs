@@ -0,0 +1,446 @@
+//! The upload and download rates of an interface, from the byte counters of `/proc/net/dev`.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use iced::{
+    subscription,
+    widget::{column, container, text, vertical_space},
+    Element, Length, Padding, Subscription,
+};
+
+use crate::style::ContainerStyle;
+use serde::Deserialize;
+
+const NET_DEV: &str = "net/dev";
+const NET_ROUTE: &str = "net/route";
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// the characters of the sparkline, from the lowest to the highest
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ThroughputConfig {
+    /// the interface to watch, e.g. `wlan0`. By default, the one of the default route.
+    pub interface: Option<String>,
+    /// how many samples the sparkline shows, none by default
+    pub sparkline_samples: usize,
+}
+
+#[derive(Debug)]
+pub enum ThroughputError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParsingError {
+        path: PathBuf,
+        line: String,
+    },
+    UnknownInterface(String),
+}
+
+impl Display for ThroughputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::ParsingError { path, line } => {
+                writeln!(f, "Unable to parse a line of {}", path.display())?;
+                write!(f, "Got '{}'", line)
+            }
+            Self::UnknownInterface(name) => write!(f, "No interface named {}", name),
+        }
+    }
+}
+
+/// The byte counters of an interface since it came up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub received: u64,
+    pub transmitted: u64,
+}
+
+/// Parses the content of `/proc/net/dev`, which has two header lines and then one line per
+/// interface, such as
+/// `  eth0: 1234 10 0 0 0 0 0 0 5678 12 0 0 0 0 0 0`, with 8 receive counters then 8 transmit
+/// ones, bytes first.
+pub fn parse_net_dev(path: &Path, raw: &str) -> Result<Vec<(String, Counters)>, ThroughputError> {
+    raw.lines()
+        .skip(2)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parsing_error = || ThroughputError::ParsingError {
+                path: path.into(),
+                line: line.into(),
+            };
+            let (name, counters) = line.split_once(':').ok_or_else(parsing_error)?;
+            let counters = counters
+                .split_whitespace()
+                .map(str::parse::<u64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| parsing_error())?;
+            match (counters.first(), counters.get(8)) {
+                (Some(&received), Some(&transmitted)) => Ok((
+                    name.trim().to_string(),
+                    Counters {
+                        received,
+                        transmitted,
+                    },
+                )),
+                _ => Err(parsing_error()),
+            }
+        })
+        .collect()
+}
+
+/// Parses the content of `/proc/net/route`, and returns the interface of the default route with
+/// the lowest metric, if any. After a header line, there is one line per route, with
+/// tab-separated columns such as `wlan0 00000000 0102A8C0 0003 0 0 600 00000000 0 0 0`, where the
+/// destination, the metric and the mask are the second, seventh and eighth ones.
+pub fn parse_default_route(path: &Path, raw: &str) -> Result<Option<String>, ThroughputError> {
+    let mut default: Option<(u32, String)> = None;
+    for line in raw.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        let columns = line.split_whitespace().collect::<Vec<_>>();
+        let (Some(name), Some(destination), Some(metric), Some(mask)) = (
+            columns.first(),
+            columns.get(1),
+            columns.get(6).and_then(|metric| metric.parse::<u32>().ok()),
+            columns.get(7),
+        ) else {
+            return Err(ThroughputError::ParsingError {
+                path: path.into(),
+                line: line.into(),
+            });
+        };
+        let is_default = *destination == "00000000" && *mask == "00000000";
+        if is_default && default.as_ref().is_none_or(|(lowest, _)| metric < *lowest) {
+            default = Some((metric, name.to_string()));
+        }
+    }
+    Ok(default.map(|(_, name)| name))
+}
+
+fn read(path: &Path) -> Result<String, ThroughputError> {
+    std::fs::read_to_string(path).map_err(|error| ThroughputError::IoError {
+        path: path.into(),
+        error,
+    })
+}
+
+/// The counters of the watched interface at some point.
+#[derive(Debug, Clone)]
+struct Sample {
+    at: Instant,
+    interface: String,
+    counters: Counters,
+}
+
+/// Returns the watched interface and its counters, or `None` when there is no default route.
+fn sample(root: &Path, configured: Option<&str>) -> Result<Option<Sample>, ThroughputError> {
+    let at = Instant::now();
+    let interface = match configured {
+        Some(name) => name.to_string(),
+        None => {
+            let path = root.join(NET_ROUTE);
+            match parse_default_route(&path, &read(&path)?)? {
+                Some(name) => name,
+                None => return Ok(None),
+            }
+        }
+    };
+    let path = root.join(NET_DEV);
+    parse_net_dev(&path, &read(&path)?)?
+        .into_iter()
+        .find(|(name, _)| *name == interface)
+        .map(|(interface, counters)| {
+            Some(Sample {
+                at,
+                interface,
+                counters,
+            })
+        })
+        .ok_or(ThroughputError::UnknownInterface(interface))
+}
+
+/// The download and upload rates between two samples, in bytes per second, or `None` when the
+/// default route moved to another interface, whose counters aren't comparable.
+fn rates(previous: &Sample, current: &Sample) -> Option<(f64, f64)> {
+    if previous.interface != current.interface {
+        return None;
+    }
+    let elapsed = current
+        .at
+        .duration_since(previous.at)
+        .as_secs_f64()
+        .max(f64::EPSILON);
+    // the counters start over when the interface goes down, and wrap around on the drivers that
+    // keep them on 32 bits, which both show up as a drop to skip rather than a huge rate
+    Some((
+        current
+            .counters
+            .received
+            .saturating_sub(previous.counters.received) as f64
+            / elapsed,
+        current
+            .counters
+            .transmitted
+            .saturating_sub(previous.counters.transmitted) as f64
+            / elapsed,
+    ))
+}
+
+/// e.g. `1.2 Mo/s`, with French units as in the rest of the bar
+fn format_rate(bytes_per_second: f64) -> String {
+    const UNITS: [&str; 4] = ["o/s", "Ko/s", "Mo/s", "Go/s"];
+    let mut rate = bytes_per_second;
+    let mut unit = 0;
+    while rate >= 1000.0 && unit < UNITS.len() - 1 {
+        rate /= 1000.0;
+        unit += 1;
+    }
+    match rate < 10.0 && unit > 0 {
+        true => format!("{:.1} {}", rate, UNITS[unit]),
+        false => format!("{:.0} {}", rate, UNITS[unit]),
+    }
+}
+
+fn sparkline(samples: &VecDeque<f64>) -> String {
+    let highest = samples.iter().copied().fold(0.0, f64::max);
+    samples
+        .iter()
+        .map(|sample| match highest > 0.0 {
+            true => SPARKS[((sample / highest) * (SPARKS.len() - 1) as f64).round() as usize],
+            false => SPARKS[0],
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub enum ThroughputMessage {
+    /// the rates since the previous sample, `None` without a default route
+    NewRates(Option<(f64, f64)>),
+    Error,
+}
+
+/// What the subscription keeps between two samples.
+struct Sampling {
+    /// `/proc`, or another directory with the same layout
+    root: PathBuf,
+    interface: Option<String>,
+    previous: Option<Sample>,
+    /// whether the last sample failed, so that the error is logged only once instead of every
+    /// second
+    failing: bool,
+}
+
+/// Shows the download and upload rates of an interface, with a sparkline of the total rate.
+pub struct ThroughputDisplay {
+    root: PathBuf,
+    config: ThroughputConfig,
+    /// in bytes per second
+    rates: Option<(f64, f64)>,
+    /// the total rates of the last samples, the oldest first
+    history: VecDeque<f64>,
+    error: bool,
+}
+
+impl ThroughputDisplay {
+    pub fn new(root: PathBuf, config: &ThroughputConfig) -> Self {
+        Self {
+            root,
+            config: config.clone(),
+            rates: None,
+            history: VecDeque::with_capacity(config.sparkline_samples),
+            error: false,
+        }
+    }
+
+    pub fn update(&mut self, message: ThroughputMessage) {
+        match message {
+            ThroughputMessage::NewRates(rates) => {
+                self.error = false;
+                self.rates = rates;
+            }
+            ThroughputMessage::Error => {
+                self.error = true;
+                self.rates = None;
+            }
+        }
+
+        if let Some((received, transmitted)) = self.rates {
+            if self.config.sparkline_samples > 0 {
+                if self.history.len() == self.config.sparkline_samples {
+                    self.history.pop_front();
+                }
+                self.history.push_back(received + transmitted);
+            }
+        }
+    }
+
+    /// Reads the counters every `SAMPLE_INTERVAL`, and sends the rates since the previous sample.
+    pub fn subscription(&self) -> Subscription<ThroughputMessage> {
+        struct ThroughputUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<ThroughputUpdates>(),
+            Sampling {
+                root: self.root.clone(),
+                interface: self.config.interface.clone(),
+                previous: None,
+                failing: false,
+            },
+            |mut sampling| async move {
+                tokio::time::sleep(SAMPLE_INTERVAL).await;
+                match sample(&sampling.root, sampling.interface.as_deref()) {
+                    Ok(current) => {
+                        let rates = match (&sampling.previous, &current) {
+                            (Some(previous), Some(current)) => rates(previous, current),
+                            _ => None,
+                        };
+                        sampling.previous = current;
+                        sampling.failing = false;
+                        (ThroughputMessage::NewRates(rates), sampling)
+                    }
+                    Err(e) => {
+                        if !sampling.failing {
+                            log::error!("Unable to read the network counters : {}", e);
+                        }
+                        sampling.previous = None;
+                        sampling.failing = true;
+                        (ThroughputMessage::Error, sampling)
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, ThroughputMessage> {
+        let label = match (self.error, self.rates) {
+            (true, _) => "󰛳 ?".to_string(),
+            (false, None) => "󰇚 - 󰕒 -".to_string(),
+            (false, Some((received, transmitted))) => {
+                let mut label =
+                    format!("󰇚 {} 󰕒 {}", format_rate(received), format_rate(transmitted));
+                if !self.history.is_empty() {
+                    label = format!("{} {}", label, sparkline(&self.history));
+                }
+                label
+            }
+        };
+
+        container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme())
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, FakeTree};
+
+    fn counters(received: u64, transmitted: u64) -> Counters {
+        Counters {
+            received,
+            transmitted,
+        }
+    }
+
+    fn sample_of(start: Instant, seconds: u64, interface: &str, counters: Counters) -> Sample {
+        Sample {
+            at: start + Duration::from_secs(seconds),
+            interface: interface.into(),
+            counters,
+        }
+    }
+
+    #[test]
+    fn parses_the_counters() {
+        let path = fixture("proc/net/dev");
+        let interfaces = parse_net_dev(&path, &read(&path).unwrap()).unwrap();
+        let names = interfaces.iter().map(|(name, _)| name.as_str());
+        assert_eq!(
+            names.collect::<Vec<_>>(),
+            ["lo", "enp0s31f6", "wlan0", "docker0"]
+        );
+        assert_eq!(interfaces[1].1, counters(1832749112, 90233871));
+    }
+
+    #[test]
+    fn watches_the_default_route_with_the_lowest_metric() {
+        let sample = sample(&fixture("proc"), None).unwrap().unwrap();
+        assert_eq!(sample.interface, "enp0s31f6");
+        assert_eq!(sample.counters, counters(1832749112, 90233871));
+    }
+
+    #[test]
+    fn watches_the_configured_interface() {
+        let sample = sample(&fixture("proc"), Some("wlan0")).unwrap().unwrap();
+        assert_eq!(sample.counters, counters(4294960000, 301827346));
+    }
+
+    #[test]
+    fn tells_about_a_missing_interface() {
+        assert!(matches!(
+            sample(&fixture("proc"), Some("wg0")),
+            Err(ThroughputError::UnknownInterface(name)) if name == "wg0"
+        ));
+    }
+
+    #[test]
+    fn reads_nothing_without_a_default_route() {
+        let tree = FakeTree::new();
+        tree.write(
+            "net/route",
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             docker0\t000011AC\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0\n",
+        );
+        assert!(sample(tree.path(), None).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_a_truncated_line() {
+        let path = Path::new("net/dev");
+        let raw = "header\nheader\n  eth0: 1234 10 0 0\n";
+        assert!(matches!(
+            parse_net_dev(path, raw),
+            Err(ThroughputError::ParsingError { line, .. }) if line == "  eth0: 1234 10 0 0"
+        ));
+    }
+
+    #[test]
+    fn computes_the_rates_per_second() {
+        let start = Instant::now();
+        let previous = sample_of(start, 0, "wlan0", counters(1_000, 500));
+        let current = sample_of(start, 2, "wlan0", counters(5_000, 1_500));
+        assert_eq!(rates(&previous, &current), Some((2_000.0, 500.0)));
+    }
+
+    #[test]
+    fn skips_counters_that_wrap_or_start_over() {
+        let start = Instant::now();
+        // a 32-bit counter wraps around, and the interface goes down and up again
+        let previous = sample_of(start, 0, "wlan0", counters(4294960000, 301827346));
+        let current = sample_of(start, 1, "wlan0", counters(2_000, 4_096));
+        assert_eq!(rates(&previous, &current), Some((0.0, 0.0)));
+    }
+
+    #[test]
+    fn skips_the_change_of_default_route() {
+        let start = Instant::now();
+        let previous = sample_of(start, 0, "wlan0", counters(1_000, 500));
+        let current = sample_of(start, 1, "enp0s31f6", counters(5_000, 1_500));
+        assert_eq!(rates(&previous, &current), None);
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers for widgets that need to get the user's attention outside of the bar.
+
+use std::process::Command;
+
+/// Sends a desktop notification through `notify-send`. This does not wait for the notification
+/// daemon, so it can be called from `update`.
+pub fn send(summary: &str, body: &str) {
+    spawn(
+        Command::new("notify-send")
+            .arg("--app-name=widgets")
+            .arg(summary)
+            .arg(body),
+        "notify-send",
+    );
+}
+
+/// Runs a command from the config through `sh -c`, without waiting for it to finish.
+pub fn run_command(command: &str) {
+    spawn(Command::new("sh").arg("-c").arg(command), command);
+}
+
+fn spawn(command: &mut Command, description: &str) {
+    // the tests shouldn't pop up notifications on the desktop of whoever runs them
+    if cfg!(test) {
+        return;
+    }
+    match command.spawn() {
+        Ok(mut child) => {
+            // reap the child once it is done, so that it doesn't stay around as a zombie
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => log::error!("Unable to run '{}' : {}", description, e),
+    }
+}
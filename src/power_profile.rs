@@ -0,0 +1,231 @@
+//! The power profile of `power-profiles-daemon`, which trades performance for battery life.
+
+use std::{pin::Pin, time::Duration};
+
+use iced::{
+    futures::{stream, Stream, StreamExt},
+    subscription,
+    widget::{column, mouse_area, text, vertical_space, Container},
+    Command, Element, Length, Padding, Subscription,
+};
+use zbus::{dbus_proxy, fdo::DBusProxy, Connection};
+
+use crate::style::ContainerStyle;
+
+/// How often to look for the daemon again when it isn't running.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// The profiles, in the order in which a click cycles through them.
+const PROFILES: [&str; 3] = ["power-saver", "balanced", "performance"];
+const SERVICE: &str = "net.hadess.PowerProfiles";
+
+#[dbus_proxy(
+    interface = "net.hadess.PowerProfiles",
+    default_service = "net.hadess.PowerProfiles",
+    default_path = "/net/hadess/PowerProfiles"
+)]
+trait PowerProfiles {
+    #[dbus_proxy(property)]
+    fn active_profile(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn set_active_profile(&self, profile: &str) -> zbus::Result<()>;
+
+    /// one dictionary per profile that the hardware supports, with its name in `Profile`
+    #[dbus_proxy(property)]
+    fn profiles(
+        &self,
+    ) -> zbus::Result<Vec<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>>;
+}
+
+/// Shows the active power profile, and switches to the next one when clicked. It is hidden while
+/// `power-profiles-daemon` isn't running.
+#[derive(Default)]
+pub struct PowerProfileDisplay {
+    active: Option<String>,
+    /// the profiles that the hardware supports, in the order of `PROFILES`
+    available: Vec<String>,
+    /// the proxy of the subscription, to switch profiles on the same connection
+    proxy: Option<PowerProfilesProxy<'static>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PowerProfileMessage {
+    /// the active profile, and the available ones
+    Changed(PowerProfilesProxy<'static>, String, Vec<String>),
+    Unavailable,
+    Cycle,
+    ProfileSet(Result<(), String>),
+}
+
+enum Event {
+    ProfileChanged,
+    /// the daemon exited, or another instance took over
+    OwnerChanged {
+        running: bool,
+    },
+}
+
+type Events = Pin<Box<dyn Stream<Item = Event> + Send>>;
+
+enum Source {
+    Connecting,
+    Listening(PowerProfilesProxy<'static>, Events),
+    Unavailable,
+}
+
+async fn connect() -> zbus::Result<(PowerProfilesProxy<'static>, Events)> {
+    let connection = Connection::system().await?;
+    let proxy = PowerProfilesProxy::new(&connection).await?;
+    // the changes of the profile stop without any error when the daemon exits
+    let owners = DBusProxy::new(&connection)
+        .await?
+        .receive_name_owner_changed_with_args(&[(0, SERVICE)])
+        .await?
+        .map(|signal| Event::OwnerChanged {
+            running: signal.args().is_ok_and(|args| args.new_owner().is_some()),
+        });
+    let profiles = proxy
+        .receive_active_profile_changed()
+        .await
+        .map(|_| Event::ProfileChanged);
+    Ok((proxy, Box::pin(stream::select(profiles, owners))))
+}
+
+async fn read(proxy: &PowerProfilesProxy<'static>) -> zbus::Result<(String, Vec<String>)> {
+    let active = proxy.active_profile().await?;
+    let profiles = proxy.profiles().await?;
+    let available = PROFILES
+        .iter()
+        .filter(|name| {
+            profiles.iter().any(|profile| {
+                profile
+                    .get("Profile")
+                    .and_then(|value| value.downcast_ref::<str>())
+                    == Some(**name)
+            })
+        })
+        .map(|name| name.to_string())
+        .collect();
+    Ok((active, available))
+}
+
+async fn set_profile(proxy: PowerProfilesProxy<'static>, profile: String) -> Result<(), String> {
+    proxy
+        .set_active_profile(&profile)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+impl PowerProfileDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, message: PowerProfileMessage) -> Command<PowerProfileMessage> {
+        match message {
+            PowerProfileMessage::Changed(proxy, active, available) => {
+                self.proxy = Some(proxy);
+                self.active = Some(active);
+                self.available = available;
+            }
+            PowerProfileMessage::Unavailable => {
+                self.proxy = None;
+                self.active = None;
+                self.available.clear();
+            }
+            PowerProfileMessage::Cycle => {
+                if let (Some(proxy), Some(next)) = (&self.proxy, self.next_profile()) {
+                    return Command::perform(
+                        set_profile(proxy.clone(), next),
+                        PowerProfileMessage::ProfileSet,
+                    );
+                }
+            }
+            PowerProfileMessage::ProfileSet(Err(e)) => {
+                log::error!("Unable to change the power profile : {}", e);
+            }
+            // the daemon tells us about the new profile like for any other change
+            PowerProfileMessage::ProfileSet(Ok(())) => (),
+        }
+        Command::none()
+    }
+
+    fn next_profile(&self) -> Option<String> {
+        let active = self.active.as_ref()?;
+        let index = self
+            .available
+            .iter()
+            .position(|profile| profile == active)
+            .map_or(0, |index| index + 1);
+        self.available
+            .get(index % self.available.len().max(1))
+            .cloned()
+    }
+
+    pub fn subscription(&self) -> Subscription<PowerProfileMessage> {
+        struct PowerProfileUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<PowerProfileUpdates>(),
+            Source::Connecting,
+            |source| async move {
+                let connected = match source {
+                    Source::Connecting => connect().await,
+                    Source::Listening(proxy, mut events) => match events.next().await {
+                        Some(Event::ProfileChanged | Event::OwnerChanged { running: true }) => {
+                            Ok((proxy, events))
+                        }
+                        Some(Event::OwnerChanged { running: false }) => {
+                            Err(zbus::Error::Failure("the daemon exited".into()))
+                        }
+                        None => Err(zbus::Error::Failure("the bus connection was closed".into())),
+                    },
+                    Source::Unavailable => {
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        connect().await
+                    }
+                };
+                let reading = match connected {
+                    Ok((proxy, events)) => {
+                        read(&proxy).await.map(|reading| (reading, proxy, events))
+                    }
+                    Err(e) => Err(e),
+                };
+                match reading {
+                    Ok(((active, available), proxy, events)) => (
+                        PowerProfileMessage::Changed(proxy.clone(), active, available),
+                        Source::Listening(proxy, events),
+                    ),
+                    Err(e) => {
+                        log::debug!("power-profiles-daemon isn't available : {}", e);
+                        (PowerProfileMessage::Unavailable, Source::Unavailable)
+                    }
+                }
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, PowerProfileMessage> {
+        let Some(active) = &self.active else {
+            return column![].into();
+        };
+        let icon = match active.as_str() {
+            "performance" => '󰓅',
+            "balanced" => '󰾅',
+            "power-saver" => '󰾆',
+            _ => '?',
+        };
+
+        let content = Container::new(column![
+            vertical_space(Length::Fill),
+            text(icon),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle::default().theme());
+
+        mouse_area(content)
+            .on_press(PowerProfileMessage::Cycle)
+            .into()
+    }
+}
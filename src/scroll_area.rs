@@ -0,0 +1,219 @@
+//! A container that turns mouse wheel events over its content into messages. `mouse_area` only
+//! knows about clicks, and several widgets use scrolling to adjust a value.
+
+use iced::{
+    advanced::{
+        layout, mouse, overlay, renderer,
+        widget::{tree, Operation, Tree},
+        Clipboard, Layout, Shell, Widget,
+    },
+    event::{self, Event},
+    Element, Length, Rectangle, Size, Vector,
+};
+
+/// How many pixels of smooth (touchpad) scrolling make up one step.
+const PIXELS_PER_STEP: f32 = 20.0;
+
+pub struct ScrollArea<'a, Message, Theme = iced::Theme, Renderer = iced::Renderer> {
+    content: Element<'a, Message, Theme, Renderer>,
+    on_scroll_up: Option<Message>,
+    on_scroll_down: Option<Message>,
+}
+
+impl<'a, Message, Theme, Renderer> ScrollArea<'a, Message, Theme, Renderer> {
+    pub fn new(content: impl Into<Element<'a, Message, Theme, Renderer>>) -> Self {
+        Self {
+            content: content.into(),
+            on_scroll_up: None,
+            on_scroll_down: None,
+        }
+    }
+
+    /// The message emitted for each step the wheel is scrolled up.
+    pub fn on_scroll_up(mut self, message: Message) -> Self {
+        self.on_scroll_up = Some(message);
+        self
+    }
+
+    /// The message emitted for each step the wheel is scrolled down.
+    pub fn on_scroll_down(mut self, message: Message) -> Self {
+        self.on_scroll_down = Some(message);
+        self
+    }
+}
+
+/// Smooth scrolling sends many small pixel deltas, which we add up until they make a step.
+#[derive(Default)]
+struct State {
+    accumulated_pixels: f32,
+}
+
+impl<'a, Message, Theme, Renderer> Widget<Message, Theme, Renderer>
+    for ScrollArea<'a, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer,
+    Message: Clone,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn size(&self) -> Size<Length> {
+        self.content.as_widget().size()
+    }
+
+    fn layout(
+        &self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        self.content
+            .as_widget()
+            .layout(&mut tree.children[0], renderer, limits)
+    }
+
+    fn operate(
+        &self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn Operation<Message>,
+    ) {
+        self.content
+            .as_widget()
+            .operate(&mut tree.children[0], layout, renderer, operation);
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) -> event::Status {
+        if let event::Status::Captured = self.content.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event.clone(),
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            shell,
+            viewport,
+        ) {
+            return event::Status::Captured;
+        }
+
+        let Event::Mouse(mouse::Event::WheelScrolled { delta }) = event else {
+            return event::Status::Ignored;
+        };
+        if !cursor.is_over(layout.bounds()) {
+            return event::Status::Ignored;
+        }
+
+        let state = tree.state.downcast_mut::<State>();
+        let steps = match delta {
+            mouse::ScrollDelta::Lines { y, .. } => y.round() as i32,
+            mouse::ScrollDelta::Pixels { y, .. } => {
+                state.accumulated_pixels += y;
+                let steps = (state.accumulated_pixels / PIXELS_PER_STEP).trunc();
+                state.accumulated_pixels -= steps * PIXELS_PER_STEP;
+                steps as i32
+            }
+        };
+
+        let message = if steps > 0 {
+            &self.on_scroll_up
+        } else {
+            &self.on_scroll_down
+        };
+        match message {
+            Some(message) if steps != 0 => {
+                for _ in 0..steps.abs() {
+                    shell.publish(message.clone());
+                }
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(
+            &tree.children[0],
+            layout,
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        renderer_style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.content.as_widget().draw(
+            &tree.children[0],
+            renderer,
+            theme,
+            renderer_style,
+            layout,
+            cursor,
+            viewport,
+        );
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        translation: Vector,
+    ) -> Option<overlay::Element<'b, Message, Theme, Renderer>> {
+        self.content
+            .as_widget_mut()
+            .overlay(&mut tree.children[0], layout, renderer, translation)
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<ScrollArea<'a, Message, Theme, Renderer>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a + Clone,
+    Theme: 'a,
+    Renderer: 'a + renderer::Renderer,
+{
+    fn from(area: ScrollArea<'a, Message, Theme, Renderer>) -> Self {
+        Element::new(area)
+    }
+}
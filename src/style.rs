@@ -0,0 +1,45 @@
+//! The look shared by the widgets of the bar and by their popups.
+
+use iced::{color, widget::container, Color, Element, Length};
+
+/// White text on the background of the bar, or on another color for the widgets that need
+/// attention.
+pub struct ContainerStyle {
+    pub background: Color,
+}
+
+impl Default for ContainerStyle {
+    fn default() -> Self {
+        Self {
+            background: color!(0x282828),
+        }
+    }
+}
+
+impl ContainerStyle {
+    pub fn theme(self) -> iced::theme::Container {
+        iced::theme::Container::Custom(Box::new(self))
+    }
+}
+
+impl container::StyleSheet for ContainerStyle {
+    type Style = iced::Theme;
+
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            text_color: Some(iced::Color::WHITE),
+            background: Some(iced::Background::Color(self.background)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Fills the popup surface with `content`, which is usually scrollable.
+pub fn popup<'a, Message: 'a>(content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    container(content)
+        .padding(10)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(ContainerStyle::default().theme())
+        .into()
+}
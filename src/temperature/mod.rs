@@ -0,0 +1,200 @@
+//! The temperatures of the hwmon sensors and of the thermal zones.
+
+pub mod sensors;
+
+use std::{path::PathBuf, time::Duration};
+
+use iced::{
+    color, subscription,
+    widget::{column, container, mouse_area, row, scrollable, text, vertical_space, Column},
+    Element, Length, Padding, Subscription,
+};
+
+use crate::style::{self, ContainerStyle};
+use serde::Deserialize;
+
+use sensors::{Sensor, TemperatureError};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Sensors only appear when a driver is loaded, so there is no hurry to look for them again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TemperatureConfig {
+    /// the label of the sensor to show, as listed in the popup, e.g. `coretemp Package id 0`. By
+    /// default, the hottest one is shown.
+    pub sensor: Option<String>,
+    /// above this temperature, in °C, the widget turns red
+    pub critical_celsius: f32,
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            critical_celsius: 90.0,
+        }
+    }
+}
+
+/// The temperature of a sensor, in °C.
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub label: String,
+    pub celsius: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum TemperatureMessage {
+    NewReadings(Vec<Reading>),
+    Error(String),
+    /// open or close the popup with all the sensors
+    TogglePopup,
+}
+
+/// Reads all the sensors. Some thermal zones fail to read depending on the state of the device
+/// they measure, so the sensors that fail are just left out.
+fn read(sensors: &[Sensor]) -> Vec<Reading> {
+    sensors
+        .iter()
+        .filter_map(|sensor| {
+            let celsius = sensor
+                .read()
+                .map_err(|e| log::debug!("Skipping a temperature sensor : {}", e))
+                .ok()?;
+            Some(Reading {
+                label: sensor.label.clone(),
+                celsius,
+            })
+        })
+        .collect()
+}
+
+/// Shows the temperature of the configured sensor, or the hottest one, and opens a popup with
+/// all of them when clicked. It turns red above the critical temperature.
+pub struct TemperatureDisplay {
+    /// `/sys/class`, or another directory with the same layout
+    root: PathBuf,
+    config: TemperatureConfig,
+    readings: Vec<Reading>,
+    error: Option<String>,
+}
+
+impl TemperatureDisplay {
+    pub fn new(root: PathBuf, config: &TemperatureConfig) -> Self {
+        Self {
+            root,
+            config: config.clone(),
+            readings: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: TemperatureMessage) {
+        match message {
+            TemperatureMessage::NewReadings(readings) => {
+                self.readings = readings;
+                self.error = None;
+            }
+            TemperatureMessage::Error(e) => {
+                self.readings.clear();
+                self.error = Some(e);
+            }
+            TemperatureMessage::TogglePopup => (),
+        }
+    }
+
+    /// Looks for the sensors once, and then reads them every `POLL_INTERVAL`.
+    pub fn subscription(&self) -> Subscription<TemperatureMessage> {
+        struct TemperatureUpdates;
+
+        subscription::unfold(
+            std::any::TypeId::of::<TemperatureUpdates>(),
+            (self.root.clone(), None),
+            |(root, sensors): (PathBuf, Option<Vec<Sensor>>)| async move {
+                let sensors = match sensors {
+                    Some(sensors) => {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                        sensors
+                    }
+                    None => match sensors::discover(&root) {
+                        Ok(sensors) => sensors,
+                        Err(e) => {
+                            log::error!("Unable to find the temperature sensors : {}", e);
+                            tokio::time::sleep(RETRY_INTERVAL).await;
+                            return (TemperatureMessage::Error(e.to_string()), (root, None));
+                        }
+                    },
+                };
+                (
+                    TemperatureMessage::NewReadings(read(&sensors)),
+                    (root, Some(sensors)),
+                )
+            },
+        )
+    }
+
+    /// the reading of the configured sensor, or the hottest one
+    fn shown(&self) -> Result<Option<&Reading>, TemperatureError> {
+        match &self.config.sensor {
+            Some(label) => self
+                .readings
+                .iter()
+                .find(|reading| reading.label == *label)
+                .map(Some)
+                .ok_or_else(|| TemperatureError::UnknownSensor(label.clone())),
+            None => Ok(self
+                .readings
+                .iter()
+                .max_by(|a, b| a.celsius.total_cmp(&b.celsius))),
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, TemperatureMessage> {
+        let shown = match (&self.error, self.shown()) {
+            (None, Ok(shown)) => shown,
+            _ => None,
+        };
+        let (label, background) = match shown {
+            Some(reading) if reading.celsius >= self.config.critical_celsius => {
+                (format!("󰸁 {:.0}°C", reading.celsius), color!(0xcc241d))
+            }
+            Some(reading) => (format!("󰔏 {:.0}°C", reading.celsius), color!(0x282828)),
+            None => ("󰔏 ?".to_string(), color!(0x282828)),
+        };
+
+        let content = container(column![
+            vertical_space(Length::Fill),
+            text(label),
+            vertical_space(Length::Fill),
+        ])
+        .padding(Padding::from([0, 5, 0, 5]))
+        .style(ContainerStyle { background }.theme());
+
+        mouse_area(content)
+            .on_press(TemperatureMessage::TogglePopup)
+            .into()
+    }
+
+    /// the content of the popup, with all the sensors and their labels, to pick one in the
+    /// config
+    pub fn view_popup(&self) -> Element<'_, TemperatureMessage> {
+        let mut content = Column::new().spacing(5);
+        if let Some(e) = &self.error {
+            content = content.push(text(e).style(color!(0xcc241d)));
+        } else if let Err(e) = self.shown() {
+            content = content.push(text(e.to_string()).style(color!(0xcc241d)));
+        }
+        for reading in &self.readings {
+            content = content.push(row![
+                text(&reading.label)
+                    .style(iced::Color::WHITE)
+                    .width(Length::Fill),
+                text(format!("{:.1}°C", reading.celsius)).style(iced::Color::WHITE)
+            ]);
+        }
+
+        style::popup(scrollable(content))
+    }
+}
@@ -0,0 +1,239 @@
+//! Discovery of the temperature sensors the kernel exposes in `/sys/class/hwmon` and
+//! `/sys/class/thermal`. The root directory, `/sys/class`, is always a parameter, so that a fake
+//! tree can be used instead.
+
+use std::{
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+pub const SYSFS_CLASS_ROOT: &str = "/sys/class";
+
+#[derive(Debug)]
+pub enum TemperatureError {
+    IoError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ParsingError {
+        path: PathBuf,
+        raw: String,
+    },
+    NoSensor {
+        root: PathBuf,
+    },
+    UnknownSensor(String),
+}
+
+impl Display for TemperatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError { path, error } => {
+                write!(f, "Unable to read {} : {}", path.display(), error)
+            }
+            Self::ParsingError { path, raw } => {
+                writeln!(f, "Unable to parse the content of {}", path.display())?;
+                write!(f, "Got '{}'", raw)
+            }
+            Self::NoSensor { root } => write!(f, "No temperature sensor in {}", root.display()),
+            Self::UnknownSensor(label) => write!(f, "No temperature sensor labeled {}", label),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    /// e.g. `coretemp Package id 0` for hwmon, from the name of the chip and the label of the
+    /// input, or `thermal x86_pkg_temp` for the thermal zones, from their type
+    pub label: String,
+    /// the file with the temperature, in m°C
+    pub input: PathBuf,
+}
+
+impl Sensor {
+    /// in °C
+    pub fn read(&self) -> Result<f32, TemperatureError> {
+        let raw = read_first_line(&self.input)?;
+        raw.trim()
+            .parse::<i64>()
+            .map(|millidegrees| millidegrees as f32 / 1000.0)
+            .map_err(|_| TemperatureError::ParsingError {
+                path: self.input.clone(),
+                raw,
+            })
+    }
+}
+
+fn read_first_line(path: &Path) -> Result<String, TemperatureError> {
+    let content = read_to_string(path).map_err(|error| TemperatureError::IoError {
+        path: path.into(),
+        error,
+    })?;
+    Ok(content.lines().next().unwrap_or_default().into())
+}
+
+/// The first number in the name of an entry, e.g. 10 for `hwmon10` or `temp10_input`.
+fn number(entry: &Path) -> Option<u64> {
+    let name = entry.file_name()?.to_str()?;
+    let digits = name
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    digits.parse().ok()
+}
+
+/// Lists the entries of a directory whose names start with `prefix`, sorted by their number, so
+/// that `hwmon2` comes before `hwmon10`. A missing directory just has no entries, as not every
+/// system has both hwmon and thermal zones.
+fn entries(directory: &Path, prefix: &str) -> Result<Vec<PathBuf>, TemperatureError> {
+    let read_dir = match std::fs::read_dir(directory) {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(TemperatureError::IoError {
+                path: directory.into(),
+                error,
+            })
+        }
+    };
+    let mut entries = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| number(a).cmp(&number(b)).then_with(|| a.cmp(b)));
+    Ok(entries)
+}
+
+/// The `temp*_input` files of a hwmon chip, labeled with `temp*_label` when the driver provides
+/// it, and with the number of the input otherwise.
+fn hwmon_sensors(chip: &Path) -> Result<Vec<Sensor>, TemperatureError> {
+    let name = read_first_line(&chip.join("name")).unwrap_or_else(|_| {
+        chip.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    Ok(entries(chip, "temp")?
+        .into_iter()
+        .filter_map(|input| {
+            let file_name = input.file_name()?.to_str()?.to_string();
+            let number = file_name.strip_prefix("temp")?.strip_suffix("_input")?;
+            let label = read_first_line(&chip.join(format!("temp{}_label", number)))
+                .unwrap_or_else(|_| number.to_string());
+            Some(Sensor {
+                label: format!("{} {}", name, label.trim()),
+                input,
+            })
+        })
+        .collect())
+}
+
+/// Lists the sensors of the hwmon chips, then the ones of the thermal zones, which often
+/// measure the same things with other names.
+pub fn discover(root: &Path) -> Result<Vec<Sensor>, TemperatureError> {
+    let mut sensors = Vec::new();
+    for chip in entries(&root.join("hwmon"), "hwmon")? {
+        sensors.extend(hwmon_sensors(&chip)?);
+    }
+    for zone in entries(&root.join("thermal"), "thermal_zone")? {
+        // some drivers fail to tell the type, which shouldn't hide the other sensors
+        let kind = match read_first_line(&zone.join("type")) {
+            Ok(kind) => kind,
+            Err(e) => {
+                log::debug!("Skipping a thermal zone : {}", e);
+                continue;
+            }
+        };
+        sensors.push(Sensor {
+            label: format!("thermal {}", kind.trim()),
+            input: zone.join("temp"),
+        });
+    }
+
+    match sensors.is_empty() {
+        true => Err(TemperatureError::NoSensor { root: root.into() }),
+        false => Ok(sensors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTree;
+
+    fn labels(sensors: &[Sensor]) -> Vec<&str> {
+        sensors.iter().map(|sensor| sensor.label.as_str()).collect()
+    }
+
+    #[test]
+    fn labels_the_hwmon_inputs() {
+        let tree = FakeTree::new();
+        tree.write("hwmon/hwmon0/name", "coretemp\n")
+            .write("hwmon/hwmon0/temp1_input", "52000\n")
+            .write("hwmon/hwmon0/temp1_label", "Package id 0\n")
+            .write("hwmon/hwmon0/temp2_input", "48000\n")
+            .write("hwmon/hwmon0/temp2_label", "Core 0\n")
+            .write("hwmon/hwmon0/temp2_max", "100000\n");
+        let sensors = discover(tree.path()).unwrap();
+        assert_eq!(
+            labels(&sensors),
+            ["coretemp Package id 0", "coretemp Core 0"]
+        );
+        assert_eq!(sensors[0].read().unwrap(), 52.0);
+    }
+
+    #[test]
+    fn numbers_the_hwmon_inputs_without_a_label() {
+        let tree = FakeTree::new();
+        tree.write("hwmon/hwmon1/name", "nvme\n")
+            .write("hwmon/hwmon1/temp1_input", "38850\n");
+        assert_eq!(labels(&discover(tree.path()).unwrap()), ["nvme 1"]);
+    }
+
+    #[test]
+    fn sorts_the_chips_and_inputs_by_number() {
+        let tree = FakeTree::new();
+        for chip in [10, 2] {
+            tree.write(
+                &format!("hwmon/hwmon{}/name", chip),
+                &format!("chip{}\n", chip),
+            );
+            for input in [10, 2] {
+                tree.write(
+                    &format!("hwmon/hwmon{}/temp{}_input", chip, input),
+                    "40000\n",
+                );
+            }
+        }
+        assert_eq!(
+            labels(&discover(tree.path()).unwrap()),
+            ["chip2 2", "chip2 10", "chip10 2", "chip10 10"]
+        );
+    }
+
+    #[test]
+    fn reads_the_thermal_zones_only() {
+        let tree = FakeTree::new();
+        tree.write("thermal/thermal_zone0/type", "acpitz\n")
+            .write("thermal/thermal_zone0/temp", "27800\n")
+            .write("thermal/thermal_zone1/temp", "45000\n")
+            .write("thermal/thermal_zone2/type", "x86_pkg_temp\n")
+            .write("thermal/thermal_zone2/temp", "45000\n")
+            .mkdir("thermal/cooling_device0");
+        // the type of the second zone can't be read
+        let sensors = discover(tree.path()).unwrap();
+        assert_eq!(labels(&sensors), ["thermal acpitz", "thermal x86_pkg_temp"]);
+        assert_eq!(sensors[0].read().unwrap(), 27.8);
+    }
+
+    #[test]
+    fn finds_nothing_in_an_empty_root() {
+        let tree = FakeTree::new();
+        assert!(matches!(
+            discover(tree.path()),
+            Err(TemperatureError::NoSensor { .. })
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+//! Helpers shared by the tests.
+
+use std::{
+    future::Future,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_TREE: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory standing for a tree like `/sys/class` or `/proc`, removed once dropped.
+pub struct FakeTree {
+    root: PathBuf,
+}
+
+impl FakeTree {
+    pub fn new() -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "widgets-test-{}-{}",
+            std::process::id(),
+            NEXT_TREE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        Self { root }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Writes a file, creating its parent directories.
+    pub fn write(&self, path: &str, content: &str) -> &Self {
+        let path = self.root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        self
+    }
+
+    pub fn mkdir(&self, path: &str) -> &Self {
+        std::fs::create_dir_all(self.root.join(path)).unwrap();
+        self
+    }
+
+    pub fn symlink(&self, target: &str, path: &str) -> &Self {
+        std::os::unix::fs::symlink(self.root.join(target), self.root.join(path)).unwrap();
+        self
+    }
+}
+
+impl Drop for FakeTree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// A directory of `fixtures`, which holds real files checked in for the parsers.
+pub fn fixture(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(path)
+}
+
+/// A `dbus-daemon` of our own, on which the tests run mock services.
+pub struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    /// Starts the bus, or returns `None` when `dbus-daemon` isn't installed, in which case the
+    /// test should be skipped.
+    pub fn start() -> Option<Self> {
+        let mut daemon = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                eprintln!("Skipping the test, unable to start dbus-daemon : {}", e);
+                return None;
+            }
+        };
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Some(Self {
+            daemon,
+            address: address.trim().into(),
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub async fn connect(&self) -> zbus::Connection {
+        zbus::ConnectionBuilder::address(self.address())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Runs a future on a runtime like the one of iced, for the code using tokio's timers.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}